@@ -5,26 +5,31 @@ use nih_plug::nih_error;
 enum AdsrStage {
     Idle,
     Attack,
+    Hold,
     Decay,
     Sustain,
     Release,
 }
 
-/// A multi-channel ADSR (Attack, Decay, Sustain, Release) envelope generator.
+/// An ADSR (Attack, Hold, Decay, Sustain, Release) envelope generator.
 ///
-/// This implementation allows multiple audio channels to share the same envelope state,
-/// with only the first channel advancing the envelope timing on each sample.
+/// Each voice owns its envelope, `next` advances it by one frame and the returned
+/// value is applied to every channel of that frame.
 ///
 /// # ADSR Envelope Stages
 ///
 /// 1. **Attack**: When a note starts, the envelope rises from 0 to 1 over the attack time
-/// 2. **Decay**: The envelope falls from 1 to the sustain level over the decay time  
-/// 3. **Sustain**: The envelope holds at the sustain level until note release
-/// 4. **Release**: When the note ends, the envelope falls from sustain level to 0
+/// 2. **Hold**: The envelope stays at 1 over the hold time, skipped without hold
+/// 3. **Decay**: The envelope falls from 1 to the sustain level over the decay time
+/// 4. **Sustain**: The envelope holds at the sustain level until note release
+/// 5. **Release**: When the note ends, the envelope falls from sustain level to 0
 ///
-/// ## Generate envelope values for stereo audio (2 channels)
-/// let left = adsr.next(0.1, 0.2, 0.7, 0.5);   // First channel advances
-/// let right = adsr.next(0.1, 0.2, 0.7, 0.5); // Second channel gets same value
+/// `reset` stops the envelope right away, back to idle without release.
+///
+/// ## Generate the envelope of a frame
+/// let gain = adsr.next(0.1, 0.2, 0.7, 0.5);
+/// let left = left * gain;
+/// let right = right * gain;
 pub struct Adsr {
     /// Current sample rate
     sample_rate: f32,
@@ -37,6 +42,25 @@ pub struct Adsr {
 
     /// current state
     stage: AdsrStage,
+
+    /// Shape of the attack, decay and release, 1.0 is linear
+    curve: f32,
+
+    /// Time at full level between the attack and the decay, in seconds
+    hold: f32,
+}
+
+/// Shapes the linear progress (0.0-1.0) of a stage, a curve of 1.0 keeps it linear.
+///
+/// Higher curves move faster at the start of the stage and slow down
+/// towards its end, close to an exponential envelope.
+#[inline]
+pub fn curve_progress(progress: f32, curve: f32) -> f32 {
+    if curve == 1. {
+        progress
+    } else {
+        1. - (1. - progress).powf(curve)
+    }
 }
 
 impl Adsr {
@@ -46,9 +70,14 @@ impl Adsr {
             current_value: 0.0,
             stage_progress: 0.0,
             sample_rate,
+            curve: 1.0,
+            hold: 0.0,
         }
     }
 
+    /// Stops the envelope immediately, without release.
+    ///
+    /// The envelope goes back to idle with a value of 0, ready for the next `note_on`.
     pub fn reset(&mut self) {
         self.stage = AdsrStage::Idle;
         self.current_value = 0.;
@@ -63,9 +92,9 @@ impl Adsr {
     /// # Example
     ///
     /// ```rust
-    /// let mut adsr = MultiChannelAdsr::new(44100.0);
+    /// let mut adsr = Adsr::new(44100.0);
     /// adsr.note_on();
-    /// assert!(!adsr.note_finished());
+    /// assert!(!adsr.is_idling());
     /// ```
     pub fn note_on(&mut self) {
         self.stage = AdsrStage::Attack;
@@ -80,7 +109,7 @@ impl Adsr {
     /// # Example
     ///
     /// ```rust
-    /// let mut adsr = MultiChannelAdsr::new(44100.0);
+    /// let mut adsr = Adsr::new(44100.0);
     /// adsr.note_on();
     /// // ... generate some samples ...
     /// adsr.note_off(); // Begin release phase
@@ -100,6 +129,16 @@ impl Adsr {
         self.sample_rate = sample_rate;
     }
 
+    /// Sets the shape of the attack, decay and release stages, see `curve_progress`.
+    pub fn set_curve(&mut self, curve: f32) {
+        self.curve = curve;
+    }
+
+    /// Sets the hold time in seconds, the envelope stays at 1 after the attack for that long.
+    pub fn set_hold(&mut self, hold: f32) {
+        self.hold = hold;
+    }
+
     /// Returns whether the envelope has finished and returned to idle state.
     ///
     /// This is useful for voice management - when `true`, the voice can be
@@ -109,6 +148,12 @@ impl Adsr {
         matches!(self.stage, AdsrStage::Idle)
     }
 
+    /// Returns whether the note was released and the envelope is fading out.
+    #[inline]
+    pub fn is_releasing(&self) -> bool {
+        matches!(self.stage, AdsrStage::Release)
+    }
+
     #[inline]
     pub fn safe_current_value(&self) -> f32 {
         #[cfg(debug_assertions)]
@@ -136,11 +181,11 @@ impl Adsr {
         }
     }
 
-    /// Generates the next envelope value for multi-channel audio.
+    /// Advances the envelope by one frame and returns its value.
     ///
-    /// This is the main method for generating envelope values. For multi-channel audio,
-    /// call this once per channel per sample, with `is_first_channel` set to `true` only
-    /// for the first channel. This ensures all channels share the same envelope timing.
+    /// Call this once per frame, whatever the number of channels, and apply
+    /// the value to all of them. The times are read on every call, so they
+    /// can change while a note plays.
     ///
     /// # Arguments
     ///
@@ -158,6 +203,25 @@ impl Adsr {
                 let attack_samples = attack * self.sample_rate;
                 if self.stage_progress >= attack_samples {
                     self.current_value = 1.0;
+                    self.stage = if self.hold > 0. {
+                        AdsrStage::Hold
+                    } else if decay > 0. {
+                        AdsrStage::Decay
+                    } else {
+                        AdsrStage::Sustain
+                    };
+                    self.stage_progress = 0.0;
+                } else {
+                    self.current_value =
+                        curve_progress(self.stage_progress / attack_samples, self.curve);
+                    self.stage_progress += 1.0;
+                }
+            }
+
+            AdsrStage::Hold => {
+                let hold_samples = self.hold * self.sample_rate;
+                self.current_value = 1.0;
+                if self.stage_progress >= hold_samples {
                     self.stage = if decay > 0. {
                         AdsrStage::Decay
                     } else {
@@ -165,7 +229,6 @@ impl Adsr {
                     };
                     self.stage_progress = 0.0;
                 } else {
-                    self.current_value = self.stage_progress / attack_samples;
                     self.stage_progress += 1.0;
                 }
             }
@@ -177,7 +240,7 @@ impl Adsr {
                     self.stage = AdsrStage::Sustain;
                     self.stage_progress = 0.0;
                 } else {
-                    let progress = self.stage_progress / decay_samples;
+                    let progress = curve_progress(self.stage_progress / decay_samples, self.curve);
                     self.current_value = 1.0 - progress * (1.0 - sustain);
                     self.stage_progress += 1.0;
                 }
@@ -194,7 +257,8 @@ impl Adsr {
                     self.stage = AdsrStage::Idle;
                     self.stage_progress = 0.0;
                 } else {
-                    let progress = self.stage_progress / release_samples;
+                    let progress =
+                        curve_progress(self.stage_progress / release_samples, self.curve);
                     self.current_value = sustain * (1.0 - progress);
                     self.stage_progress += 1.0;
                 }
@@ -232,6 +296,25 @@ mod tests {
         assert!(adsr.is_idling());
     }
 
+    #[test]
+    fn test_reset() {
+        let mut adsr = Adsr::new(44100.0);
+        adsr.note_on();
+        run_adsr_samples(&mut adsr, 1000, 0.01, 0.1, 0.5, 0.1);
+        assert!(matches!(adsr.stage, AdsrStage::Decay));
+
+        adsr.reset();
+        assert!(adsr.is_idling());
+        assert_eq!(adsr.current_value, 0.0);
+        assert_eq!(adsr.stage_progress, 0.0);
+
+        // The next note starts from the beginning of the attack
+        adsr.note_on();
+        assert!(matches!(adsr.stage, AdsrStage::Attack));
+        assert_eq!(adsr.next(0.01, 0.1, 0.5, 0.1), 0.0);
+        assert!(adsr.next(0.01, 0.1, 0.5, 0.1) > 0.0);
+    }
+
     #[test]
     fn test_normal_adsr_cycle() {
         let mut adsr = Adsr::new(44100.0);
@@ -323,6 +406,59 @@ mod tests {
         assert!((adsr.current_value - 0.5).abs() < 0.001);
     }
 
+    #[test]
+    fn test_zero_hold() {
+        let mut adsr = Adsr::new(44100.0);
+        adsr.set_hold(0.0);
+        adsr.note_on();
+
+        // Run through attack
+        let attack_samples = (0.1 * 44100.0) as usize;
+        run_adsr_samples(&mut adsr, attack_samples + 1, 0.1, 0.1, 0.5, 0.1);
+
+        // Should be in decay without hold
+        assert!(matches!(adsr.stage, AdsrStage::Decay));
+        assert_eq!(adsr.current_value, 1.0);
+    }
+
+    #[test]
+    fn test_hold_stage() {
+        let mut adsr = Adsr::new(44100.0);
+        adsr.set_hold(0.05);
+        adsr.note_on();
+
+        // Run through attack
+        let attack_samples = (0.1 * 44100.0) as usize;
+        run_adsr_samples(&mut adsr, attack_samples + 1, 0.1, 0.1, 0.5, 0.1);
+        assert!(matches!(adsr.stage, AdsrStage::Hold));
+
+        // Stays at full level during the hold
+        let hold_samples = (0.05 * 44100.0) as usize;
+        let values = run_adsr_samples(&mut adsr, hold_samples, 0.1, 0.1, 0.5, 0.1);
+        assert!(values.iter().all(|v| *v == 1.0));
+        assert!(matches!(adsr.stage, AdsrStage::Hold));
+
+        // Then decays
+        run_adsr_samples(&mut adsr, 2, 0.1, 0.1, 0.5, 0.1);
+        assert!(matches!(adsr.stage, AdsrStage::Decay));
+        assert!(adsr.next(0.1, 0.1, 0.5, 0.1) < 1.0);
+    }
+
+    #[test]
+    fn test_hold_with_zero_decay() {
+        let mut adsr = Adsr::new(44100.0);
+        adsr.set_hold(0.01);
+        adsr.note_on();
+
+        // Run through attack and hold
+        let samples = ((0.1 + 0.01) * 44100.0) as usize;
+        run_adsr_samples(&mut adsr, samples + 3, 0.1, 0.0, 0.5, 0.1);
+
+        // Should be in sustain with zero decay
+        assert!(matches!(adsr.stage, AdsrStage::Sustain));
+        assert!((adsr.next(0.1, 0.0, 0.5, 0.1) - 0.5).abs() < 0.001);
+    }
+
     #[test]
     fn test_zero_release() {
         let mut adsr = Adsr::new(44100.0);
@@ -551,4 +687,50 @@ mod tests {
         assert!(matches!(adsr.stage, AdsrStage::Idle));
         assert_eq!(adsr.current_value, 0.0);
     }
+
+    #[test]
+    fn test_linear_curve_is_unchanged() {
+        let mut linear = Adsr::new(44100.0);
+        let mut curved = Adsr::new(44100.0);
+        curved.set_curve(1.0);
+        linear.note_on();
+        curved.note_on();
+
+        assert_eq!(
+            run_adsr_samples(&mut linear, 10000, 0.01, 0.05, 0.6, 0.1),
+            run_adsr_samples(&mut curved, 10000, 0.01, 0.05, 0.6, 0.1)
+        );
+    }
+
+    #[test]
+    fn test_curved_release_progression() {
+        let mut adsr = Adsr::new(44100.0);
+        adsr.set_curve(4.0);
+        adsr.note_on();
+        run_adsr_samples(&mut adsr, 2000, 0.01, 0.01, 0.7, 0.1);
+        assert!(matches!(adsr.stage, AdsrStage::Sustain));
+
+        adsr.note_off();
+        let release_samples = (0.1 * 44100.0) as usize;
+        let values = run_adsr_samples(&mut adsr, release_samples + 1, 0.01, 0.01, 0.7, 0.1);
+
+        // Still monotonically decreasing, but faster than linear at the start
+        for i in 1..values.len() {
+            assert!(
+                values[i] <= values[i - 1],
+                "Curved release should be monotonically decreasing"
+            );
+        }
+        assert!(values[release_samples / 4] < 0.7 * 0.75);
+        assert!(adsr.is_idling());
+    }
+
+    #[test]
+    fn test_curve_progress_bounds() {
+        for curve in [0.5, 1.0, 3.0] {
+            assert_eq!(curve_progress(0.0, curve), 0.0);
+            assert_eq!(curve_progress(1.0, curve), 1.0);
+        }
+        assert!(curve_progress(0.5, 3.0) > 0.5);
+    }
 }
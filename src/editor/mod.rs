@@ -4,7 +4,8 @@ mod waveform;
 #[allow(dead_code)]
 mod widgets;
 
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
 use egui::*;
@@ -13,9 +14,12 @@ use nih_plug::{editor::Editor, prelude::AsyncExecutor};
 use nih_plug_egui::{create_egui_editor, EguiState};
 
 use crate::editor::waveform::WavePlot;
-use crate::params::{BlendGroup, HardKickSamplerParams, SamplePlayerParams, MAX_SAMPLES};
+use crate::params::{
+    BlendCurve, BlendGroup, HardKickSamplerParams, SamplePlayerParams, MAX_SAMPLES,
+};
 use crate::pitch_shift::PitchShiftKind;
 use crate::plugin::{HardKickSampler, DEFAULT_BPM};
+use crate::saturation::{Oversampling, SaturationMode};
 use crate::shared_states::SharedStates;
 use crate::tasks::{AudioData, TaskRequests, TaskResults};
 use crate::utils;
@@ -55,8 +59,26 @@ fn handle_file_drop(
     async_executor: &AsyncExecutor<HardKickSampler>,
     current_tab: usize,
 ) {
-    if let Some(Some(path)) = ctx.input(|i| i.raw.dropped_files.first().map(|f| f.path.clone())) {
-        async_executor.execute_background(TaskRequests::LoadFile(current_tab, path));
+    // Some hosts drop a `text/uri-list` instead of a native path
+    let dropped_paths: Vec<PathBuf> = ctx.input(|i| {
+        i.raw
+            .dropped_files
+            .iter()
+            .flat_map(|file| match (&file.path, &file.bytes) {
+                (Some(path), _) => vec![path.clone()],
+                (None, Some(bytes)) => std::str::from_utf8(bytes)
+                    .map(utils::paths_from_uri_list)
+                    .unwrap_or_default(),
+                (None, None) => Vec::new(),
+            })
+            .collect()
+    });
+
+    // Several files fill the slots from the current one
+    for (index, path) in
+        (current_tab..MAX_SAMPLES).zip(utils::audio_files_from_drop(&dropped_paths))
+    {
+        async_executor.execute_background(TaskRequests::LoadFile(index, path));
     }
 }
 
@@ -115,12 +137,17 @@ fn render_sample_info_strip(
     ui: &mut Ui,
     async_executor: &AsyncExecutor<HardKickSampler>,
     params: Arc<HardKickSamplerParams>,
+    states: &SharedStates,
     current_tab: usize,
     setter: &ParamSetter,
 ) {
     let sample_params = &params.samples[current_tab];
+    let can_undo = states.can_undo_load(current_tab);
+    let sample_info = states.get_sample_info(current_tab);
+    let sample_rate_mismatch = states.has_sample_rate_mismatch(current_tab);
     let current_file_path = get_sample_path(sample_params);
     let current_file_name = get_sample_name(sample_params);
+    let recursive = params.recursive_browse.value();
 
     ui.allocate_ui_with_layout(
         Vec2::new(ui.available_width(), 30.0), // Set explicit height too
@@ -139,6 +166,14 @@ fn render_sample_info_strip(
                         if ui.checkbox(&mut value, "Muted").clicked() {
                             setter.set_parameter(&sample_params.muted, value);
                         }
+                        let mut value = sample_params.soloed.value();
+                        if ui.checkbox(&mut value, "S").clicked() {
+                            setter.set_parameter(&sample_params.soloed, value);
+                        }
+
+                        ui.add_space(10.0);
+
+                        widgets::create_checkbox(ui, &params.recursive_browse, setter);
 
                         ui.add_space(10.0);
 
@@ -150,6 +185,21 @@ fn render_sample_info_strip(
                             .color(theme::TEXT_COLOR),
                         );
 
+                        // Length and format of the sample
+                        if let Some(sample_info) = sample_info {
+                            ui.label(RichText::new(sample_info).small().color(theme::TEXT_COLOR));
+                        }
+
+                        // The sample is read faster or slower than the host rate
+                        if sample_rate_mismatch
+                            && ui
+                                .button(RichText::new("⚠ Resample").color(theme::TEXT_COLOR_ACCENT))
+                                .on_hover_text("The sample rate differs from the host")
+                                .clicked()
+                        {
+                            setter.set_parameter(&params.resample_to_host, true);
+                        }
+
                         // Push file controls to the right
                         ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
                             if ui.button("Delete").clicked() {
@@ -158,9 +208,32 @@ fn render_sample_info_strip(
                                 ));
                             }
 
-                            let next_file = current_file_path
-                                .clone()
-                                .and_then(|file| utils::get_next_file_in_directory_wrap(&file));
+                            if ui
+                                .add_enabled(current_file_path.is_some(), Button::new("🔍"))
+                                .clicked()
+                            {
+                                if let Some(file) = current_file_path.clone() {
+                                    std::thread::spawn(move || {
+                                        let path = PathBuf::from(file);
+                                        if let Err(e) = utils::reveal_in_file_manager(&path) {
+                                            nih_plug::nih_error!(
+                                                "Failed to reveal {:?}: {}",
+                                                path,
+                                                e
+                                            );
+                                        }
+                                    });
+                                }
+                            }
+
+                            if ui.add_enabled(can_undo, Button::new("↶")).clicked() {
+                                async_executor
+                                    .execute_background(TaskRequests::UndoLoad(current_tab));
+                            }
+
+                            let next_file = current_file_path.clone().and_then(|file| {
+                                utils::get_next_file_in_directory_wrap(&file, recursive)
+                            });
                             if ui
                                 .add_enabled(next_file.is_some(), Button::new(">"))
                                 .clicked()
@@ -173,9 +246,9 @@ fn render_sample_info_strip(
                                 }
                             }
 
-                            let previous_file = current_file_path
-                                .clone()
-                                .and_then(|file| utils::get_previous_file_in_directory_wrap(&file));
+                            let previous_file = current_file_path.clone().and_then(|file| {
+                                utils::get_previous_file_in_directory_wrap(&file, recursive)
+                            });
                             if ui
                                 .add_enabled(previous_file.is_some(), Button::new("<"))
                                 .clicked()
@@ -204,8 +277,9 @@ fn render_waveform_display(
     shared_data: Option<&AudioData>,
     params: &SamplePlayerParams,
     global_params: Arc<HardKickSamplerParams>,
-    current_position: Arc<AtomicU64>,
+    current_position: Arc<AtomicF32>,
     host_bpm: Arc<AtomicF32>,
+    setter: &ParamSetter,
 ) {
     // Render image if needed
     match shared_data {
@@ -230,6 +304,7 @@ fn render_waveform_display(
             };
             let sample_rate = shared_data.spec.sample_rate as f32;
             let samples_per_beat = (60.0 / bpm) * sample_rate;
+            let (blend_time, blend_transition) = global_params.get_blend_seconds(bpm);
 
             // Data to be displayed
             let wave_plot = WavePlot::new(
@@ -238,9 +313,13 @@ fn render_waveform_display(
                 sample_rate,
                 shared_data.spec.channels as usize,
                 position,
-                params.blend_group.value(),
-                global_params.blend_time.value(),
-                global_params.blend_transition.value(),
+                // The layers aren't drawn, they depend on every blend point
+                match params.get_blend_layer() {
+                    Some(_) => BlendGroup::None,
+                    None => params.blend_group.value(),
+                },
+                blend_time,
+                blend_transition,
                 samples_per_beat,
             );
 
@@ -249,9 +328,16 @@ fn render_waveform_display(
                 // Paint rect
                 let rect = paint_rect(ui, height_per_channel, ui.available_width());
 
-                ui.allocate_new_ui(UiBuilder::new().max_rect(rect), |ui| {
-                    wave_plot.display(ui, channel_index);
-                });
+                let clicked = ui
+                    .allocate_new_ui(UiBuilder::new().max_rect(rect), |ui| {
+                        wave_plot.display(ui, channel_index)
+                    })
+                    .inner;
+
+                // Clicking the waveform moves the start offset there
+                if let Some(x) = clicked {
+                    setter.set_parameter(&params.start_offset, wave_plot.start_offset_at(x));
+                }
             }
         }
         _ => {
@@ -295,17 +381,16 @@ fn render_tabs(ui: &mut egui::Ui, current_tab: usize) -> usize {
     // Current tab
     let mut new_tab = current_tab;
 
-    ui.horizontal(|ui| {
-        ui.columns(MAX_SAMPLES, |ui| {
-            for (tab, sub_ui) in ui.iter_mut().enumerate() {
-                if sub_ui
-                    .selectable_label(current_tab == tab, format!("Sample {}", tab + 1))
-                    .clicked()
-                {
-                    new_tab = tab;
-                }
+    // Wraps on several rows, one column per slot gets too cramped with many slots
+    ui.horizontal_wrapped(|ui| {
+        for tab in 0..MAX_SAMPLES {
+            if ui
+                .selectable_label(current_tab == tab, format!("Sample {}", tab + 1))
+                .clicked()
+            {
+                new_tab = tab;
             }
-        })
+        }
     });
 
     new_tab
@@ -325,6 +410,7 @@ fn render_control_tonal_blend(
                 ui.horizontal(|ui| {
                     ui.label("Semi:");
                     widgets::create_integer_input(ui, &sample_params.semitone_offset, setter);
+                    widgets::create_knob(ui, &sample_params.fine_tune, setter, 0.01);
 
                     // also add the pitch mode
                     let current_pitch = sample_params.pitch_shift_kind.value();
@@ -346,6 +432,10 @@ fn render_control_tonal_blend(
                     }
                 });
 
+                if sample_params.pitch_shift_kind.value() == PitchShiftKind::PSOLA {
+                    widgets::create_toggle_button(ui, &sample_params.preserve_formants, setter);
+                }
+
                 widgets::create_toggle_button(ui, &sample_params.is_tonal, setter);
 
                 widgets::create_combo_box(
@@ -378,6 +468,7 @@ fn render_control_tonal_blend(
                 if ui.radio(current_group == BlendGroup::End, "End").clicked() {
                     setter.set_parameter(&sample_params.blend_group, BlendGroup::End);
                 }
+                widgets::create_integer_input(ui, &sample_params.blend_layer, setter);
             },
         );
         render_panel(
@@ -386,32 +477,50 @@ fn render_control_tonal_blend(
             width * global_width,
             PANEL_HEIGHT,
             |ui| {
+                // The knobs of the synced blend are in beats
+                let (blend_time, blend_transition) = if global_params.blend_sync.value() {
+                    (
+                        &global_params.blend_time_beats,
+                        &global_params.blend_transition_beats,
+                    )
+                } else {
+                    (&global_params.blend_time, &global_params.blend_transition)
+                };
                 ui.horizontal(|ui| {
                     ui.columns(2, |columns| {
-                        widgets::create_knob(
-                            &mut columns[0],
-                            &global_params.blend_time,
-                            setter,
-                            0.1,
-                        );
-                        widgets::create_knob(
-                            &mut columns[1],
-                            &global_params.blend_transition,
-                            setter,
-                            0.1,
-                        );
+                        widgets::create_knob(&mut columns[0], blend_time, setter, 0.1);
+                        widgets::create_knob(&mut columns[1], blend_transition, setter, 0.1);
                     });
                 });
+                ui.horizontal(|ui| {
+                    widgets::create_checkbox(ui, &global_params.blend_sync, setter);
+                    widgets::create_integer_input(ui, &global_params.blend_points, setter);
+                    let current_curve = global_params.blend_curve.value();
+                    for (curve, name) in [
+                        (BlendCurve::Linear, "Linear"),
+                        (BlendCurve::EqualPower, "Power"),
+                        (BlendCurve::Smooth, "Smooth"),
+                    ] {
+                        if ui.radio(current_curve == curve, name).clicked() {
+                            setter.set_parameter(&global_params.blend_curve, curve);
+                        }
+                    }
+                });
             },
         );
         // ui.label(format!("{}", ui.style().spacing.item_spacing.x))
     });
 }
 
-fn render_control_adsr_time_gain(ui: &mut Ui, params: &SamplePlayerParams, setter: &ParamSetter) {
-    let width = ui.available_width() - 2. * 8.;
+fn render_control_adsr_time_gain(
+    ui: &mut Ui,
+    global_params: Arc<HardKickSamplerParams>,
+    params: &SamplePlayerParams,
+    setter: &ParamSetter,
+) {
+    let width = ui.available_width() - 3. * 8.;
     ui.horizontal(|ui| {
-        render_panel(ui, "Adsr", width * 0.6, PANEL_HEIGHT, |ui| {
+        render_panel(ui, "Adsr", width * 0.45, PANEL_HEIGHT, |ui| {
             ui.horizontal(|ui| {
                 ui.columns(4, |columns| {
                     widgets::create_knob(&mut columns[0], &params.attack, setter, 0.1);
@@ -421,15 +530,55 @@ fn render_control_adsr_time_gain(ui: &mut Ui, params: &SamplePlayerParams, sette
                 });
             });
         });
-        render_panel(ui, "Time Control", width * 0.2, PANEL_HEIGHT, |ui| {
+        render_panel(ui, "Time Control", width * 0.15, PANEL_HEIGHT, |ui| {
             ui.horizontal(|ui| {
                 widgets::create_knob(ui, &params.start_offset, setter, 0.1);
+                widgets::create_knob(ui, &params.pitch_env_amount, setter, 0.1);
+                widgets::create_knob(ui, &params.pitch_env_time, setter, 0.1);
+            });
+        });
+        render_panel(ui, "Gain", width * 0.15, PANEL_HEIGHT, |ui| {
+            ui.horizontal(|ui| {
+                widgets::create_knob(ui, &params.gain, setter, 0.025);
+                widgets::create_knob(ui, &params.pan, setter, 0.1);
+                widgets::create_knob(ui, &params.dry_wet, setter, 0.1);
             });
         });
-        render_panel(ui, "Gain", width * 0.2, PANEL_HEIGHT, |ui| {
+        render_panel(ui, "Master", width * 0.25, PANEL_HEIGHT, |ui| {
             ui.horizontal(|ui| {
-                ui.vertical_centered(|ui| {
-                    widgets::create_knob(ui, &params.gain, setter, 0.025);
+                widgets::create_knob(ui, &global_params.gain, setter, 0.025);
+                widgets::create_knob(ui, &global_params.drive, setter, 0.1);
+                ui.vertical(|ui| {
+                    widgets::create_knob(ui, &global_params.ceiling, setter, 0.025);
+                    widgets::create_checkbox(ui, &global_params.limiter_enabled, setter);
+                });
+                ui.vertical(|ui| {
+                    let current_mode = global_params.saturation_mode.value();
+                    for (mode, name) in [
+                        (SaturationMode::Off, "Off"),
+                        (SaturationMode::Tanh, "Tanh"),
+                        (SaturationMode::HardClip, "Clip"),
+                        (SaturationMode::Foldback, "Fold"),
+                    ] {
+                        if ui.radio(current_mode == mode, name).clicked() {
+                            setter.set_parameter(&global_params.saturation_mode, mode);
+                        }
+                    }
+                    let current_oversampling = global_params.oversampling.value();
+                    ui.horizontal(|ui| {
+                        for (oversampling, name) in [
+                            (Oversampling::Off, "1x"),
+                            (Oversampling::X2, "2x"),
+                            (Oversampling::X4, "4x"),
+                        ] {
+                            if ui
+                                .radio(current_oversampling == oversampling, name)
+                                .clicked()
+                            {
+                                setter.set_parameter(&global_params.oversampling, oversampling);
+                            }
+                        }
+                    });
                 });
             });
         });
@@ -485,13 +634,19 @@ pub fn create_editor(
 
                     // Render the first row of controls
                     render_control_tonal_blend(ui, params.clone(), current_sample_params, setter);
-                    render_control_adsr_time_gain(ui, current_sample_params, setter);
+                    render_control_adsr_time_gain(
+                        ui,
+                        params.clone(),
+                        current_sample_params,
+                        setter,
+                    );
 
                     // Sample info strip above waveform
                     render_sample_info_strip(
                         ui,
                         &async_executor,
                         params.clone(),
+                        states,
                         current_tab,
                         setter,
                     );
@@ -504,6 +659,7 @@ pub fn create_editor(
                         params.clone(),
                         current_position,
                         states.host_bpm.clone(),
+                        setter,
                     );
                 });
             });
@@ -3,6 +3,7 @@ use egui::Color32;
 use egui_plot::*;
 
 use crate::params::BlendGroup;
+use crate::utils::DISPLAYED_BEATS;
 
 const N_BEAT_DISPLAYED: f32 = 1.5;
 
@@ -35,7 +36,7 @@ pub struct WavePlot<'a> {
     start_offset: f32,
     sample_rate: f32,
     num_channels: usize,
-    position: u64,
+    position: f32,
     blend_group: BlendGroup,
     blend_time: f32,
     blend_transition: f32,
@@ -59,6 +60,17 @@ impl WavePlot<'_> {
         }
     }
 
+    /// Number of frames of the buffer, not its interleaved length
+    fn num_frames(&self) -> usize {
+        self.buffer.len() / self.num_channels.max(1)
+    }
+
+    /// Returns the x of a frame of the buffer in the plot, the same way `data` places them
+    fn frame_to_x(&self, frame: f32) -> f64 {
+        let skipped_frames = self.num_skip() / self.num_channels.max(1);
+        (self.num_silent() as f32 + frame - skipped_frames as f32) as f64
+    }
+
     pub fn data(&self, channel_index: usize) -> impl Iterator<Item = [f64; 2]> + '_ {
         let step = self.num_channels;
 
@@ -77,7 +89,11 @@ impl WavePlot<'_> {
     }
 
     pub fn position(&self) -> impl Iterator<Item = [f64; 2]> {
-        let fpos = self.num_channels as f64 * self.position as f64 / self.num_channels as f64;
+        // The position is the progress through the beats shown from the start offset,
+        // it is turned back into the frame played to be placed like the data
+        let frame = self.start_offset * self.sample_rate
+            + self.position * self.samples_per_beat * DISPLAYED_BEATS;
+        let fpos = self.frame_to_x(frame);
         vec![[fpos, -1.], [fpos, 1.]].into_iter()
     }
 
@@ -114,13 +130,11 @@ impl WavePlot<'_> {
                     (self.blend_time - self.blend_transition / 2.) * self.sample_rate;
                 let blend_end_sample =
                     (self.blend_time + self.blend_transition / 2.) * self.sample_rate;
-                let audio_end_sample =
-                    self.start_offset.max(0.) * self.sample_rate + self.buffer.len() as f32;
 
                 // Convert sample positions to pixel positions
                 let blend_start_pixel = blend_start_sample as f64;
                 let blend_end_pixel = blend_end_sample as f64;
-                let audio_end_pixel = audio_end_sample as f64;
+                let audio_end_pixel = self.frame_to_x(self.num_frames() as f32);
 
                 // Create plot points: [pixel_position, amplitude]
                 // Start at negative amplitude, fade to positive, then maintain until end
@@ -136,8 +150,14 @@ impl WavePlot<'_> {
         }
     }
 
-    pub fn display(&self, ui: &mut egui::Ui, channel_index: usize) {
-        Plot::new(format!("{}Plot", channel_index))
+    /// Converts an x of the plot (in frames from the start offset) to a start offset in seconds
+    pub fn start_offset_at(&self, x: f64) -> f32 {
+        self.start_offset + x as f32 / self.sample_rate
+    }
+
+    /// Displays the plot of a channel, returns the x of the plot that was clicked, if any
+    pub fn display(&self, ui: &mut egui::Ui, channel_index: usize) -> Option<f64> {
+        let plot = Plot::new(format!("{}Plot", channel_index))
             // .legend(Legend::default())
             .allow_drag(false)
             .allow_scroll(false)
@@ -190,7 +210,15 @@ impl WavePlot<'_> {
                     .color(Color32::LIGHT_BLUE)
                     .width(4.),
                 );
+
+                ui.pointer_coordinate()
             });
+
+        plot.response
+            .clicked()
+            .then_some(plot.inner)
+            .flatten()
+            .map(|point| point.x)
     }
 }
 
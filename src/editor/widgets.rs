@@ -191,10 +191,8 @@ pub fn create_knob(
         ui.available_size(),
         Layout::top_down(egui::Align::Center),
         |ui| {
-            // set name + value
-            ui.centered_and_justified(|ui| {
-                ui.label(param.name());
-            });
+            // Name above the knob, the layout already centers it
+            ui.label(param.name());
 
             let mut value = param.modulated_normalized_value();
 
@@ -226,9 +224,8 @@ pub fn create_knob(
                 }
             }
 
-            ui.centered_and_justified(|ui| {
-                ui.label(param.normalized_value_to_string(value, true));
-            });
+            // Exact value under the knob, formatted by the param with its unit
+            ui.label(param.to_string());
             response
         },
     )
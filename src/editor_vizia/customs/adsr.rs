@@ -1,20 +1,42 @@
 use nih_plug_vizia::vizia::{prelude::*, vg};
 
+use crate::adsr::curve_progress;
+
+/// Number of segments drawn for each curved stage
+const CURVE_SEGMENTS: usize = 24;
+
 #[derive(Lens)]
 pub struct AdsrVizualizer<L: Lens<Target = f32>> {
+    origin: L,
     attack: L,
+    hold: L,
     decay: L,
     sustain: L,
     release: L,
+    curve: L,
 }
 
 impl<L: Lens<Target = f32>> AdsrVizualizer<L> {
-    pub fn new(cx: &mut Context, attack: L, decay: L, sustain: L, release: L) -> Handle<Self> {
+    /// `origin` is the x of the note start, the lengths are fractions of the width
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        cx: &mut Context,
+        origin: L,
+        attack: L,
+        hold: L,
+        decay: L,
+        sustain: L,
+        release: L,
+        curve: L,
+    ) -> Handle<Self> {
         Self {
+            origin,
             attack,
+            hold,
             decay,
             sustain,
             release,
+            curve,
         }
         .build(cx, |_| {})
         .class("adsr-vizualizer")
@@ -23,19 +45,39 @@ impl<L: Lens<Target = f32>> AdsrVizualizer<L> {
 
 impl<L: Lens<Target = f32>> View for AdsrVizualizer<L> {
     fn draw(&self, cx: &mut DrawContext, canvas: &mut Canvas) {
+        let origin = self.origin.get(cx);
         let attack = self.attack.get(cx);
+        let hold = self.hold.get(cx);
         let decay = self.decay.get(cx);
         let sustain = self.sustain.get(cx);
         let release = self.release.get(cx);
+        let curve = self.curve.get(cx);
 
         // First let's do the blend time (bar on the center)
         let BoundingBox { x, y, w, h } = cx.bounds();
 
+        // The envelope may start or end outside of the view when scrolled
+        canvas.save();
+        canvas.scissor(x, y, w, h);
+        let x = x + origin * w;
+
         let mut path = vg::Path::new();
         path.move_to(x, y + h);
-        path.line_to(x + attack * w, y);
-        path.line_to(x + (attack + decay) * w, y + (1. - sustain) * h);
-        path.line_to(x + (attack + decay + release) * w, y + h);
+
+        // Each stage goes from its start level to its end level, along the curve
+        let stages = [
+            (0., attack, 0., 1.),
+            (attack, hold, 1., 1.),
+            (attack + hold, decay, 1., sustain),
+            (attack + hold + decay, release, sustain, 0.),
+        ];
+        for (start, length, from, to) in stages {
+            for segment in 1..=CURVE_SEGMENTS {
+                let progress = segment as f32 / CURVE_SEGMENTS as f32;
+                let level = from + (to - from) * curve_progress(progress, curve);
+                path.line_to(x + (start + length * progress) * w, y + (1. - level) * h);
+            }
+        }
 
         let mut fill_path = path.clone();
         fill_path.close();
@@ -51,5 +93,6 @@ impl<L: Lens<Target = f32>> View for AdsrVizualizer<L> {
             &path,
             &vg::Paint::color(color.into()).with_line_width(cx.scale_factor() * cx.outline_width()),
         );
+        canvas.restore();
     }
 }
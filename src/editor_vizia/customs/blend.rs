@@ -1,23 +1,189 @@
+use std::sync::Arc;
+
+use nih_plug::prelude::{FloatParam, Param};
 use nih_plug_vizia::vizia::{prelude::*, vg};
+use nih_plug_vizia::widgets::RawParamEvent;
+
+use crate::editor_vizia::events::SetDraggingBlend;
+use crate::params::HardKickSamplerParams;
+use crate::utils;
+
+/// Width of the grabbable area around each bar, as a fraction of the view
+const HANDLE_WIDTH: f32 = 0.02;
+
+/// The bar being dragged
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BlendHandle {
+    /// The center bar, moves the blend time
+    Time,
 
-#[derive(Lens)]
+    /// One of the edges, changes the transition
+    Transition,
+}
+
+/// Draws the blend region, its center bar and edges can be dragged
+/// to change the blend time and transition.
+///
+/// The view itself lets the mouse through, only the handles around the bars are hoverable.
 pub struct BlendVizualizer<L: Lens<Target = f32>> {
     blend_time: L,
     blend_transition: L,
+    params: Arc<HardKickSamplerParams>,
+
+    /// The blend point shown, see `HardKickSamplerParams::get_blend_point_seconds`
+    point: usize,
+
+    /// Time at the left of the view and time the view spans, in seconds
+    view_start: f32,
+    view_length: f32,
+
+    /// Converts the dragged times to beats when the blend is synced
+    seconds_per_beat: f32,
+
+    dragging: Option<BlendHandle>,
 }
 
 impl<L: Lens<Target = f32>> BlendVizualizer<L> {
-    pub fn new(cx: &mut Context, blend_time: L, blend_transition: L) -> Handle<Self> {
+    /// `blend_time` and `blend_transition` are fractions of the view width,
+    /// `view` is the time at its left and the time it spans in seconds
+    pub fn new(
+        cx: &mut Context,
+        blend_time: L,
+        blend_transition: L,
+        params: Arc<HardKickSamplerParams>,
+        point: usize,
+        (view_start, view_length): (f32, f32),
+        bpm: f32,
+    ) -> Handle<Self> {
+        let (time, transition) = (blend_time.clone(), blend_transition.clone());
         Self {
             blend_time,
             blend_transition,
+            params,
+            point,
+            view_start,
+            view_length,
+            seconds_per_beat: 60. / utils::effective_bpm(Some(bpm)),
+            dragging: None,
         }
-        .build(cx, |_| {})
+        .build(cx, move |cx| {
+            // Handles are rebuilt when the bars move
+            Binding::new(cx, time, move |cx, time| {
+                let time = time.get(cx);
+                Binding::new(cx, transition.clone(), move |cx, transition| {
+                    let half_transition = transition.get(cx) / 2.;
+                    for x in [time - half_transition, time, time + half_transition] {
+                        Element::new(cx)
+                            .height(Stretch(1.0))
+                            .width(Percentage(HANDLE_WIDTH * 100.))
+                            .left(Percentage((x - HANDLE_WIDTH / 2.) * 100.))
+                            .cursor(CursorIcon::EwResize)
+                            .class("blend-handle");
+                    }
+                });
+            });
+        })
+        .hoverable(false)
         .class("blend-vizualizer")
     }
+
+    /// Returns the param moved by a handle
+    fn param(&self, handle: BlendHandle) -> &FloatParam {
+        let sync = self.params.blend_sync.value();
+        let extra_point = self
+            .point
+            .checked_sub(1)
+            .and_then(|index| self.params.extra_blend_points.get(index));
+
+        match (extra_point, handle, sync) {
+            (None, BlendHandle::Time, false) => &self.params.blend_time,
+            (None, BlendHandle::Transition, false) => &self.params.blend_transition,
+            (None, BlendHandle::Time, true) => &self.params.blend_time_beats,
+            (None, BlendHandle::Transition, true) => &self.params.blend_transition_beats,
+            (Some(point), BlendHandle::Time, false) => &point.time,
+            (Some(point), BlendHandle::Transition, false) => &point.transition,
+            (Some(point), BlendHandle::Time, true) => &point.time_beats,
+            (Some(point), BlendHandle::Transition, true) => &point.transition_beats,
+        }
+    }
+
+    /// Converts a time in seconds to the unit of the params
+    fn to_param_unit(&self, seconds: f32) -> f32 {
+        if self.params.blend_sync.value() {
+            seconds / self.seconds_per_beat
+        } else {
+            seconds
+        }
+    }
+
+    /// Returns the handle closest to `x`, the center one wins ties
+    fn closest_handle(&self, cx: &EventContext, x: f32) -> BlendHandle {
+        let time = self.blend_time.get(cx);
+        let half_transition = self.blend_transition.get(cx) / 2.;
+        if (x - time).abs() <= ((x - time).abs() - half_transition).abs() {
+            BlendHandle::Time
+        } else {
+            BlendHandle::Transition
+        }
+    }
 }
 
 impl<L: Lens<Target = f32>> View for BlendVizualizer<L> {
+    fn element(&self) -> Option<&'static str> {
+        Some("blend-vizualizer")
+    }
+
+    fn event(&mut self, cx: &mut EventContext, event: &mut Event) {
+        event.map(|window_event: &WindowEvent, meta| {
+            // Cursor position as a fraction of the view
+            let bounds = cx.bounds();
+            let x = if bounds.w > 0. {
+                (cx.mouse().cursorx - bounds.x) / bounds.w
+            } else {
+                0.
+            };
+
+            match window_event {
+                WindowEvent::MouseDown(MouseButton::Left) => {
+                    let handle = self.closest_handle(cx, x);
+                    cx.capture();
+                    cx.emit(SetDraggingBlend(true));
+                    cx.emit(RawParamEvent::BeginSetParameter(
+                        self.param(handle).as_ptr(),
+                    ));
+                    self.dragging = Some(handle);
+                    meta.consume();
+                }
+                WindowEvent::MouseMove(_, _) => {
+                    let Some(handle) = self.dragging else {
+                        return;
+                    };
+                    let value = match handle {
+                        BlendHandle::Time => self.view_start + x * self.view_length,
+                        BlendHandle::Transition => {
+                            2. * (x - self.blend_time.get(cx)).abs() * self.view_length
+                        }
+                    };
+                    let param = self.param(handle);
+                    cx.emit(RawParamEvent::SetParameterNormalized(
+                        param.as_ptr(),
+                        param.preview_normalized(self.to_param_unit(value)),
+                    ));
+                    meta.consume();
+                }
+                WindowEvent::MouseUp(MouseButton::Left) => {
+                    if let Some(handle) = self.dragging.take() {
+                        cx.emit(RawParamEvent::EndSetParameter(self.param(handle).as_ptr()));
+                        cx.emit(SetDraggingBlend(false));
+                        cx.release();
+                        meta.consume();
+                    }
+                }
+                _ => {}
+            }
+        });
+    }
+
     fn draw(&self, cx: &mut DrawContext, canvas: &mut Canvas) {
         let time = self.blend_time.get(cx);
         let transition = self.blend_transition.get(cx);
@@ -0,0 +1,40 @@
+use nih_plug_vizia::vizia::prelude::*;
+
+use crate::editor_vizia::AppEvent;
+
+/// Container loading the audio files dropped on it from a slot, see `AppEvent::FilesDropped`.
+///
+/// A right click opens the menu of the slot.
+pub struct SlotDropTarget {
+    index: usize,
+}
+
+impl SlotDropTarget {
+    pub fn new<F>(cx: &mut Context, index: usize, content: F) -> Handle<Self>
+    where
+        F: FnOnce(&mut Context),
+    {
+        Self { index }.build(cx, content)
+    }
+}
+
+impl View for SlotDropTarget {
+    fn element(&self) -> Option<&'static str> {
+        Some("slot-drop-target")
+    }
+
+    fn event(&mut self, cx: &mut EventContext, event: &mut Event) {
+        event.map(|window_event: &WindowEvent, meta| {
+            // A dropped directory fills the slots from this one
+            if let WindowEvent::Drop(DropData::File(path)) = window_event {
+                cx.emit(AppEvent::FilesDropped(self.index, vec![path.clone()]));
+                meta.consume();
+            }
+
+            if let WindowEvent::MouseDown(MouseButton::Right) = window_event {
+                cx.emit(AppEvent::OpenSlotMenu(self.index));
+                meta.consume();
+            }
+        });
+    }
+}
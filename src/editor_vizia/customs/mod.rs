@@ -1,27 +1,33 @@
 pub mod adsr;
 pub mod blend;
+pub mod drop_target;
+pub mod navigator;
+pub mod spectrum;
 
+use nih_plug::util;
 use nih_plug_vizia::vizia::prelude::*;
 
-use crate::plugin::DEFAULT_BPM;
-
-pub fn get_num_displayed_frames(bars: f32, sr: f32, mut bpm: f32) -> usize {
-    if bpm <= 0. {
-        bpm = DEFAULT_BPM as f32;
-    }
-    (bars * 60.0 * sr / bpm) as usize
-}
+/// Levels at the bottom and the top of the level meters, in dB
+const METER_MIN_DB: f32 = -60.;
+const METER_MAX_DB: f32 = 6.;
 
+/// Returns `num_frames` frames of a channel, from the start offset moved by the scroll.
+///
+/// The frames outside of the data are silent. A single frame is placed at the left of the view.
 pub fn get_waveform(
     data: &[f32],
     num_frames: usize,
     num_channels: usize,
     channel_index: usize,
     offset_seconds: f32,
+    scroll_frames: i32,
     sample_rate: f32,
 ) -> Vec<[f32; 2]> {
-    let offset_frames = (offset_seconds * sample_rate) as i32;
-    let total_frames_in_data = data.len() / num_channels;
+    let offset_frames = (offset_seconds * sample_rate) as i32 + scroll_frames;
+    let total_frames_in_data = data.len() / num_channels.max(1);
+
+    // The last frame is at the right of the view, unless it is also the first one
+    let last_frame = num_frames.saturating_sub(1).max(1) as f32;
 
     let mut result = Vec::new();
 
@@ -38,7 +44,7 @@ pub fn get_waveform(
         };
 
         // Normalize x position (0.0 to 1.0)
-        let x_norm = i as f32 / (num_frames - 1) as f32;
+        let x_norm = i as f32 / last_frame;
 
         result.push([x_norm, sample_value]);
     }
@@ -46,6 +52,64 @@ pub fn get_waveform(
     result
 }
 
+/// Fewest points of the preview a view must hold, below it the frames are read instead
+const MIN_PREVIEW_POINTS: usize = 2048;
+
+/// Returns the points of a waveform preview displayed by `get_waveform` with the same view,
+/// `None` when the view is zoomed in too much for the preview to show its details.
+///
+/// The view is silent outside of the data.
+pub fn get_preview_waveform(
+    preview: &[[f32; 2]],
+    num_frames: usize,
+    offset_seconds: f32,
+    scroll_frames: i32,
+    sample_rate: f32,
+) -> Option<Vec<[f32; 2]>> {
+    let offset_frames = (offset_seconds * sample_rate) as i32 + scroll_frames;
+    let start = offset_frames as f32;
+    let length = (num_frames.max(2) - 1) as f32;
+
+    let mut result = preview
+        .iter()
+        .filter(|[frame, _]| (start..=start + length).contains(frame))
+        .map(|&[frame, value]| [(frame - start) / length, value])
+        .collect::<Vec<_>>();
+    if result.len() < MIN_PREVIEW_POINTS {
+        return None;
+    }
+
+    if result[0][0] > 0. {
+        result.insert(0, [0., 0.]);
+    }
+    if result[result.len() - 1][0] < 1. {
+        result.push([1., 0.]);
+    }
+    Some(result)
+}
+
+/// Number of RMS windows across the view, the window grows with the displayed frames
+const RMS_WINDOWS: usize = 256;
+
+/// Returns the windowed RMS of a waveform from `get_waveform`, one point per window.
+///
+/// The window covers a fixed fraction of the view so the envelope stays smooth when zoomed out.
+pub fn get_rms_envelope(waveform: &[[f32; 2]]) -> Vec<[f32; 2]> {
+    let window = (waveform.len() / RMS_WINDOWS).max(1);
+
+    waveform
+        .chunks(window)
+        .map(|chunk| {
+            let sum: f32 = chunk.iter().map(|[_, y]| y * y).sum();
+            let rms = (sum / chunk.len() as f32).sqrt();
+
+            // Placed at the middle of the window
+            let x = (chunk[0][0] + chunk[chunk.len() - 1][0]) / 2.;
+            [x, rms]
+        })
+        .collect()
+}
+
 pub fn neon_indicator<T: Lens<Target = f32>>(cx: &mut Context, lens: T) -> Handle<'_, Element> {
     Element::new(cx)
         .height(Stretch(1.0))
@@ -53,3 +117,39 @@ pub fn neon_indicator<T: Lens<Target = f32>>(cx: &mut Context, lens: T) -> Handl
         .disabled(lens.map(|&value| value <= 0.))
         .class("time-indicator")
 }
+
+/// Vertical level meter of a peak given as a linear gain, it is highlighted over 0 dB
+pub fn level_meter<T: Lens<Target = f32>>(cx: &mut Context, lens: T) -> Handle<'_, VStack> {
+    VStack::new(cx, move |cx| {
+        Element::new(cx)
+            .width(Stretch(1.0))
+            .height(lens.map(|&peak| {
+                let db = util::gain_to_db(peak.max(util::MINUS_INFINITY_GAIN));
+                let fill = (db - METER_MIN_DB) / (METER_MAX_DB - METER_MIN_DB);
+                Percentage(fill.clamp(0., 1.) * 100.)
+            }))
+            .toggle_class("clipping", lens.map(|&peak| peak > 1.))
+            .class("level-meter-fill");
+    })
+    // The fill grows from the bottom
+    .child_top(Stretch(1.0))
+    .height(Stretch(1.0))
+    .class("level-meter")
+}
+
+/// Vertical marker at the start offset, hidden when scrolled out of the view
+pub fn start_marker<T: Lens<Target = f32>>(cx: &mut Context, lens: T) -> Handle<'_, Element> {
+    Element::new(cx)
+        .height(Stretch(1.0))
+        .left(lens.map(|val| Percentage(val.clamp(0., 1.) * 100.0)))
+        .visibility(lens.map(|val| (0.0..1.0).contains(val)))
+        .class("start-marker")
+}
+
+pub fn trim_end_overlay<T: Lens<Target = f32>>(cx: &mut Context, lens: T) -> Handle<'_, Element> {
+    Element::new(cx)
+        .height(Stretch(1.0))
+        .left(lens.map(|val| Percentage(val.clamp(0., 1.) * 100.0)))
+        .width(Stretch(1.0))
+        .class("trim-end-overlay")
+}
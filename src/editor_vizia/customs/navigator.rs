@@ -0,0 +1,93 @@
+use nih_plug_vizia::vizia::prelude::*;
+
+use crate::editor_vizia::events::{ScrollWaveform, ZoomWaveform};
+
+/// Zoom factor applied for each step of the mouse wheel
+const WHEEL_ZOOM: f32 = 1.25;
+
+/// Distance in pixels the cursor has to move before a press becomes a drag
+const DRAG_THRESHOLD: f32 = 3.;
+
+/// Transparent layer over the waveform, zooms with the mouse wheel, scrolls on drag
+/// and reports clicks as a position (0.0-1.0 of the width)
+pub struct WaveformNavigator {
+    /// Cursor x when the button was pressed
+    press_x: Option<f32>,
+
+    /// Last cursor x once the press became a drag
+    drag_x: Option<f32>,
+
+    on_click: Box<dyn Fn(&mut EventContext, f32)>,
+}
+
+impl WaveformNavigator {
+    pub fn new<F>(cx: &mut Context, on_click: F) -> Handle<Self>
+    where
+        F: 'static + Fn(&mut EventContext, f32),
+    {
+        Self {
+            press_x: None,
+            drag_x: None,
+            on_click: Box::new(on_click),
+        }
+        .build(cx, |_| {})
+        .cursor(CursorIcon::Grab)
+        .class("waveform-navigator")
+    }
+}
+
+impl View for WaveformNavigator {
+    fn element(&self) -> Option<&'static str> {
+        Some("waveform-navigator")
+    }
+
+    fn event(&mut self, cx: &mut EventContext, event: &mut Event) {
+        event.map(|window_event: &WindowEvent, meta| match window_event {
+            WindowEvent::MouseScroll(_, y) => {
+                // The point under the cursor stays in place
+                let bounds = cx.bounds();
+                let anchor = ((cx.mouse().cursorx - bounds.x) / bounds.w).clamp(0., 1.);
+                cx.emit(ZoomWaveform {
+                    factor: WHEEL_ZOOM.powf(-y),
+                    anchor,
+                });
+                meta.consume();
+            }
+            WindowEvent::MouseDown(MouseButton::Left) => {
+                cx.capture();
+                self.press_x = Some(cx.mouse().cursorx);
+                meta.consume();
+            }
+            WindowEvent::MouseMove(x, _) => {
+                let Some(press_x) = self.press_x else {
+                    return;
+                };
+                let last_x = match self.drag_x {
+                    Some(last_x) => last_x,
+                    None if (x - press_x).abs() >= DRAG_THRESHOLD => press_x,
+                    None => return,
+                };
+                let width = cx.bounds().w;
+                if width > 0. {
+                    cx.emit(ScrollWaveform((x - last_x) / width));
+                }
+                self.drag_x = Some(*x);
+            }
+            WindowEvent::MouseUp(MouseButton::Left) => {
+                cx.release();
+                // Released without dragging, it's a click
+                if let (Some(press_x), None) = (self.press_x, self.drag_x) {
+                    let bounds = cx.bounds();
+                    if bounds.w > 0. {
+                        let x = ((press_x - bounds.x) / bounds.w).clamp(0., 1.);
+                        (self.on_click)(cx, x);
+                    }
+                }
+                self.press_x = None;
+                self.drag_x = None;
+                meta.consume();
+            }
+            _ => {}
+        });
+    }
+}
@@ -0,0 +1,91 @@
+use std::sync::Arc;
+
+use nih_plug_vizia::vizia::{prelude::*, vg};
+
+use crate::utils::{SPECTRUM_MIN_DB, SPECTRUM_MIN_FREQUENCY};
+
+/// Frequencies marked by a vertical line, in Hz
+const GRID_FREQUENCIES: [f32; 3] = [100., 1000., 10000.];
+
+/// Magnitude spectrum of a sample on a log frequency scale, from `utils::compute_spectrum`
+pub struct SpectrumView {
+    points: Arc<Vec<[f32; 2]>>,
+
+    /// The frequency at the right of the view, in Hz
+    nyquist: f32,
+}
+
+impl SpectrumView {
+    pub fn new(cx: &mut Context, points: Arc<Vec<[f32; 2]>>, sample_rate: f32) -> Handle<Self> {
+        Self {
+            points,
+            nyquist: sample_rate / 2.,
+        }
+        .build(cx, |_| {})
+        .class("spectrum-view")
+    }
+
+    /// Returns the x of a frequency, 0 at the lowest frequency and 1 at nyquist
+    fn frequency_to_x(&self, frequency: f32) -> f32 {
+        (frequency / SPECTRUM_MIN_FREQUENCY).ln() / (self.nyquist / SPECTRUM_MIN_FREQUENCY).ln()
+    }
+}
+
+impl View for SpectrumView {
+    fn element(&self) -> Option<&'static str> {
+        Some("spectrum-view")
+    }
+
+    fn draw(&self, cx: &mut DrawContext, canvas: &mut Canvas) {
+        let BoundingBox { x, y, w, h } = cx.bounds();
+        let color = cx.font_color();
+        if self.points.len() < 2 || self.nyquist <= SPECTRUM_MIN_FREQUENCY {
+            return;
+        }
+
+        canvas.save();
+        canvas.scissor(x, y, w, h);
+
+        // Background, the spectrum is drawn over the waveform
+        let mut background = vg::Path::new();
+        background.rect(x, y, w, h);
+        canvas.fill_path(&background, &vg::Paint::color(cx.background_color().into()));
+
+        let mut grid = vg::Path::new();
+        for frequency in GRID_FREQUENCIES.into_iter().filter(|f| *f < self.nyquist) {
+            let grid_x = x + self.frequency_to_x(frequency) * w;
+            grid.move_to(grid_x, y);
+            grid.line_to(grid_x, y + h);
+        }
+        canvas.stroke_path(
+            &grid,
+            &vg::Paint::color(Color::rgba(color.r(), color.g(), color.b(), 40).into())
+                .with_line_width(cx.scale_factor()),
+        );
+
+        let mut path = vg::Path::new();
+        for (i, &[frequency, level]) in self.points.iter().enumerate() {
+            let point_x = x + self.frequency_to_x(frequency) * w;
+            let point_y = y + (level / SPECTRUM_MIN_DB).clamp(0., 1.) * h;
+            if i == 0 {
+                path.move_to(point_x, point_y);
+            } else {
+                path.line_to(point_x, point_y);
+            }
+        }
+
+        let mut fill_path = path.clone();
+        fill_path.line_to(x + w, y + h);
+        fill_path.line_to(x, y + h);
+        fill_path.close();
+        canvas.fill_path(
+            &fill_path,
+            &vg::Paint::color(Color::rgba(color.r(), color.g(), color.b(), 64).into()),
+        );
+        canvas.stroke_path(
+            &path,
+            &vg::Paint::color(color.into()).with_line_width(cx.scale_factor() * cx.outline_width()),
+        );
+        canvas.restore();
+    }
+}
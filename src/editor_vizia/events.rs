@@ -3,3 +3,15 @@ pub struct SetDraggingBlend(pub bool);
 
 #[derive(Debug, Clone, Copy)]
 pub struct SetDraggingAdsr(pub bool);
+
+/// Zooms the waveform by `factor` (> 1 shows more beats), keeping the point at
+/// `anchor` (0.0-1.0 of the width) in place
+#[derive(Debug, Clone, Copy)]
+pub struct ZoomWaveform {
+    pub factor: f32,
+    pub anchor: f32,
+}
+
+/// Scrolls the waveform by a fraction of its width, positive moves the view back in time
+#[derive(Debug, Clone, Copy)]
+pub struct ScrollWaveform(pub f32);
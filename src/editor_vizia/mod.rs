@@ -6,7 +6,8 @@ mod widgets;
 
 use std::path::PathBuf;
 use std::sync::atomic::Ordering;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 use icons::*;
 use nih_plug::prelude::*;
@@ -14,22 +15,55 @@ use nih_plug_vizia::vizia::prelude::*;
 use nih_plug_vizia::widgets::RawParamEvent;
 use nih_plug_vizia::{create_vizia_editor, ViziaState};
 
-use crate::editor_vizia::events::{SetDraggingAdsr, SetDraggingBlend};
+use crate::editor_vizia::events::{
+    ScrollWaveform, SetDraggingAdsr, SetDraggingBlend, ZoomWaveform,
+};
 use crate::editor_vizia::widgets::svg_icon;
 use crate::editor_vizia::widgets::widget_base::ParamWidget;
-use crate::params::BlendGroup;
-use crate::params::{SamplePlayerParams, MAX_SAMPLES};
+use crate::filter::FilterMode;
+use crate::midi_learn::CcTarget;
+use crate::params::{SamplePlayerParams, MAX_BLEND_POINTS, MAX_SAMPLES};
+use crate::pitch_shift::PitchShiftKind;
 use crate::plugin::HardKickSampler;
+use crate::presets::{self, Preset};
+use crate::saturation::SaturationMode;
 use crate::shared_states::SharedStates;
-use crate::tasks::{TaskRequests, TaskResults};
+use crate::tasks::{self, TaskRequests, TaskResults};
 use crate::utils;
 use style::*;
 use widgets::widget_base::*;
 
+/// Range of the waveform zoom, in beats displayed
+const MIN_DISPLAYED_BEATS: f32 = 1. / 16.;
+const MAX_DISPLAYED_BEATS: f32 = 16.;
+
+/// Number of sample tabs on a row, the next ones go on a new row
+const TABS_PER_ROW: usize = 8;
+
+/// How often the CC values received by the audio thread are applied to the params
+const MIDI_CC_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(20);
+
 pub enum AppEvent {
     SelectSample(usize),
     FileLoading(usize, PathBuf),
+    FilesDropped(usize, Vec<PathBuf>),
     SampleDeleted(usize),
+    UndoLoad(usize),
+    LearnCc(CcTarget),
+    PollMidiCc,
+    CopySlot(usize),
+    PasteSlot(usize),
+    CopySlotToAll(usize),
+    DuplicateSlot(usize),
+    RevealSample(usize),
+    OpenSlotMenu(usize),
+    CloseSlotMenu,
+    SavePreset(PathBuf),
+    LoadPreset(PathBuf, Preset),
+    SetTonal(usize, bool),
+    ClearAll,
+    CancelClearAll,
+    ToggleAlignmentTick,
 }
 
 #[derive(Lens)]
@@ -39,6 +73,14 @@ pub struct Data {
     executor: AsyncExecutor<HardKickSampler>,
     is_dragging_blend: bool,
     is_dragging_adsr: bool,
+    confirm_clear_all: bool,
+    alignment_tick: bool,
+    copied_slot: Option<usize>,
+    slot_menu: Option<usize>,
+    drop_slots: utils::DropSlots,
+    learning_cc: Option<CcTarget>,
+    displayed_beats: f32,
+    waveform_scroll: f32,
 }
 
 impl Model for Data {
@@ -48,25 +90,75 @@ impl Model for Data {
                 self.selected_sample = *index;
             }
             AppEvent::FileLoading(index, path) => {
-                self.executor
-                    .execute_background(TaskRequests::LoadFile(*index, path.clone()));
+                let request = if self.states.params.split_stereo.value() {
+                    TaskRequests::LoadFileSplit(*index, path.clone())
+                } else {
+                    TaskRequests::LoadFile(*index, path.clone())
+                };
+                self.executor.execute_background(request);
 
-                // Check if the sample is tonal
-                // We also check the current value of the root note to set it
+                // A note in the file name tells both the root note and that the sample is tonal
                 let root = utils::get_root_note_from_filename(
                     path.file_name()
                         .and_then(|name| name.to_str())
                         .unwrap_or("")
                         .into(),
-                )
-                .unwrap_or_default();
-                // Get the param
-                let param = &get_param(&self.states, self.selected_sample).root_note;
-                let ptr = param.as_ptr();
-                let normalized = param.preview_normalized(root);
-                cx.emit(RawParamEvent::BeginSetParameter(ptr));
-                cx.emit(RawParamEvent::SetParameterNormalized(ptr, normalized));
-                cx.emit(RawParamEvent::EndSetParameter(ptr));
+                );
+                set_param(
+                    cx,
+                    &get_param(&self.states, *index).root_note,
+                    root.unwrap_or_default(),
+                );
+                if root.is_some() {
+                    set_param(cx, &get_param(&self.states, *index).is_tonal, true);
+                }
+
+                // Detect whether the sample is tonal, this is opt-in
+                // since it requires to analyse the whole file
+                if root.is_none() && get_param(&self.states, *index).auto_tonal.value() {
+                    let (index, path) = (*index, path.clone());
+                    cx.spawn(move |proxy: &mut ContextProxy| {
+                        if let Ok(audio_data) = utils::load_audio_file(&path) {
+                            let is_tonal = utils::is_tonal_sample(
+                                &audio_data.data,
+                                audio_data.spec.channels as usize,
+                                audio_data.spec.sample_rate as f32,
+                            );
+                            let _ = proxy.emit(AppEvent::SetTonal(index, is_tonal));
+                        }
+                    });
+                }
+            }
+            AppEvent::FilesDropped(index, paths) => {
+                // Several files fill the slots from the one they were dropped on
+                let files = utils::audio_files_from_drop(paths);
+                let first = self
+                    .drop_slots
+                    .first_slot(*index, files.len(), Instant::now());
+                for (index, path) in (first..MAX_SAMPLES).zip(files) {
+                    cx.emit(AppEvent::FileLoading(index, path));
+                }
+            }
+            AppEvent::ClearAll => {
+                // Clearing every slot is destructive, so the first
+                // press only asks for a confirmation
+                if self.confirm_clear_all {
+                    self.executor
+                        .execute_background(TaskRequests::TransfertTask(TaskResults::ClearAll));
+                }
+                self.confirm_clear_all = !self.confirm_clear_all;
+            }
+            AppEvent::CancelClearAll => {
+                self.confirm_clear_all = false;
+            }
+            AppEvent::ToggleAlignmentTick => {
+                self.alignment_tick = !self.alignment_tick;
+                self.states
+                    .alignment_tick
+                    .store(self.alignment_tick, Ordering::Relaxed);
+            }
+            AppEvent::SetTonal(index, is_tonal) => {
+                set_param(cx, &get_param(&self.states, *index).is_tonal, *is_tonal);
             }
             AppEvent::SampleDeleted(index) => {
                 self.executor
@@ -74,6 +166,137 @@ impl Model for Data {
                         *index,
                     )));
             }
+            AppEvent::UndoLoad(index) => {
+                self.executor
+                    .execute_background(TaskRequests::UndoLoad(*index));
+            }
+            AppEvent::LearnCc(target) => {
+                // Pressing the learn button again cancels it
+                let target = (self.learning_cc != Some(*target)).then_some(*target);
+                self.states.midi_learn.set_learning(target);
+                self.learning_cc = target;
+            }
+            AppEvent::PollMidiCc => {
+                for target in CcTarget::ALL {
+                    if let Some(value) = self.states.midi_learn.take_value(target) {
+                        let ptr = match target {
+                            CcTarget::Gain => self.states.params.gain.as_ptr(),
+                            CcTarget::Drive => self.states.params.drive.as_ptr(),
+                        };
+                        cx.emit(RawParamEvent::BeginSetParameter(ptr));
+                        cx.emit(RawParamEvent::SetParameterNormalized(ptr, value));
+                        cx.emit(RawParamEvent::EndSetParameter(ptr));
+                    }
+                }
+
+                // Only update when it changes, the learn button is bound to it
+                let learning = self.states.midi_learn.learning();
+                if learning != self.learning_cc {
+                    self.learning_cc = learning;
+                }
+            }
+            AppEvent::CopySlot(index) => {
+                self.copied_slot = Some(*index);
+            }
+            AppEvent::PasteSlot(index) => {
+                if let Some(source) = self.copied_slot {
+                    copy_slot(cx, &self.states, source, *index);
+                }
+            }
+            AppEvent::CopySlotToAll(index) => {
+                for target in 0..MAX_SAMPLES {
+                    copy_slot(cx, &self.states, *index, target);
+                }
+            }
+            AppEvent::DuplicateSlot(index) => {
+                // The settings are copied here, the sample is loaded by the executor
+                let target = *index + 1;
+                if target < MAX_SAMPLES {
+                    copy_slot(cx, &self.states, *index, target);
+                    self.executor
+                        .execute_background(TaskRequests::DuplicateSample(*index, target));
+                }
+            }
+            AppEvent::RevealSample(index) => {
+                let path = get_param(&self.states, *index)
+                    .sample_path
+                    .read()
+                    .ok()
+                    .and_then(|path| path.clone());
+                if let Some(path) = path {
+                    cx.spawn(move |_| {
+                        if let Err(e) = utils::reveal_in_file_manager(&path) {
+                            nih_error!("Failed to reveal {:?}: {}", path, e);
+                        }
+                    });
+                }
+            }
+            AppEvent::OpenSlotMenu(index) => {
+                self.slot_menu = Some(*index);
+            }
+            AppEvent::CloseSlotMenu => {
+                self.slot_menu = None;
+            }
+            AppEvent::SavePreset(path) => {
+                self.executor
+                    .execute_background(TaskRequests::SavePreset(path.clone()));
+            }
+            AppEvent::LoadPreset(path, preset) => {
+                // Params can only be set from the GUI, the samples are loaded by the executor
+                for (id, ptr, _) in self.states.params.param_map() {
+                    if let Some(&normalized) = preset.params.get(&id) {
+                        cx.emit(RawParamEvent::BeginSetParameter(ptr));
+                        cx.emit(RawParamEvent::SetParameterNormalized(ptr, normalized));
+                        cx.emit(RawParamEvent::EndSetParameter(ptr));
+                    }
+                }
+                self.executor
+                    .execute_background(TaskRequests::LoadPreset(path.clone()));
+            }
+        });
+
+        // Paste a file path (or file url) to load it in the selected slot
+        event.map(|window_event: &WindowEvent, meta| {
+            if let WindowEvent::KeyDown(Code::KeyV, _) = window_event {
+                let modifiers = cx.modifiers();
+                if modifiers.contains(Modifiers::CTRL) || modifiers.contains(Modifiers::LOGO) {
+                    if let Some(path) = cx
+                        .get_clipboard()
+                        .ok()
+                        .and_then(|text| utils::path_from_text(&text))
+                    {
+                        cx.emit(AppEvent::FileLoading(self.selected_sample, path));
+                        meta.consume();
+                    }
+                }
+            }
+        });
+
+        // Files dropped anywhere but on a tab go to the selected slot
+        event.map(|window_event: &WindowEvent, meta| {
+            if let WindowEvent::Drop(DropData::File(path)) = window_event {
+                cx.emit(AppEvent::FilesDropped(
+                    self.selected_sample,
+                    vec![path.clone()],
+                ));
+                meta.consume();
+            }
+        });
+
+        // The arrows cycle through the slots. Focused text fields consume
+        // the keys they use, only the unhandled ones get here
+        event.map(|window_event: &WindowEvent, meta| {
+            let step = match window_event {
+                WindowEvent::KeyDown(Code::ArrowLeft, _) => MAX_SAMPLES - 1,
+                WindowEvent::KeyDown(Code::ArrowRight, _) => 1,
+                _ => return,
+            };
+            if cx.modifiers().is_empty() {
+                cx.emit(AppEvent::SelectSample(
+                    (self.selected_sample + step) % MAX_SAMPLES,
+                ));
+                meta.consume();
+            }
         });
 
         event.map(|event: &SetDraggingBlend, meta| {
@@ -85,6 +308,33 @@ impl Model for Data {
             self.is_dragging_adsr = event.0;
             meta.consume();
         });
+
+        // The scroll (in beats from the start offset) can go back to the lowest start offset,
+        // so a click before the start marker moves it earlier
+        event.map(|event: &ZoomWaveform, meta| {
+            let anchor_beats = self.waveform_scroll + event.anchor * self.displayed_beats;
+            self.displayed_beats = (self.displayed_beats * event.factor)
+                .clamp(MIN_DISPLAYED_BEATS, MAX_DISPLAYED_BEATS);
+            self.waveform_scroll =
+                (anchor_beats - event.anchor * self.displayed_beats).max(self.min_scroll());
+            meta.consume();
+        });
+
+        event.map(|event: &ScrollWaveform, meta| {
+            self.waveform_scroll =
+                (self.waveform_scroll - event.0 * self.displayed_beats).max(self.min_scroll());
+            meta.consume();
+        });
+    }
+}
+
+impl Data {
+    /// Returns the lowest waveform scroll, in beats, where the view starts at the
+    /// lowest start offset of the selected slot
+    fn min_scroll(&self) -> f32 {
+        let start_offset = &get_param(&self.states, self.selected_sample).start_offset;
+        let bpm = utils::effective_bpm(Some(self.states.host_bpm.load(Ordering::Relaxed)));
+        (start_offset.preview_plain(0.) - start_offset.value()) * bpm / 60.
     }
 }
 
@@ -92,28 +342,207 @@ pub fn get_param(st: &Arc<SharedStates>, index: usize) -> &SamplePlayerParams {
     &st.params.samples[index]
 }
 
-fn create_title_section(cx: &mut Context) {
-    // Title - this doesn't need to change
-    Label::new(cx, "Hard Kick Sampler").class("title");
+/// Sets a param from the GUI, wrapping the change in a begin / end gesture
+fn set_param<P: Param>(cx: &mut EventContext, param: &P, value: P::Plain) {
+    let ptr = param.as_ptr();
+    let normalized = param.preview_normalized(value);
+    cx.emit(RawParamEvent::BeginSetParameter(ptr));
+    cx.emit(RawParamEvent::SetParameterNormalized(ptr, normalized));
+    cx.emit(RawParamEvent::EndSetParameter(ptr));
 }
 
-fn create_sample_tabs(cx: &mut Context) {
-    // Tabs - OUTSIDE the binding so they keep their event handlers
+/// Copies the settings of a slot into another one, the sample isn't copied
+fn copy_slot(cx: &mut EventContext, states: &Arc<SharedStates>, source: usize, target: usize) {
+    if source == target {
+        return;
+    }
+    get_param(states, target).copy_params_from(get_param(states, source), |ptr, normalized| {
+        cx.emit(RawParamEvent::BeginSetParameter(ptr));
+        cx.emit(RawParamEvent::SetParameterNormalized(ptr, normalized));
+        cx.emit(RawParamEvent::EndSetParameter(ptr));
+    });
+}
+
+fn create_title_section(cx: &mut Context) {
     HStack::new(cx, |cx| {
-        for index in 0..MAX_SAMPLES {
-            let txt = format!("Sample {}", index + 1);
+        // Title - this doesn't need to change
+        Label::new(cx, "Hard Kick Sampler").class("title");
+
+        // Lit while any sample is playing
+        Element::new(cx).class("active-indicator").toggle_class(
+            "active",
+            Data::states.map(|st| st.is_active.load(Ordering::Relaxed)),
+        );
+
+        // Global actions, clear all has a confirmation step
+        HStack::new(cx, |cx| {
+            widgets::ParamDragNumber::new(cx, Data::states, |st| &st.params.base_note);
+            widgets::ParamDragNumber::new(cx, Data::states, |st| &st.params.trigger_mode);
+            widgets::ButtonToggle::builder()
+                .no_icon()
+                .build(cx, Data::states, |st| &st.params.stack_mode);
+            widgets::ButtonToggle::builder()
+                .no_icon()
+                .build(cx, Data::states, |st| &st.params.split_stereo);
+            widgets::ButtonToggle::builder()
+                .no_icon()
+                .build(cx, Data::states, |st| &st.params.recursive_browse);
+            widgets::ButtonToggle::builder()
+                .no_icon()
+                .build(cx, Data::states, |st| &st.params.mini_editor);
+            widgets::ButtonToggle::builder()
+                .with_text("HQ")
+                .no_icon()
+                .build(cx, Data::states, |st| &st.params.high_quality);
+            widgets::ButtonToggle::builder()
+                .with_text("Resample")
+                .no_icon()
+                .build(cx, Data::states, |st| &st.params.resample_to_host);
+            widgets::ButtonToggle::builder()
+                .no_icon()
+                .build(cx, Data::states, |st| &st.params.dim);
+            widgets::ButtonToggle::builder()
+                .no_icon()
+                .build(cx, Data::states, |st| &st.params.mute);
+
+            // Monitoring only, not a param so it is never saved nor rendered
             Button::new(
                 cx,
-                move |cx| cx.emit(AppEvent::SelectSample(index)), // Add the event handler!
-                |cx| Label::new(cx, &txt).cursor(CursorIcon::Hand),
+                |cx| cx.emit(AppEvent::ToggleAlignmentTick),
+                |cx| Label::new(cx, "Tick"),
             )
-            .hoverable(true)
-            .class("tab")
-            .toggle_class(
-                "selected",
-                Data::selected_sample.map(move |selected| *selected == index),
+            .checkable(true)
+            .checked(Data::alignment_tick);
+
+            Button::new(
+                cx,
+                |cx| {
+                    cx.spawn(|proxy: &mut ContextProxy| {
+                        let path_opt = rfd::FileDialog::new()
+                            .add_filter("preset", &[presets::PRESET_EXTENSION])
+                            .save_file();
+                        if let Some(path) = path_opt {
+                            let _ = proxy.emit(AppEvent::SavePreset(
+                                path.with_extension(presets::PRESET_EXTENSION),
+                            ));
+                        }
+                    });
+                },
+                |cx| Label::new(cx, "Save"),
             );
-        }
+            Button::new(
+                cx,
+                |cx| {
+                    cx.spawn(|proxy: &mut ContextProxy| {
+                        let path_opt = rfd::FileDialog::new()
+                            .add_filter("preset", &[presets::PRESET_EXTENSION])
+                            .pick_file();
+                        if let Some(path) = path_opt {
+                            match presets::read_preset(&path) {
+                                Ok(preset) => {
+                                    let _ = proxy.emit(AppEvent::LoadPreset(path, preset));
+                                }
+                                Err(e) => nih_error!("Failed to read preset {:?}: {}", path, e),
+                            }
+                        }
+                    });
+                },
+                |cx| Label::new(cx, "Load"),
+            );
+
+            Button::new(
+                cx,
+                |cx| cx.emit(AppEvent::ClearAll),
+                |cx| {
+                    Label::new(
+                        cx,
+                        Data::confirm_clear_all.map(|confirm| {
+                            if *confirm {
+                                String::from("Confirm clear all")
+                            } else {
+                                String::from("Clear all")
+                            }
+                        }),
+                    )
+                },
+            )
+            .toggle_class("confirm", Data::confirm_clear_all);
+            Button::new(
+                cx,
+                |cx| cx.emit(AppEvent::CancelClearAll),
+                |cx| Label::new(cx, "Cancel"),
+            )
+            .visibility(Data::confirm_clear_all);
+        })
+        .col_between(Pixels(4.0))
+        .child_left(Stretch(1.0))
+        .child_top(Stretch(1.0))
+        .child_bottom(Stretch(1.0));
+    })
+    .height(Auto);
+}
+
+fn create_sample_tabs(cx: &mut Context) {
+    // Tabs - OUTSIDE the binding so they keep their event handlers
+    HStack::new(cx, |cx| {
+        // The tabs wrap on several rows so they don't overflow the window with many slots
+        VStack::new(cx, |cx| {
+            for row_start in (0..MAX_SAMPLES).step_by(TABS_PER_ROW) {
+                HStack::new(cx, |cx| {
+                    for index in row_start..(row_start + TABS_PER_ROW).min(MAX_SAMPLES) {
+                        let txt = format!("Sample {}", index + 1);
+                        // A file dropped on a tab goes to its slot, a right click opens its menu
+                        customs::drop_target::SlotDropTarget::new(cx, index, |cx| {
+                            Button::new(
+                                cx,
+                                move |cx| cx.emit(AppEvent::SelectSample(index)),
+                                |cx| Label::new(cx, &txt).cursor(CursorIcon::Hand),
+                            )
+                            .hoverable(true)
+                            .class("tab")
+                            .toggle_class(
+                                "selected",
+                                Data::selected_sample.map(move |selected| *selected == index),
+                            );
+                            create_slot_menu(cx, index);
+                        })
+                        .width(Auto)
+                        .height(Auto);
+                    }
+                })
+                .width(Auto)
+                .height(Auto);
+            }
+        })
+        .width(Auto)
+        .height(Auto);
+
+        // Settings of the selected slot, the sample stays where it is
+        Button::new(
+            cx,
+            |cx| {
+                let index = Data::selected_sample.get(cx);
+                cx.emit(AppEvent::CopySlot(index));
+            },
+            |cx| Label::new(cx, "Copy"),
+        );
+        Button::new(
+            cx,
+            |cx| {
+                let index = Data::selected_sample.get(cx);
+                cx.emit(AppEvent::PasteSlot(index));
+            },
+            |cx| Label::new(cx, "Paste"),
+        )
+        .disabled(Data::copied_slot.map(|slot| slot.is_none()));
+        Button::new(
+            cx,
+            |cx| {
+                let index = Data::selected_sample.get(cx);
+                cx.emit(AppEvent::CopySlotToAll(index));
+            },
+            |cx| Label::new(cx, "Copy to all"),
+        );
     })
     .width(Stretch(1.0))
     .col_between(Stretch(1.0))
@@ -121,6 +550,37 @@ fn create_sample_tabs(cx: &mut Context) {
     .height(Auto);
 }
 
+/// Menu of a tab, managing its slot without selecting it
+fn create_slot_menu(cx: &mut Context, index: usize) {
+    Popup::new(
+        cx,
+        Data::slot_menu.map(move |menu| *menu == Some(index)),
+        true,
+        move |cx| {
+            let items = [
+                ("Clear", AppEvent::SampleDeleted as fn(usize) -> AppEvent),
+                ("Duplicate to next slot", AppEvent::DuplicateSlot),
+                ("Copy settings", AppEvent::CopySlot),
+                ("Reveal in file browser", AppEvent::RevealSample),
+            ];
+            for (text, event) in items {
+                Button::new(
+                    cx,
+                    move |cx| {
+                        cx.emit(event(index));
+                        cx.emit(AppEvent::CloseSlotMenu);
+                    },
+                    |cx| Label::new(cx, text),
+                )
+                .width(Stretch(1.0))
+                .class("menu-item");
+            }
+        },
+    )
+    .on_blur(|cx| cx.emit(AppEvent::CloseSlotMenu))
+    .class("slot-menu");
+}
+
 fn create_first_panel_row(cx: &mut Context, index: usize) {
     // First panel row - equal height
     HStack::new(cx, |cx| {
@@ -135,6 +595,12 @@ fn create_first_panel_row(cx: &mut Context, index: usize) {
                 widgets::ParamDragNumber::new(cx, Data::states, move |st| {
                     &get_param(st, index).semitone_offset
                 });
+
+                widgets::ParamKnob::builder().centered().hide_label().build(
+                    cx,
+                    Data::states,
+                    move |st| &get_param(st, index).fine_tune,
+                );
             });
 
             widgets::ParamDragNumber::new(cx, Data::states, move |st| {
@@ -142,6 +608,23 @@ fn create_first_panel_row(cx: &mut Context, index: usize) {
             })
             .class("root-note-select")
             .disabled(Data::states.map(move |st| !get_param(st, index).is_tonal.value()));
+
+            widgets::ButtonToggle::builder()
+                .with_text("Auto")
+                .no_icon()
+                .build(cx, Data::states, move |st| &get_param(st, index).auto_tonal);
+
+            widgets::ParamDragNumber::new(cx, Data::states, move |st| {
+                &get_param(st, index).midi_channel
+            });
+
+            widgets::ParamDragNumber::new(cx, Data::states, move |st| {
+                &get_param(st, index).output_bus
+            });
+
+            widgets::ParamDragNumber::new(cx, Data::states, move |st| {
+                &get_param(st, index).choke_group
+            });
         })
         .width(Stretch(0.3));
         widgets::WidgetPanel::new(cx, "Pitch Algorithm", |cx| {
@@ -151,35 +634,126 @@ fn create_first_panel_row(cx: &mut Context, index: usize) {
                 move |st| &get_param(st, index).pitch_shift_kind,
                 false,
             );
+
+            widgets::ParamDragNumber::new(cx, Data::states, move |st| {
+                &get_param(st, index).interpolation
+            })
+            .disabled(Data::states.map(move |st| {
+                get_param(st, index).pitch_shift_kind.value() != PitchShiftKind::Classic
+            }));
+
+            widgets::ButtonToggle::builder()
+                .with_text("Formants")
+                .no_icon()
+                .build(cx, Data::states, move |st| {
+                    &get_param(st, index).preserve_formants
+                })
+                .disabled(Data::states.map(move |st| {
+                    get_param(st, index).pitch_shift_kind.value() != PitchShiftKind::Psola
+                }));
+
+            // The fundamental PSOLA locked onto
+            Label::new(
+                cx,
+                Data::states.map(move |st| match st.get_detected_frequency(index) {
+                    Some(frequency) => format!(
+                        "Detected: {:.0} Hz ({})",
+                        frequency,
+                        utils::midi_note_to_name(utils::frequency_to_midi_note(frequency))
+                    ),
+                    None if st.has_pitch_detection_failed(index) => {
+                        String::from("Pitch detection failed, using classic")
+                    }
+                    None => String::from("\u{2014}"),
+                }),
+            )
+            .visibility(Data::states.map(move |st| {
+                get_param(st, index).pitch_shift_kind.value() == PitchShiftKind::Psola
+            }))
+            .class("detected-frequency");
         })
         .width(Stretch(0.2));
+        widgets::WidgetPanel::new(cx, "Filter", |cx| {
+            widgets::ParamRadio::vertical(
+                cx,
+                Data::states,
+                move |st| &get_param(st, index).filter_mode,
+                false,
+            );
+            widgets::ParamKnob::new(cx, Data::states, move |st| {
+                &get_param(st, index).filter_cutoff
+            })
+            .disabled(
+                Data::states
+                    .map(move |st| get_param(st, index).filter_mode.value() == FilterMode::Off),
+            );
+            widgets::ParamKnob::new(cx, Data::states, move |st| &get_param(st, index).dry_wet)
+                .disabled(
+                    Data::states
+                        .map(move |st| get_param(st, index).filter_mode.value() == FilterMode::Off),
+                );
+        })
+        .width(Stretch(0.25));
         widgets::WidgetPanel::new(cx, "Blend Group", |cx| {
+            // The blend layer replaces the blend group when set
             widgets::ParamRadio::vertical(
                 cx,
                 Data::states,
                 move |st| &get_param(st, index).blend_group,
                 false,
-            );
+            )
+            .disabled(Data::states.map(move |st| get_param(st, index).get_blend_layer().is_some()));
+            widgets::ParamDragNumber::new(cx, Data::states, move |st| {
+                &get_param(st, index).blend_layer
+            });
         })
         .width(Stretch(0.2));
         widgets::WidgetPanel::new(cx, "Global Blend Param", |cx| {
-            // Create a binding to disabled on blend group = None
-            let no_blend = Data::states.map(move |st| {
-                matches!(get_param(st, index).blend_group.value(), BlendGroup::None)
-            });
+            // Create a binding to disabled when the slot isn't blended
+            let no_blend = Data::states.map(move |st| !get_param(st, index).is_blended());
 
-            widgets::ParamKnob::builder()
-                .on_drag_start(|cx| cx.emit(SetDraggingBlend(true)))
-                .on_drag_end(|cx| cx.emit(SetDraggingBlend(false)))
-                .with_label("Time")
-                .build(cx, Data::states, move |st| &st.params.blend_time)
-                .disabled(no_blend);
-            widgets::ParamKnob::builder()
-                .on_drag_start(|cx| cx.emit(SetDraggingBlend(true)))
-                .on_drag_end(|cx| cx.emit(SetDraggingBlend(false)))
-                .with_label("Transition")
-                .build(cx, Data::states, move |st| &st.params.blend_transition)
-                .disabled(no_blend);
+            // The knobs of the synced blend are in beats
+            let sync_binding = Data::states.map(|st| st.params.blend_sync.value());
+            let knobs_disabled = no_blend.clone();
+            Binding::new(cx, sync_binding, move |cx, sync| {
+                let sync = sync.get(cx);
+                widgets::ParamKnob::builder()
+                    .on_drag_start(|cx| cx.emit(SetDraggingBlend(true)))
+                    .on_drag_end(|cx| cx.emit(SetDraggingBlend(false)))
+                    .with_label("Time")
+                    .build(cx, Data::states, move |st| {
+                        if sync {
+                            &st.params.blend_time_beats
+                        } else {
+                            &st.params.blend_time
+                        }
+                    })
+                    .disabled(knobs_disabled.clone());
+                widgets::ParamKnob::builder()
+                    .on_drag_start(|cx| cx.emit(SetDraggingBlend(true)))
+                    .on_drag_end(|cx| cx.emit(SetDraggingBlend(false)))
+                    .with_label("Transition")
+                    .build(cx, Data::states, move |st| {
+                        if sync {
+                            &st.params.blend_transition_beats
+                        } else {
+                            &st.params.blend_transition
+                        }
+                    })
+                    .disabled(knobs_disabled.clone());
+            });
+            VStack::new(cx, |cx| {
+                widgets::ButtonToggle::builder()
+                    .with_text("Sync")
+                    .no_icon()
+                    .build(cx, Data::states, |st| &st.params.blend_sync)
+                    .disabled(no_blend.clone());
+                widgets::ParamDragNumber::new(cx, Data::states, |st| &st.params.blend_curve)
+                    .disabled(no_blend.clone());
+                widgets::ParamDragNumber::new(cx, Data::states, |st| &st.params.blend_points)
+                    .disabled(no_blend);
+            })
+            .row_between(Units::Pixels(4.));
         })
         .width(Stretch(0.3));
     })
@@ -195,6 +769,10 @@ fn create_second_panel_row(cx: &mut Context, index: usize) {
                 .on_drag_start(|cx| cx.emit(SetDraggingAdsr(true)))
                 .on_drag_end(|cx| cx.emit(SetDraggingAdsr(false)))
                 .build(cx, Data::states, move |st| &get_param(st, index).attack);
+            widgets::ParamKnob::builder()
+                .on_drag_start(|cx| cx.emit(SetDraggingAdsr(true)))
+                .on_drag_end(|cx| cx.emit(SetDraggingAdsr(false)))
+                .build(cx, Data::states, move |st| &get_param(st, index).hold);
             widgets::ParamKnob::builder()
                 .on_drag_start(|cx| cx.emit(SetDraggingAdsr(true)))
                 .on_drag_end(|cx| cx.emit(SetDraggingAdsr(false)))
@@ -207,6 +785,10 @@ fn create_second_panel_row(cx: &mut Context, index: usize) {
                 .on_drag_start(|cx| cx.emit(SetDraggingAdsr(true)))
                 .on_drag_end(|cx| cx.emit(SetDraggingAdsr(false)))
                 .build(cx, Data::states, move |st| &get_param(st, index).release);
+            widgets::ParamKnob::builder()
+                .on_drag_start(|cx| cx.emit(SetDraggingAdsr(true)))
+                .on_drag_end(|cx| cx.emit(SetDraggingAdsr(false)))
+                .build(cx, Data::states, move |st| &get_param(st, index).adsr_curve);
         })
         .width(Stretch(0.5));
         widgets::WidgetPanel::new(cx, "Time Control", |cx| {
@@ -215,10 +797,120 @@ fn create_second_panel_row(cx: &mut Context, index: usize) {
                 .build(cx, Data::states, move |st| {
                     &get_param(st, index).start_offset
                 });
+            widgets::ParamKnob::new(cx, Data::states, move |st| &get_param(st, index).trim_end);
+            widgets::ParamKnob::new(cx, Data::states, move |st| &get_param(st, index).fade_in);
+            widgets::ParamKnob::new(cx, Data::states, move |st| &get_param(st, index).fade_out);
+            widgets::ParamKnob::builder()
+                .with_label("Jitter")
+                .build(cx, Data::states, move |st| {
+                    &get_param(st, index).start_jitter
+                });
+            widgets::ParamKnob::builder().with_label("Retrigger").build(
+                cx,
+                Data::states,
+                move |st| &get_param(st, index).retrigger_fade,
+            );
         })
         .width(Stretch(0.25));
+        widgets::WidgetPanel::new(cx, "Loop", |cx| {
+            let no_loop = Data::states.map(move |st| !get_param(st, index).loop_enabled.value());
+
+            widgets::ButtonToggle::builder()
+                .with_text("Loop")
+                .no_icon()
+                .build(cx, Data::states, move |st| {
+                    &get_param(st, index).loop_enabled
+                });
+            widgets::ParamKnob::builder()
+                .with_label("Start")
+                .build(cx, Data::states, move |st| &get_param(st, index).loop_start)
+                .disabled(no_loop.clone());
+            widgets::ParamKnob::builder()
+                .with_label("End")
+                .build(cx, Data::states, move |st| &get_param(st, index).loop_end)
+                .disabled(no_loop.clone());
+            widgets::ParamKnob::builder()
+                .with_label("Fade")
+                .build(cx, Data::states, move |st| {
+                    &get_param(st, index).loop_crossfade
+                })
+                .disabled(no_loop);
+        })
+        .width(Stretch(0.3));
+        widgets::WidgetPanel::new(cx, "Pump", |cx| {
+            widgets::ParamKnob::builder()
+                .with_label("Amount")
+                .build(cx, Data::states, move |st| {
+                    &get_param(st, index).pump_amount
+                });
+            widgets::ParamKnob::builder()
+                .with_label("Time")
+                .build(cx, Data::states, move |st| &get_param(st, index).pump_time);
+        })
+        .width(Stretch(0.2));
+        widgets::WidgetPanel::new(cx, "Pitch Env", |cx| {
+            widgets::ParamKnob::builder()
+                .with_label("Amount")
+                .centered()
+                .build(cx, Data::states, move |st| {
+                    &get_param(st, index).pitch_env_amount
+                });
+            widgets::ParamKnob::builder()
+                .with_label("Time")
+                .build(cx, Data::states, move |st| {
+                    &get_param(st, index).pitch_env_time
+                })
+                .disabled(
+                    Data::states.map(move |st| get_param(st, index).pitch_env_amount.value() == 0.),
+                );
+        })
+        .width(Stretch(0.2));
         widgets::WidgetPanel::new(cx, "Gain", |cx| {
             widgets::ParamKnob::new(cx, Data::states, move |st| &get_param(st, index).gain);
+            customs::level_meter(cx, Data::states.map(move |st| st.get_peak(index)));
+            widgets::ParamKnob::builder()
+                .centered()
+                .build(cx, Data::states, move |st| &get_param(st, index).pan);
+            widgets::ParamKnob::new(cx, Data::states, move |st| &get_param(st, index).send_gain);
+            // Only stereo samples have a width
+            widgets::ParamKnob::new(cx, Data::states, move |st| &get_param(st, index).width)
+                .disabled(Data::states.map(move |st| st.get_num_channels(index) != Some(2)));
+            widgets::ParamKnob::new(cx, Data::states, move |st| {
+                &get_param(st, index).velocity_sensitivity
+            });
+            widgets::ParamKnob::new(cx, Data::states, move |st| {
+                &get_param(st, index).vel_to_attack
+            });
+        })
+        .width(Stretch(0.25));
+        widgets::WidgetPanel::new(cx, "Master", |cx| {
+            VStack::new(cx, |cx| {
+                widgets::ParamKnob::new(cx, Data::states, |st| &st.params.gain);
+                create_learn_button(cx, CcTarget::Gain);
+            });
+            customs::level_meter(
+                cx,
+                Data::states.map(|st| st.master_peak.load(Ordering::Relaxed)),
+            );
+            VStack::new(cx, |cx| {
+                widgets::ParamKnob::new(cx, Data::states, |st| &st.params.drive).disabled(
+                    Data::states.map(|st| st.params.saturation_mode.value() == SaturationMode::Off),
+                );
+                widgets::ParamDragNumber::new(cx, Data::states, |st| &st.params.saturation_mode);
+                widgets::ParamDragNumber::new(cx, Data::states, |st| &st.params.oversampling)
+                    .disabled(
+                        Data::states
+                            .map(|st| st.params.saturation_mode.value() == SaturationMode::Off),
+                    );
+                create_learn_button(cx, CcTarget::Drive);
+            });
+            VStack::new(cx, |cx| {
+                widgets::ParamKnob::new(cx, Data::states, |st| &st.params.ceiling)
+                    .disabled(Data::states.map(|st| !st.params.limiter_enabled.value()));
+                widgets::ButtonToggle::builder()
+                    .no_icon()
+                    .build(cx, Data::states, |st| &st.params.limiter_enabled);
+            });
         })
         .width(Stretch(0.25));
     })
@@ -226,6 +918,34 @@ fn create_second_panel_row(cx: &mut Context, index: usize) {
     .height(Stretch(1.0)); // Equal height distribution
 }
 
+/// MIDI learn of a target, shows the bound CC once learned
+fn create_learn_button(cx: &mut Context, target: CcTarget) {
+    Button::new(
+        cx,
+        move |cx| cx.emit(AppEvent::LearnCc(target)),
+        move |cx| {
+            // Rebuilt when learning ends, the CC is bound by then
+            Binding::new(cx, Data::learning_cc, move |cx, learning| {
+                let text = if learning.get(cx) == Some(target) {
+                    String::from("Move a knob")
+                } else {
+                    let states = Data::states.get(cx);
+                    let cc = states
+                        .params
+                        .cc_mappings
+                        .read()
+                        .ok()
+                        .and_then(|mappings| mappings.get(target));
+                    cc.map_or(String::from("Learn"), |cc| format!("CC {cc}"))
+                };
+                Label::new(cx, &text);
+            });
+        },
+    )
+    .checkable(true)
+    .checked(Data::learning_cc.map(move |learning| *learning == Some(target)));
+}
+
 fn create_sample_info_strip(cx: &mut Context, index: usize) {
     // Get the lens of current file
     let file_path = Data::states.map(move |st| {
@@ -264,6 +984,12 @@ fn create_sample_info_strip(cx: &mut Context, index: usize) {
             .width(Stretch(1.0))
             .class("mute-toggle");
 
+        widgets::ButtonToggle::builder()
+            .no_icon()
+            .with_text("S")
+            .build(cx, Data::states, move |st| &get_param(st, index).soloed)
+            .class("solo-toggle");
+
         // Sample Name
         Label::new(
             cx,
@@ -273,6 +999,49 @@ fn create_sample_info_strip(cx: &mut Context, index: usize) {
         .top(Stretch(1.0))
         .bottom(Stretch(1.0));
 
+        // Length and format of the sample, with the played rate when the sample is resampled
+        let sample_info = Data::states.map(move |st| {
+            let info = st.get_sample_info(index).unwrap_or_default();
+            match (
+                st.get_sample_rate(index),
+                st.get_playback_sample_rate(index),
+            ) {
+                (Some(sample_rate), Some(playback_rate)) if sample_rate as f32 != playback_rate => {
+                    format!(
+                        "{} → {} (resampled)",
+                        info,
+                        utils::format_sample_rate(playback_rate)
+                    )
+                }
+                _ => info,
+            }
+        });
+        Label::new(cx, sample_info)
+            .class("sample-rate")
+            .top(Stretch(1.0))
+            .bottom(Stretch(1.0));
+
+        // The sample is read faster or slower than the host rate, one click resamples it
+        let mismatch = Data::states.map(move |st| st.has_sample_rate_mismatch(index));
+        Button::new(
+            cx,
+            |cx| {
+                let states = Data::states.get(cx);
+                set_param(cx, &states.params.resample_to_host, true);
+            },
+            |cx| {
+                Label::new(
+                    cx,
+                    Data::states.map(|st| {
+                        let host_rate = st.host_sample_rate.load(Ordering::Relaxed);
+                        format!("⚠ Resample to {}", utils::format_sample_rate(host_rate))
+                    }),
+                )
+            },
+        )
+        .class("sample-rate-warning")
+        .visibility(mismatch);
+
         // Btn group
         create_button_group(cx, index, file_path);
     })
@@ -288,20 +1057,15 @@ fn create_button_group(
     file_path: impl Lens<Target = Option<String>>,
 ) {
     HStack::new(cx, |cx| {
-        let next_file = file_path.map(|path| {
-            path.clone()
-                .and_then(|path| utils::get_next_file_in_directory_wrap(&path))
-        });
-        let previous_file = file_path.map(|path| {
-            path.clone()
-                .and_then(|path| utils::get_previous_file_in_directory_wrap(&path))
-        });
+        // The directory is only read on click, see `load_sibling_file`
+        let no_file = file_path.map(|path| path.is_none());
+        let (previous_path, next_path) = (file_path.clone(), file_path.clone());
         Button::new(
             cx,
             move |cx| {
                 cx.spawn(move |proxy: &mut ContextProxy| {
                     let path_opt = rfd::FileDialog::new()
-                        .add_filter("audio", &["wav"])
+                        .add_filter("audio", utils::SUPPORTED_EXTENSIONS)
                         .pick_file();
                     if let Some(path) = path_opt {
                         // We send a message > load audio
@@ -314,23 +1078,35 @@ fn create_button_group(
         Button::new(
             cx,
             move |cx| {
-                if let Some(path) = previous_file.get(cx) {
-                    cx.emit(AppEvent::FileLoading(index, path));
+                if let Some(path) = previous_path.get(cx) {
+                    load_sibling_file(cx, index, path, false);
                 }
             },
             |cx| svg_icon(cx, ICON_ARROW_LEFT, Units::Pixels(16.), 2.),
         )
-        .disabled(previous_file.map(|v| v.is_none()));
+        .disabled(no_file.clone());
         Button::new(
             cx,
             move |cx| {
-                if let Some(path) = next_file.get(cx) {
-                    cx.emit(AppEvent::FileLoading(index, path));
+                if let Some(path) = next_path.get(cx) {
+                    load_sibling_file(cx, index, path, true);
                 }
             },
             |cx| svg_icon(cx, ICON_ARROW_RIGHT, Units::Pixels(16.), 2.),
         )
-        .disabled(next_file.map(|v| v.is_none()));
+        .disabled(no_file.clone());
+        Button::new(
+            cx,
+            move |cx| cx.emit(AppEvent::RevealSample(index)),
+            |cx| Label::new(cx, "🔍"),
+        )
+        .disabled(no_file);
+        Button::new(
+            cx,
+            move |cx| cx.emit(AppEvent::UndoLoad(index)),
+            |cx| Label::new(cx, "↶"),
+        )
+        .disabled(Data::states.map(move |st| !st.can_undo_load(index)));
         Button::new(
             cx,
             move |cx| cx.emit(AppEvent::SampleDeleted(index)),
@@ -388,133 +1164,288 @@ fn create_waveform_section(cx: &mut Context, index: usize) {
 
     // Create a binding so the entire wave isn't always redrawn
     Binding::new(cx, binding_lens, move |cx, new_value| {
-        // The display for waves
-        VStack::new(cx, |cx| {
-            let buffer = Data::states.get(cx).get_buffer_copy(index);
-            if let Some(audio_data) = buffer {
-                ZStack::new(cx, |cx| {
-                    // background canvas
-                    create_grid(cx);
-
-                    // First, we have to know how many frame we wanna display
-                    let bpm = Data::states.get(cx).host_bpm.load(Ordering::Relaxed);
-                    let sr = audio_data.spec.sample_rate as f32;
-                    let (_, start_offset) = new_value.get(cx);
-
-                    // calc sum
-                    let num_frames = customs::get_num_displayed_frames(1., sr, bpm);
-                    let num_channels = audio_data.spec.channels as usize;
-
-                    // Waveform canvas
-                    // TODO
-                    // DO something better here!
-                    let final_data = customs::get_waveform(
-                        &audio_data.data,
-                        num_frames,
-                        num_channels,
-                        0,
-                        start_offset,
-                        sr,
-                    );
+        Binding::new(cx, Data::displayed_beats, move |cx, displayed_beats| {
+            Binding::new(cx, Data::waveform_scroll, move |cx, waveform_scroll| {
+                let view = (displayed_beats.get(cx), waveform_scroll.get(cx));
+                create_waveform_view(cx, index, new_value.get(cx), view);
+            });
+        });
+    });
+}
 
-                    // Make waveform
-                    let disabled_binding =
-                        Data::states.map(move |st| get_param(st, index).muted.value());
-                    Binding::new(cx, disabled_binding, move |cx, disabled| {
-                        let disabled = disabled.get(cx);
-                        widgets::StaticWavePlot::new(cx, final_data.clone())
+/// Loads the file after (or before) `path` in its folder into the slot.
+///
+/// Listing a whole pack can be slow, so the files are read away from the GUI thread.
+fn load_sibling_file(cx: &mut EventContext, index: usize, path: String, forward: bool) {
+    let recursive = Data::states.get(cx).params.recursive_browse.value();
+    cx.spawn(move |proxy: &mut ContextProxy| {
+        let sibling = if forward {
+            utils::get_next_file_in_directory_wrap(&path, recursive)
+        } else {
+            utils::get_previous_file_in_directory_wrap(&path, recursive)
+        };
+        if let Some(sibling) = sibling {
+            let _ = proxy.emit(AppEvent::FileLoading(index, sibling));
+        }
+    });
+}
+
+/// The waveform and its overlays, `view` is the number of beats displayed
+/// and the scroll from the start offset (in beats)
+fn create_waveform_view(
+    cx: &mut Context,
+    index: usize,
+    (_, start_offset): (Option<String>, f32),
+    (displayed_beats, waveform_scroll): (f32, f32),
+) {
+    // The display for waves
+    VStack::new(cx, |cx| {
+        let buffer = Data::states.get(cx).get_buffer_copy(index);
+        if let Some(audio_data) = buffer {
+            ZStack::new(cx, |cx| {
+                // background canvas
+                create_grid(cx);
+
+                // First, we have to know how many frame we wanna display
+                let bpm = Data::states.get(cx).host_bpm.load(Ordering::Relaxed);
+                let sr = audio_data.spec.sample_rate as f32;
+
+                // calc sum
+                // At least two frames, a short view at a high bpm could round down to 0
+                let num_frames = utils::get_num_displayed_frames(displayed_beats, sr, bpm).max(2);
+                // Negative before the start offset
+                let scroll_frames = utils::get_num_displayed_frames(waveform_scroll.abs(), sr, bpm)
+                    as i32
+                    * waveform_scroll.signum() as i32;
+                let num_channels = audio_data.spec.channels as usize;
+
+                // Waveform canvas, the preview is enough unless zoomed in
+                let final_data = audio_data
+                    .preview
+                    .as_ref()
+                    .and_then(|preview| {
+                        customs::get_preview_waveform(
+                            preview,
+                            num_frames,
+                            start_offset,
+                            scroll_frames,
+                            sr,
+                        )
+                    })
+                    .unwrap_or_else(|| {
+                        customs::get_waveform(
+                            &audio_data.data,
+                            num_frames,
+                            num_channels,
+                            0,
+                            start_offset,
+                            scroll_frames,
+                            sr,
+                        )
+                    });
+
+                // Make waveform, the plot draws the points as they are
+                let rms = customs::get_rms_envelope(&final_data);
+                let final_data = utils::downsample_lttb(&final_data, tasks::PREVIEW_POINTS);
+                let disabled_binding =
+                    Data::states.map(move |st| get_param(st, index).muted.value());
+                let rms_binding = Data::states.map(move |st| get_param(st, index).show_rms.value());
+                Binding::new(cx, disabled_binding, move |cx, disabled| {
+                    let disabled = disabled.get(cx);
+                    let final_data = final_data.clone();
+                    let rms = rms.clone();
+                    Binding::new(cx, rms_binding.clone(), move |cx, show_rms| {
+                        let rms = show_rms.get(cx).then(|| rms.clone());
+                        widgets::StaticWavePlot::new(cx, final_data.clone(), rms)
                             .disabled(disabled)
                             .class("waveform-canvas");
                     });
+                });
+
+                // Trimmed tail
+                let total_frames = audio_data.data.len() / num_channels.max(1);
+                customs::trim_end_overlay(
+                    cx,
+                    Data::states.map(move |st| {
+                        let end_frame =
+                            total_frames as f32 - get_param(st, index).trim_end.value() * sr;
+                        (end_frame - start_offset * sr - scroll_frames as f32) / num_frames as f32
+                    }),
+                );
+
+                // Start offset, the view starts there when not scrolled
+                customs::start_marker(
+                    cx,
+                    Data::states.map(move |_| -(scroll_frames as f32) / num_frames as f32),
+                );
 
-                    // Time indicator
-                    customs::neon_indicator(
+                // Time indicator, the positions are only published once per block
+                // so the smoothing moves it between the blocks
+                let interpolator = Mutex::new(utils::PositionInterpolator::default());
+                customs::neon_indicator(
+                    cx,
+                    Data::states.map(move |st| {
+                        let mut progress = st.positions[index].load(Ordering::Relaxed);
+                        if get_param(st, index).smooth_indicator.value() {
+                            if let Ok(mut interpolator) = interpolator.lock() {
+                                progress = interpolator.position(
+                                    progress,
+                                    st.get_block_duration(),
+                                    Instant::now(),
+                                );
+                            }
+                        }
+                        (progress * utils::DISPLAYED_BEATS - waveform_scroll) / displayed_beats
+                    }),
+                )
+                .visibility(
+                    Data::states.map(move |st| get_param(st, index).show_indicator.value()),
+                );
+
+                // Blend indicator
+                let visibility_binding_blend = Data::is_dragging_blend
+                    .map(|v| *v)
+                    .or(Data::states.map(move |st| get_param(st, index).show_blend.value()))
+                    .and(Data::states.map(move |st| get_param(st, index).is_blended()));
+
+                // Adsr
+                let attack = Data::states
+                    .map(move |st| get_param(st, index).attack.value() * sr / num_frames as f32);
+                let hold = Data::states
+                    .map(move |st| get_param(st, index).hold.value() * sr / num_frames as f32);
+                let decay = Data::states
+                    .map(move |st| get_param(st, index).decay.value() * sr / num_frames as f32);
+                let release = Data::states
+                    .map(move |st| get_param(st, index).release.value() * sr / num_frames as f32);
+                let sustain = Data::states.map(move |st| get_param(st, index).sustain.value());
+                let curve = Data::states.map(move |st| get_param(st, index).adsr_curve.value());
+
+                let visibility_binding_adsr = Data::is_dragging_adsr
+                    .map(|v| *v)
+                    .or(Data::states.map(move |st| get_param(st, index).show_adsr.value()));
+                let origin = Data::states.map(move |_| -(scroll_frames as f32) / num_frames as f32);
+                customs::adsr::AdsrVizualizer::new(
+                    cx, origin, attack, hold, decay, sustain, release, curve,
+                )
+                .visibility(visibility_binding_adsr);
+
+                // Wheel to zoom, drag to scroll and click to move the start offset there,
+                // under the buttons so they stay clickable
+                let states = Data::states.get(cx);
+                customs::navigator::WaveformNavigator::new(cx, move |cx, x| {
+                    let offset = (scroll_frames as f32 + x * num_frames as f32) / sr;
+                    set_param(
                         cx,
-                        Data::states.map(move |st| {
-                            st.positions[index].load(Ordering::Relaxed) as f32 / num_frames as f32
-                        }),
-                    )
-                    .visibility(
-                        Data::states.map(move |st| get_param(st, index).show_indicator.value()),
+                        &get_param(&states, index).start_offset,
+                        start_offset + offset,
                     );
+                });
 
-                    // Blend indicator
-                    let blend_time = Data::states
-                        .map(move |st| st.params.blend_time.value() * sr / num_frames as f32);
-                    let blend_transition = Data::states
-                        .map(move |st| st.params.blend_transition.value() * sr / num_frames as f32);
-                    let visibility_binding_blend = Data::is_dragging_blend
-                        .map(|v| *v)
-                        .or(Data::states.map(move |st| get_param(st, index).show_blend.value()))
-                        .and(Data::states.map(move |st| {
-                            !matches!(get_param(st, index).blend_group.value(), BlendGroup::None)
-                        }));
-                    customs::blend::BlendVizualizer::new(cx, blend_time, blend_transition)
-                        .visibility(visibility_binding_blend);
-
-                    // Adsr
-                    let attack = Data::states.map(move |st| {
-                        get_param(st, index).attack.value() * sr / num_frames as f32
+                // Above the navigator so its handles can be dragged.
+                // Only the layers use the blend points after the first one
+                for point in 0..MAX_BLEND_POINTS {
+                    let blend_time = Data::states.map(move |st| {
+                        let (blend_time, _) = st.params.get_blend_point_seconds(point, bpm);
+                        (blend_time * sr - scroll_frames as f32) / num_frames as f32
                     });
-                    let decay = Data::states
-                        .map(move |st| get_param(st, index).decay.value() * sr / num_frames as f32);
-                    let release = Data::states.map(move |st| {
-                        get_param(st, index).release.value() * sr / num_frames as f32
+                    let blend_transition = Data::states.map(move |st| {
+                        let (_, blend_transition) = st.params.get_blend_point_seconds(point, bpm);
+                        blend_transition * sr / num_frames as f32
                     });
-                    let sustain = Data::states.map(move |st| get_param(st, index).sustain.value());
-
-                    let visibility_binding_adsr = Data::is_dragging_adsr
-                        .map(|v| *v)
-                        .or(Data::states.map(move |st| get_param(st, index).show_adsr.value()));
-                    customs::adsr::AdsrVizualizer::new(cx, attack, decay, sustain, release)
-                        .visibility(visibility_binding_adsr);
-
-                    // A Container that has button !
-                    HStack::new(cx, |cx| {
-                        widgets::ButtonToggle::builder()
-                            .with_text("")
-                            .with_icon(ICON_TIME_INDICATOR)
-                            .icon_size(Units::Pixels(16.))
-                            .icon_stroke_width(2.0)
-                            .build(cx, Data::states, move |st| {
-                                &get_param(st, index).show_indicator
-                            })
-                            .width(Auto)
-                            .height(Auto)
-                            .class("indicator-toggle");
-                        widgets::ButtonToggle::builder()
-                            .with_text("")
-                            .with_icon(ICON_BLEND)
-                            .icon_size(Units::Pixels(16.))
-                            .icon_stroke_width(2.0)
-                            .build(cx, Data::states, move |st| &get_param(st, index).show_blend)
-                            .width(Auto)
-                            .height(Auto)
-                            .class("indicator-toggle");
-                        widgets::ButtonToggle::builder()
-                            .with_text("")
-                            .with_icon(ICON_ADSR)
-                            .icon_size(Units::Pixels(16.))
-                            .icon_stroke_width(2.0)
-                            .build(cx, Data::states, move |st| &get_param(st, index).show_adsr)
-                            .width(Auto)
-                            .height(Auto)
-                            .class("indicator-toggle");
-                    })
-                    .col_between(Pixels(8.))
-                    .left(Stretch(1.0))
-                    .top(Pixels(PANEL_PADDING))
-                    .right(Pixels(PANEL_PADDING))
-                    .width(Auto)
-                    .height(Auto)
-                    .class("widget-panel")
-                    .class("button-group");
-                });
-            }
-        })
-        .class("waveform-vizualizer")
-        .height(Stretch(1.0));
-    });
+                    let point_used = Data::states.map(move |st| {
+                        point == 0
+                            || (point < st.params.blend_points.value() as usize
+                                && get_param(st, index).get_blend_layer().is_some())
+                    });
+                    customs::blend::BlendVizualizer::new(
+                        cx,
+                        blend_time,
+                        blend_transition,
+                        Data::states.get(cx).params.clone(),
+                        point,
+                        (scroll_frames as f32 / sr, num_frames as f32 / sr),
+                        bpm,
+                    )
+                    .visibility(visibility_binding_blend.clone().and(point_used));
+                }
+
+                // Spectrum, it covers the waveform and its overlays
+                if let Some(spectrum) = audio_data.spectrum.clone() {
+                    customs::spectrum::SpectrumView::new(cx, spectrum, sr).visibility(
+                        Data::states.map(move |st| get_param(st, index).show_spectrum.value()),
+                    );
+                }
+
+                // A Container that has button !
+                HStack::new(cx, |cx| {
+                    widgets::ButtonToggle::builder()
+                        .with_text("")
+                        .with_icon(ICON_TIME_INDICATOR)
+                        .icon_size(Units::Pixels(16.))
+                        .icon_stroke_width(2.0)
+                        .build(cx, Data::states, move |st| {
+                            &get_param(st, index).show_indicator
+                        })
+                        .width(Auto)
+                        .height(Auto)
+                        .class("indicator-toggle");
+                    widgets::ButtonToggle::builder()
+                        .with_text("Smooth")
+                        .no_icon()
+                        .build(cx, Data::states, move |st| {
+                            &get_param(st, index).smooth_indicator
+                        })
+                        .width(Auto)
+                        .height(Auto)
+                        .class("indicator-toggle");
+                    widgets::ButtonToggle::builder()
+                        .with_text("")
+                        .with_icon(ICON_BLEND)
+                        .icon_size(Units::Pixels(16.))
+                        .icon_stroke_width(2.0)
+                        .build(cx, Data::states, move |st| &get_param(st, index).show_blend)
+                        .width(Auto)
+                        .height(Auto)
+                        .class("indicator-toggle");
+                    widgets::ButtonToggle::builder()
+                        .with_text("")
+                        .with_icon(ICON_ADSR)
+                        .icon_size(Units::Pixels(16.))
+                        .icon_stroke_width(2.0)
+                        .build(cx, Data::states, move |st| &get_param(st, index).show_adsr)
+                        .width(Auto)
+                        .height(Auto)
+                        .class("indicator-toggle");
+                    widgets::ButtonToggle::builder()
+                        .with_text("RMS")
+                        .no_icon()
+                        .build(cx, Data::states, move |st| &get_param(st, index).show_rms)
+                        .width(Auto)
+                        .height(Auto)
+                        .class("indicator-toggle");
+                    widgets::ButtonToggle::builder()
+                        .with_text("FFT")
+                        .no_icon()
+                        .build(cx, Data::states, move |st| {
+                            &get_param(st, index).show_spectrum
+                        })
+                        .width(Auto)
+                        .height(Auto)
+                        .class("indicator-toggle");
+                })
+                .col_between(Pixels(8.))
+                .left(Stretch(1.0))
+                .top(Pixels(PANEL_PADDING))
+                .right(Pixels(PANEL_PADDING))
+                .width(Auto)
+                .height(Auto)
+                .class("widget-panel")
+                .class("button-group");
+            });
+        }
+    })
+    .class("waveform-vizualizer")
+    .height(Stretch(1.0));
 }
 
 fn create_third_panel_row(cx: &mut Context, index: usize) {
@@ -543,6 +1474,39 @@ fn create_parameter_panels(cx: &mut Context) {
     });
 }
 
+fn create_mini_panels(cx: &mut Context) {
+    // Gain, pitch and envelope of the selected slot only
+    Binding::new(cx, Data::selected_sample, |cx, selected_idx| {
+        let index = selected_idx.get(cx);
+
+        HStack::new(cx, |cx| {
+            widgets::WidgetPanel::new(cx, "Gain", |cx| {
+                widgets::ParamKnob::new(cx, Data::states, move |st| &get_param(st, index).gain);
+            })
+            .width(Stretch(0.2));
+            widgets::WidgetPanel::new(cx, "Pitch", |cx| {
+                widgets::ParamDragNumber::new(cx, Data::states, move |st| {
+                    &get_param(st, index).semitone_offset
+                });
+                widgets::ParamKnob::builder()
+                    .centered()
+                    .with_label("Cents")
+                    .build(cx, Data::states, move |st| &get_param(st, index).fine_tune);
+            })
+            .width(Stretch(0.2));
+            widgets::WidgetPanel::new(cx, "ADSR", |cx| {
+                widgets::ParamKnob::new(cx, Data::states, move |st| &get_param(st, index).attack);
+                widgets::ParamKnob::new(cx, Data::states, move |st| &get_param(st, index).decay);
+                widgets::ParamKnob::new(cx, Data::states, move |st| &get_param(st, index).sustain);
+                widgets::ParamKnob::new(cx, Data::states, move |st| &get_param(st, index).release);
+            })
+            .width(Stretch(0.6));
+        })
+        .col_between(Units::Pixels(PANEL_SPACING))
+        .height(Stretch(1.0));
+    });
+}
+
 struct CssString(String);
 
 impl IntoCssStr for CssString {
@@ -576,13 +1540,42 @@ pub fn create_editor(
                 executor: async_executor.clone(),
                 is_dragging_blend: false,
                 is_dragging_adsr: false,
+                confirm_clear_all: false,
+                alignment_tick: states.alignment_tick.load(Ordering::Relaxed),
+                copied_slot: None,
+                slot_menu: None,
+                drop_slots: utils::DropSlots::default(),
+                learning_cc: None,
+                displayed_beats: utils::DISPLAYED_BEATS,
+                waveform_scroll: 0.,
             }
             .build(cx);
 
+            // The CC values heard by the audio thread are only set to the params here, through
+            // the host. The loop stops once the editor is closed and the events can't be sent anymore
+            cx.spawn(|proxy: &mut ContextProxy| loop {
+                std::thread::sleep(MIDI_CC_POLL_INTERVAL);
+                if proxy.emit(AppEvent::PollMidiCc).is_err() {
+                    break;
+                }
+            });
+
             VStack::new(cx, |cx| {
                 create_title_section(cx);
-                create_sample_tabs(cx);
-                create_parameter_panels(cx);
+
+                // The view tree is rebuilt when switching between mini and full editor
+                Binding::new(
+                    cx,
+                    Data::states.map(|st| st.params.mini_editor.value()),
+                    |cx, mini| {
+                        if mini.get(cx) {
+                            create_mini_panels(cx);
+                        } else {
+                            create_sample_tabs(cx);
+                            create_parameter_panels(cx);
+                        }
+                    },
+                );
             })
             .child_space(Pixels(MAIN_PADDING));
         },
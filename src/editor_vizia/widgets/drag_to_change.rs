@@ -2,6 +2,8 @@ use nih_plug::prelude::*;
 use nih_plug_vizia::vizia::prelude::*;
 use nih_plug_vizia::widgets::param_base::ParamWidgetBase;
 
+use super::widget_base::ResetEvent;
+
 #[derive(Lens)]
 pub struct ParamDragNumber {
     param_base: ParamWidgetBase,
@@ -47,8 +49,6 @@ impl ParamDragNumber {
     }
 }
 
-struct ResetEvent;
-
 impl View for ParamDragNumber {
     fn element(&self) -> Option<&'static str> {
         Some("param-drag-number")
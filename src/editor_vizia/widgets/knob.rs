@@ -6,6 +6,12 @@ use nih_plug_vizia::widgets::param_base::ParamWidgetBase;
 
 use super::widget_base::*;
 
+/// Normalized step of a mouse wheel notch for continuous params
+const SCROLL_STEP: f32 = 0.01;
+
+/// Normalized step of a mouse wheel notch while holding shift
+const FINE_SCROLL_STEP: f32 = 0.001;
+
 #[derive(Clone, Default)]
 pub struct ParamKnobBuilder {
     centered: bool,
@@ -111,7 +117,8 @@ impl ParamWidget for ParamKnob {
                     )
                     .on_changing(|cx, val| {
                         cx.emit(NormalizedParamUpdate(val));
-                    });
+                    })
+                    .on_double_click(|cx, _| cx.emit(ResetEvent));
 
                     if let Some(on_drag) = modifiers.on_drag_start {
                         knob = knob.on_mouse_down(move |cx, _| on_drag(cx))
@@ -145,5 +152,29 @@ impl View for ParamKnob {
 
     fn event(&mut self, cx: &mut EventContext, event: &mut Event) {
         self.handle_param_event(cx, event);
+
+        event.map(|window_event: &WindowEvent, meta| {
+            if let WindowEvent::MouseScroll(_, y) = window_event {
+                if *y != 0.0 {
+                    // Shift for fine tuning, else one step of the param
+                    let step_size = if cx.modifiers().contains(Modifiers::SHIFT) {
+                        FINE_SCROLL_STEP
+                    } else if let Some(step_count) = self.param_base.step_count() {
+                        1.0 / step_count as f32
+                    } else {
+                        SCROLL_STEP
+                    };
+
+                    // Scroll up = positive y = increase value
+                    let current_value = self.param_base.unmodulated_normalized_value();
+                    let new_value = (current_value + step_size * y.signum()).clamp(0.0, 1.0);
+
+                    self.param_base.begin_set_parameter(cx);
+                    self.param_base.set_normalized_value(cx, new_value);
+                    self.param_base.end_set_parameter(cx);
+                    meta.consume();
+                }
+            }
+        });
     }
 }
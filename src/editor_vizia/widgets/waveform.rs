@@ -1,11 +1,8 @@
 use std::cell::RefCell;
 
 use derive_more::Constructor;
-use nih_plug::nih_log;
 use nih_plug_vizia::vizia::{prelude::*, vg};
 
-use crate::utils;
-
 /// Mini struct to easy normalize the vlaues
 #[derive(Debug, Constructor)]
 struct Normalizer {
@@ -30,34 +27,67 @@ impl Normalizer {
     fn normalize(&self, x: f32, y: f32) -> (f32, f32) {
         (self.normalize_x(x), self.normalize_y(y))
     }
-
-    #[inline]
-    fn get_width(&self) -> f32 {
-        self.width
-    }
 }
 
+/// Plots a waveform, the points are drawn as they are so they should
+/// already be downsampled to the detail the view needs
 pub struct StaticWavePlot {
     data: Vec<[f32; 2]>,
+
+    /// RMS envelope drawn behind the waveform, mirrored around 0
+    rms: Option<Vec<[f32; 2]>>,
     cached_texture: RefCell<Option<vg::ImageId>>,
 }
 
 impl StaticWavePlot {
-    pub fn new(cx: &mut Context, data: Vec<[f32; 2]>) -> Handle<Self> {
+    pub fn new(cx: &mut Context, data: Vec<[f32; 2]>, rms: Option<Vec<[f32; 2]>>) -> Handle<Self> {
         Self {
             data,
+            rms,
             cached_texture: RefCell::new(None),
         }
         .build(cx, |_| {})
     }
 
+    /// Closed path going along the envelope on top and back along its mirror
+    fn build_rms_path(rms: &[[f32; 2]], normalizer: &Normalizer) -> vg::Path {
+        let mut path = vg::Path::new();
+        let mut iterator = rms.iter();
+
+        if let Some(&[x, y]) = iterator.next() {
+            let (x, y) = normalizer.normalize(x, y);
+            path.move_to(x, y);
+        }
+
+        for &[x, y] in iterator {
+            let (x, y) = normalizer.normalize(x, y);
+            path.line_to(x, y);
+        }
+
+        for &[x, y] in rms.iter().rev() {
+            let (x, y) = normalizer.normalize(x, -y);
+            path.line_to(x, y);
+        }
+
+        path.close();
+        path
+    }
+
+    /// Fills the RMS envelope, if any, with a translucent font color
+    fn fill_rms(&self, cx: &DrawContext, canvas: &mut Canvas, normalizer: &Normalizer) {
+        if let Some(rms) = &self.rms {
+            let color = cx.font_color();
+            canvas.fill_path(
+                &Self::build_rms_path(rms, normalizer),
+                &vg::Paint::color(Color::rgba(color.r(), color.g(), color.b(), 70).into()),
+            );
+        }
+    }
+
     fn build_waveform_path(&self, normalizer: &Normalizer) -> vg::Path {
         let mut path = vg::Path::new();
 
-        // Downsample and create path
-        let num_points = normalizer.get_width() * 4.0;
-        let downsampled = utils::downsample_lttb(&self.data, num_points as usize);
-        let mut iterator = downsampled.iter();
+        let mut iterator = self.data.iter();
 
         if let Some(&[mut x, mut y]) = iterator.next() {
             (x, y) = normalizer.normalize(x, y);
@@ -69,11 +99,6 @@ impl StaticWavePlot {
             path.line_to(x, y);
         }
 
-        nih_log!(
-            "Path created! Ratio {}",
-            100.0 * (1. - downsampled.len() as f32 / self.data.len() as f32)
-        );
-
         path
     }
 
@@ -95,6 +120,7 @@ impl StaticWavePlot {
 
         // Build and stroke path to texture (expensive, but only once!)
         let normalizer = Normalizer::new(0.0, 0.0, w, h / 2.0);
+        self.fill_rms(cx, canvas, &normalizer);
         canvas.stroke_path(
             &self.build_waveform_path(&normalizer),
             &vg::Paint::color(cx.font_color().into())
@@ -149,7 +175,8 @@ impl View for StaticWavePlot {
 
         let normalizer = Normalizer::new(x_bound, y_bound, width, half_height);
 
-        // Waveform
+        // Waveform, above its envelope
+        self.fill_rms(cx, canvas, &normalizer);
         canvas.stroke_path(
             &self.build_waveform_path(&normalizer),
             &vg::Paint::color(cx.font_color().into())
@@ -161,6 +161,13 @@ pub trait ParamWidget: Sized {
             self.param_base().end_set_parameter(cx);
             meta.consume();
         });
+        event.map(|_reset_event: &ResetEvent, meta| {
+            let default = self.param_base().default_normalized_value();
+            self.param_base().begin_set_parameter(cx);
+            self.param_base().set_normalized_value(cx, default);
+            self.param_base().end_set_parameter(cx);
+            meta.consume();
+        });
     }
 
     /// Creates a parameter widget from a builder configuration.
@@ -209,3 +216,9 @@ pub trait ParamWidget: Sized {
 /// ```
 #[derive(Debug, Clone, Copy)]
 pub struct NormalizedParamUpdate(pub f32);
+
+/// Event sent to set a parameter widget back to its default value, on a double click.
+///
+/// Handled by `ParamWidget::handle_param_event` like `NormalizedParamUpdate`.
+#[derive(Debug, Clone, Copy)]
+pub struct ResetEvent;
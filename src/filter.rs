@@ -0,0 +1,153 @@
+use nih_plug::prelude::Enum;
+
+use crate::pitch_shift::FrameOutput;
+
+/// Damping of the filter, a butterworth response (1 / Q with Q = 0.707)
+const BUTTERWORTH_DAMPING: f32 = std::f32::consts::SQRT_2;
+
+#[derive(Debug, PartialEq, Clone, Copy, Enum)]
+pub enum FilterMode {
+    Off,
+    #[name = "Low Pass"]
+    LowPass,
+    #[name = "High Pass"]
+    HighPass,
+}
+
+/// A 12dB/oct state variable filter (trapezoidal integration).
+///
+/// It keeps its state across blocks, one state per channel for mono and stereo samples.
+#[derive(Debug, Clone, Copy)]
+pub struct StateVariableFilter {
+    mode: FilterMode,
+
+    // Coefficients
+    a1: f32,
+    a2: f32,
+    a3: f32,
+
+    // Integrators state, per channel
+    ic1eq: [f32; 2],
+    ic2eq: [f32; 2],
+}
+
+impl Default for StateVariableFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StateVariableFilter {
+    pub fn new() -> Self {
+        Self {
+            mode: FilterMode::Off,
+            a1: 1.,
+            a2: 0.,
+            a3: 0.,
+            ic1eq: [0.; 2],
+            ic2eq: [0.; 2],
+        }
+    }
+
+    /// Clears the filter state, the settings are kept
+    pub fn reset(&mut self) {
+        self.ic1eq = [0.; 2];
+        self.ic2eq = [0.; 2];
+    }
+
+    /// Updates the filter settings.
+    ///
+    /// The cutoff is clamped below nyquist, the state is kept so it can be called every block.
+    pub fn set(&mut self, mode: FilterMode, cutoff: f32, sample_rate: f32) {
+        self.mode = mode;
+        if mode == FilterMode::Off {
+            return;
+        }
+
+        let cutoff = cutoff.clamp(1., sample_rate * 0.49);
+        let g = (std::f32::consts::PI * cutoff / sample_rate).tan();
+        self.a1 = 1. / (1. + g * (g + BUTTERWORTH_DAMPING));
+        self.a2 = g * self.a1;
+        self.a3 = g * self.a2;
+    }
+
+    /// Filters a single sample of a channel
+    #[inline]
+    fn process_sample(&mut self, channel: usize, v0: f32) -> f32 {
+        let v3 = v0 - self.ic2eq[channel];
+        let v1 = self.a1 * self.ic1eq[channel] + self.a2 * v3;
+        let v2 = self.ic2eq[channel] + self.a2 * self.ic1eq[channel] + self.a3 * v3;
+        self.ic1eq[channel] = 2. * v1 - self.ic1eq[channel];
+        self.ic2eq[channel] = 2. * v2 - self.ic2eq[channel];
+
+        match self.mode {
+            FilterMode::LowPass => v2,
+            FilterMode::HighPass => v0 - BUTTERWORTH_DAMPING * v1 - v2,
+            FilterMode::Off => v0,
+        }
+    }
+
+    /// Filters a frame of the shifter output
+    #[inline]
+    pub fn process(&mut self, frame: FrameOutput) -> FrameOutput {
+        if self.mode == FilterMode::Off {
+            return frame;
+        }
+
+        match frame {
+            FrameOutput::Mono(v) => FrameOutput::Mono(self.process_sample(0, v)),
+            FrameOutput::Stereo([left, right]) => {
+                FrameOutput::Stereo([self.process_sample(0, left), self.process_sample(1, right)])
+            }
+            FrameOutput::Unsupported => FrameOutput::Unsupported,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(filter: &mut StateVariableFilter, input: f32, samples: usize) -> f32 {
+        let mut output = 0.;
+        for _ in 0..samples {
+            if let FrameOutput::Mono(v) = filter.process(FrameOutput::Mono(input)) {
+                output = v;
+            }
+        }
+        output
+    }
+
+    #[test]
+    fn test_off_is_transparent() {
+        let mut filter = StateVariableFilter::new();
+        filter.set(FilterMode::Off, 100., 48000.);
+        assert_eq!(
+            filter.process(FrameOutput::Stereo([0.3, -0.7])),
+            FrameOutput::Stereo([0.3, -0.7])
+        );
+    }
+
+    #[test]
+    fn test_low_pass_keeps_dc() {
+        let mut filter = StateVariableFilter::new();
+        filter.set(FilterMode::LowPass, 1000., 48000.);
+        assert!((run(&mut filter, 1., 4800) - 1.).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_high_pass_removes_dc() {
+        let mut filter = StateVariableFilter::new();
+        filter.set(FilterMode::HighPass, 1000., 48000.);
+        assert!(run(&mut filter, 1., 4800).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_reset_clears_state() {
+        let mut filter = StateVariableFilter::new();
+        filter.set(FilterMode::LowPass, 1000., 48000.);
+        run(&mut filter, 1., 100);
+        filter.reset();
+        assert_eq!(run(&mut filter, 0., 1), 0.);
+    }
+}
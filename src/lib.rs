@@ -1,10 +1,15 @@
 mod adsr;
 // mod editor;
 mod editor_vizia;
+mod filter;
+mod limiter;
+mod midi_learn;
 mod params;
 mod pitch_shift;
 mod plugin;
+mod presets;
 mod sample_wrapper;
+mod saturation;
 mod shared_states;
 mod tasks;
 mod utils;
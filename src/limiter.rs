@@ -0,0 +1,106 @@
+/// Time for the gain to recover after a peak, in ms
+const RELEASE_MS: f32 = 50.;
+
+/// Channels with their own gain, the next ones share the last gain
+const MAX_CHANNELS: usize = 2;
+
+/// A brickwall peak limiter without look-ahead.
+///
+/// The gain drops instantly on a peak over the ceiling, so nothing gets past it,
+/// then recovers exponentially. Each channel keeps its own gain.
+#[derive(Debug, Clone, Copy)]
+pub struct Limiter {
+    release_coeff: f32,
+    gain: [f32; MAX_CHANNELS],
+}
+
+impl Default for Limiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Limiter {
+    pub fn new() -> Self {
+        let mut limiter = Self {
+            release_coeff: 0.,
+            gain: [1.; MAX_CHANNELS],
+        };
+        limiter.set_sample_rate(48000.);
+        limiter
+    }
+
+    /// Updates the release for a new sample rate, the gain is kept
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        let release_samples = RELEASE_MS * 0.001 * sample_rate;
+        self.release_coeff = (-1. / release_samples.max(1.)).exp();
+    }
+
+    /// Clears the gain reduction
+    pub fn reset(&mut self) {
+        self.gain = [1.; MAX_CHANNELS];
+    }
+
+    /// Limits a single sample of a channel, the output never goes over `ceiling`
+    #[inline]
+    pub fn process_sample(&mut self, channel: usize, ceiling: f32, x: f32) -> f32 {
+        let peak = x.abs();
+        let target = if peak > ceiling { ceiling / peak } else { 1. };
+
+        // Instant attack, the release only ever moves the gain towards its target
+        let gain = &mut self.gain[channel.min(MAX_CHANNELS - 1)];
+        if target < *gain {
+            *gain = target;
+        } else {
+            *gain = target + (*gain - target) * self.release_coeff;
+        }
+        x * *gain
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_below_ceiling_is_transparent() {
+        let mut limiter = Limiter::new();
+        for x in [-0.9, -0.2, 0., 0.5, 0.99] {
+            assert_eq!(limiter.process_sample(0, 1., x), x);
+        }
+    }
+
+    #[test]
+    fn test_never_exceeds_ceiling() {
+        let mut limiter = Limiter::new();
+        for i in 0..4800 {
+            let x = (i as f32 * 0.05).sin() * 4.;
+            assert!(limiter.process_sample(0, 0.5, x).abs() <= 0.5 + 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_gain_recovers_after_peak() {
+        let mut limiter = Limiter::new();
+        limiter.set_sample_rate(48000.);
+        limiter.process_sample(0, 1., 2.);
+        assert_eq!(limiter.gain[0], 0.5);
+
+        // Five time constants later the gain is almost back
+        for _ in 0..(5. * RELEASE_MS * 48.) as usize {
+            limiter.process_sample(0, 1., 0.);
+        }
+        assert!(limiter.gain[0] > 0.99);
+    }
+
+    #[test]
+    fn test_channels_are_independent() {
+        let mut limiter = Limiter::new();
+        limiter.process_sample(0, 1., 4.);
+        assert_eq!(limiter.gain[0], 0.25);
+        assert_eq!(limiter.gain[1], 1.);
+
+        limiter.reset();
+        assert_eq!(limiter.gain[0], 1.);
+    }
+}
@@ -0,0 +1,148 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use nih_plug::prelude::AtomicF32;
+use serde::{Deserialize, Serialize};
+
+/// Params a MIDI CC can be bound to
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum CcTarget {
+    Gain,
+    Drive,
+}
+
+impl CcTarget {
+    pub const ALL: [CcTarget; 2] = [CcTarget::Gain, CcTarget::Drive];
+
+    fn index(self) -> usize {
+        match self {
+            CcTarget::Gain => 0,
+            CcTarget::Drive => 1,
+        }
+    }
+}
+
+/// The CC number bound to each target, persisted with the plugin state
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CcMappings {
+    ccs: [Option<u8>; CcTarget::ALL.len()],
+}
+
+impl CcMappings {
+    /// Returns the CC bound to the target, if any
+    pub fn get(&self, target: CcTarget) -> Option<u8> {
+        self.ccs[target.index()]
+    }
+
+    /// Binds a CC to the target, a CC controls a single target
+    /// so it is removed from any other one
+    pub fn bind(&mut self, target: CcTarget, cc: u8) {
+        for bound in self.ccs.iter_mut() {
+            if *bound == Some(cc) {
+                *bound = None;
+            }
+        }
+        self.ccs[target.index()] = Some(cc);
+    }
+
+    /// Returns the target controlled by the CC, if any
+    pub fn target_of(&self, cc: u8) -> Option<CcTarget> {
+        CcTarget::ALL
+            .into_iter()
+            .find(|target| self.get(*target) == Some(cc))
+    }
+}
+
+/// MIDI learn state shared between the audio thread and the GUI.
+///
+/// The audio thread leaves the CC values here and the GUI sets them to the params
+/// through the host, so a CC only moves its param while the editor is open.
+#[derive(Debug)]
+pub struct MidiLearn {
+    /// The target waiting for a CC, 0 when not learning, else its index + 1
+    learning: AtomicU8,
+
+    /// Last CC value received for each target, NaN once applied
+    pending: [AtomicF32; CcTarget::ALL.len()],
+}
+
+impl Default for MidiLearn {
+    fn default() -> Self {
+        Self {
+            learning: AtomicU8::new(0),
+            pending: std::array::from_fn(|_| AtomicF32::new(f32::NAN)),
+        }
+    }
+}
+
+impl MidiLearn {
+    /// Starts waiting for a CC to bind to the target, `None` cancels it
+    pub fn set_learning(&self, target: Option<CcTarget>) {
+        let value = target.map_or(0, |target| target.index() as u8 + 1);
+        self.learning.store(value, Ordering::Relaxed);
+    }
+
+    /// Returns the target waiting for a CC
+    pub fn learning(&self) -> Option<CcTarget> {
+        let value = self.learning.load(Ordering::Relaxed) as usize;
+        value.checked_sub(1).map(|index| CcTarget::ALL[index])
+    }
+
+    /// Stops learning once a CC is bound to the target,
+    /// unless another target started learning meanwhile
+    pub fn finish_learning(&self, target: CcTarget) {
+        let _ = self.learning.compare_exchange(
+            target.index() as u8 + 1,
+            0,
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Stores the CC value (0.0-1.0) to apply to the target
+    pub fn push_value(&self, target: CcTarget, value: f32) {
+        self.pending[target.index()].store(value, Ordering::Relaxed);
+    }
+
+    /// Returns the value to apply to the target, if a CC was received since the last call
+    pub fn take_value(&self, target: CcTarget) -> Option<f32> {
+        let value = self.pending[target.index()].swap(f32::NAN, Ordering::Relaxed);
+        (!value.is_nan()).then_some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cc_controls_a_single_target() {
+        let mut mappings = CcMappings::default();
+        mappings.bind(CcTarget::Gain, 7);
+        assert_eq!(mappings.target_of(7), Some(CcTarget::Gain));
+
+        mappings.bind(CcTarget::Drive, 7);
+        assert_eq!(mappings.get(CcTarget::Gain), None);
+        assert_eq!(mappings.target_of(7), Some(CcTarget::Drive));
+        assert_eq!(mappings.target_of(1), None);
+    }
+
+    #[test]
+    fn test_learn_and_values_are_taken_once() {
+        let learn = MidiLearn::default();
+        assert_eq!(learn.learning(), None);
+
+        learn.set_learning(Some(CcTarget::Drive));
+        assert_eq!(learn.learning(), Some(CcTarget::Drive));
+
+        // Binding another target doesn't stop the current learn
+        learn.finish_learning(CcTarget::Gain);
+        assert_eq!(learn.learning(), Some(CcTarget::Drive));
+        learn.finish_learning(CcTarget::Drive);
+        assert_eq!(learn.learning(), None);
+
+        learn.push_value(CcTarget::Gain, 0.25);
+        assert_eq!(learn.take_value(CcTarget::Gain), Some(0.25));
+        assert_eq!(learn.take_value(CcTarget::Gain), None);
+        assert_eq!(learn.take_value(CcTarget::Drive), None);
+    }
+}
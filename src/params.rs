@@ -5,34 +5,105 @@ use std::{
 
 use nih_plug::prelude::*;
 
-use crate::{pitch_shift::PitchShiftKind, utils};
+use crate::{
+    filter::FilterMode,
+    midi_learn::CcMappings,
+    pitch_shift::{Interpolation, PitchShiftKind},
+    saturation::{Oversampling, SaturationMode},
+    utils,
+};
+
+pub const MAX_SAMPLES: usize = 16;
+
+/// Number of aux outputs the slots can be routed to, besides the main output
+pub const NUM_OUTPUT_BUSES: usize = 4;
 
-pub const MAX_SAMPLES: usize = 8;
+/// Number of transitions between the blend layers, there is one more layer than transitions
+pub const MAX_BLEND_POINTS: usize = 3;
+
+/// Number of choke groups the slots can be assigned to
+const NUM_CHOKE_GROUPS: i32 = 8;
+
+/// MIDI note number for middle C (C3), the default base note for pitch calculations
+const DEFAULT_BASE_NOTE: i32 = 60;
 
 #[derive(Params, Debug)]
 pub struct SamplePlayerParams {
     #[persist = "sample_path"]
     pub sample_path: Arc<RwLock<Option<PathBuf>>>,
 
+    // Only this channel of the file is used (stereo split into mono slots)
+    #[persist = "sample_channel"]
+    pub sample_channel: Arc<RwLock<Option<usize>>>,
+
     #[id = "muted"]
     pub muted: BoolParam,
 
+    // When any slot is soloed, only the soloed slots play
+    #[id = "soloed"]
+    pub soloed: BoolParam,
+
     #[id = "is_tonal"]
     pub is_tonal: BoolParam,
 
+    // Set is_tonal automatically when a sample is loaded
+    #[id = "auto_tonal"]
+    pub auto_tonal: BoolParam,
+
     #[id = "gain"]
     pub gain: FloatParam,
 
+    // How much the note velocity scales the gain
+    #[id = "velocity_sensitivity"]
+    pub velocity_sensitivity: FloatParam,
+
+    // How much the note velocity shortens the attack
+    #[id = "vel_to_attack"]
+    pub vel_to_attack: FloatParam,
+
+    // Position in the stereo field (-1 = left, 1 = right)
+    #[id = "pan"]
+    pub pan: FloatParam,
+
+    // Stereo width of stereo samples (0 = mono, 1 = original)
+    #[id = "width"]
+    pub width: FloatParam,
+
+    // Starting the slot silences the other slots of its group (0 = none)
+    #[id = "choke_group"]
+    pub choke_group: IntParam,
+
+    // Output the slot plays on, 0 is the main output.
+    // The buses are pre-master, the master gain, drive and limiter don't apply to them
+    #[id = "output_bus"]
+    pub output_bus: IntParam,
+
+    // Level sent to the aux output, for parallel processing
+    #[id = "send_gain"]
+    pub send_gain: FloatParam,
+
     #[id = "root_note"]
     pub root_note: IntParam,
 
     #[id = "semitone_offset"]
     pub semitone_offset: IntParam,
 
+    // Fine tuning on top of the semitone offset, in cents
+    #[id = "fine_tune"]
+    pub fine_tune: FloatParam,
+
+    // Only notes on this MIDI channel trigger the sample (0 = omni)
+    #[id = "midi_channel"]
+    pub midi_channel: IntParam,
+
     // ADSR Envelope Parameters
     #[id = "attack"]
     pub attack: FloatParam,
 
+    // Time at full level before the decay
+    #[id = "hold"]
+    pub hold: FloatParam,
+
     #[id = "decay"]
     pub decay: FloatParam,
 
@@ -42,16 +113,91 @@ pub struct SamplePlayerParams {
     #[id = "release"]
     pub release: FloatParam,
 
+    // Shape of the envelope stages, 1 is linear
+    #[id = "adsr_curve"]
+    pub adsr_curve: FloatParam,
+
     // Delay start (in s)
     #[id = "start_offset"]
     pub start_offset: FloatParam,
 
+    // Random start offset of each note, humanizes rolls (in ms)
+    #[id = "start_jitter"]
+    pub start_jitter: FloatParam,
+
+    // Trim the tail (in s from the end of the sample)
+    #[id = "trim_end"]
+    pub trim_end: FloatParam,
+
+    // Fades at the edges of the played part of the sample, against clicks (in ms)
+    #[id = "fade_in"]
+    pub fade_in: FloatParam,
+
+    #[id = "fade_out"]
+    pub fade_out: FloatParam,
+
+    // Fade out of a voice stolen by a new note (in s)
+    #[id = "retrigger_fade"]
+    pub retrigger_fade: FloatParam,
+
+    // Region looped while the note is held (in s from the start of the sample)
+    #[id = "loop_enabled"]
+    pub loop_enabled: BoolParam,
+
+    #[id = "loop_start"]
+    pub loop_start: FloatParam,
+
+    #[id = "loop_end"]
+    pub loop_end: FloatParam,
+
+    // Crossfade into the loop start (in ms)
+    #[id = "loop_crossfade"]
+    pub loop_crossfade: FloatParam,
+
+    // Ducking of the tail after the transient
+    #[id = "pump_amount"]
+    pub pump_amount: FloatParam,
+
+    // Recovery time of the ducking (in s)
+    #[id = "pump_time"]
+    pub pump_time: FloatParam,
+
+    // Pitch offset at the note start (in semitones), it goes back to 0 over the pitch env time
+    #[id = "pitch_env_amount"]
+    pub pitch_env_amount: FloatParam,
+
+    #[id = "pitch_env_time"]
+    pub pitch_env_time: FloatParam,
+
     #[id = "blend_group"]
     pub blend_group: EnumParam<BlendGroup>,
 
+    // The blend layer of the slot, starting at 1. It replaces the blend group when set
+    #[id = "blend_layer"]
+    pub blend_layer: IntParam,
+
     #[id = "pitch_shift_kind"]
     pub pitch_shift_kind: EnumParam<PitchShiftKind>,
 
+    // PSOLA only, keeps the formants in place on upward shifts
+    #[id = "preserve_formants"]
+    pub preserve_formants: BoolParam,
+
+    // Classic only, how the sample is read between two frames
+    #[id = "interpolation"]
+    pub interpolation: EnumParam<Interpolation>,
+
+    #[id = "filter_mode"]
+    pub filter_mode: EnumParam<FilterMode>,
+
+    #[id = "filter_cutoff"]
+    pub filter_cutoff: FloatParam,
+
+    // Mix of the filtered frame with the raw pitch shifted one,
+    // both still go through the slot gains and envelope
+    #[id = "dry_wet"]
+    pub dry_wet: FloatParam,
+
     // For gui
     #[id = "show_blend"]
     pub show_blend: BoolParam,
@@ -59,8 +205,50 @@ pub struct SamplePlayerParams {
     #[id = "show_indicator"]
     pub show_indicator: BoolParam,
 
+    #[id = "smooth_indicator"]
+    pub smooth_indicator: BoolParam,
+
     #[id = "show_adsr"]
     pub show_adsr: BoolParam,
+
+    #[id = "show_rms"]
+    pub show_rms: BoolParam,
+
+    #[id = "show_spectrum"]
+    pub show_spectrum: BoolParam,
+}
+
+impl SamplePlayerParams {
+    /// Returns the blend layer of the slot (from 0), `None` when it follows its blend group
+    pub fn get_blend_layer(&self) -> Option<usize> {
+        (self.blend_layer.value() as usize).checked_sub(1)
+    }
+
+    /// Returns whether the slot is blended, by its layer or its group
+    pub fn is_blended(&self) -> bool {
+        self.get_blend_layer().is_some() || self.blend_group.value() != BlendGroup::None
+    }
+
+    /// Copies the value of every param of `other` into this slot, the sample isn't copied.
+    ///
+    /// The new values are passed to `set` as normalized values, so the change can go
+    /// through the GUI context and the host sees it like any other edit.
+    /// Params that already have the value are skipped.
+    pub fn copy_params_from(&self, other: &SamplePlayerParams, mut set: impl FnMut(ParamPtr, f32)) {
+        for ((_, ptr, _), (_, source, _)) in self.param_map().into_iter().zip(other.param_map()) {
+            // SAFETY: the pointers come from `param_map()` of `self` and `other`, which are
+            // borrowed for the duration of the call so the params outlive them
+            let (current, value) = unsafe {
+                (
+                    ptr.unmodulated_normalized_value(),
+                    source.unmodulated_normalized_value(),
+                )
+            };
+            if current != value {
+                set(ptr, value);
+            }
+        }
+    }
 }
 
 impl Default for SamplePlayerParams {
@@ -68,6 +256,8 @@ impl Default for SamplePlayerParams {
         Self {
             sample_path: Arc::new(RwLock::new(None)),
 
+            sample_channel: Arc::new(RwLock::new(None)),
+
             muted: BoolParam::new("Muted", false).with_value_to_string(Arc::new(|value| {
                 if value {
                     String::from("Muted")
@@ -76,6 +266,14 @@ impl Default for SamplePlayerParams {
                 }
             })),
 
+            soloed: BoolParam::new("Soloed", false).with_value_to_string(Arc::new(|value| {
+                if value {
+                    String::from("Soloed")
+                } else {
+                    String::from("Solo")
+                }
+            })),
+
             is_tonal: BoolParam::new("Tonal", true).with_value_to_string(Arc::new(|value| {
                 if value {
                     String::from("Tonal")
@@ -84,6 +282,8 @@ impl Default for SamplePlayerParams {
                 }
             })),
 
+            auto_tonal: BoolParam::new("Auto Tonal", false).non_automatable(),
+
             gain: FloatParam::new(
                 "Gain",
                 util::db_to_gain(0.0),
@@ -97,6 +297,91 @@ impl Default for SamplePlayerParams {
             .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
             .with_string_to_value(formatters::s2v_f32_gain_to_db()),
 
+            velocity_sensitivity: FloatParam::new(
+                "Velocity",
+                0.0, // Velocity is ignored by default
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(1))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            vel_to_attack: FloatParam::new(
+                "Vel > Attack",
+                0.0, // The attack doesn't depend on the velocity by default
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(1))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            pan: FloatParam::new(
+                "Pan",
+                0.0,
+                FloatRange::Linear {
+                    min: -1.0,
+                    max: 1.0,
+                },
+            )
+            .with_value_to_string(formatters::v2s_f32_panning())
+            .with_string_to_value(formatters::s2v_f32_panning()),
+
+            width: FloatParam::new(
+                "Width",
+                1.0, // The sample as it is
+                FloatRange::Linear { min: 0.0, max: 2.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            choke_group: IntParam::new(
+                "Choke Group",
+                0,
+                IntRange::Linear {
+                    min: 0,
+                    max: NUM_CHOKE_GROUPS,
+                },
+            )
+            .with_value_to_string(Arc::new(|value| {
+                if value == 0 {
+                    String::from("No choke")
+                } else {
+                    format!("Choke {}", value)
+                }
+            }))
+            .non_automatable(),
+
+            output_bus: IntParam::new(
+                "Output",
+                0,
+                IntRange::Linear {
+                    min: 0,
+                    max: NUM_OUTPUT_BUSES as i32,
+                },
+            )
+            .with_value_to_string(Arc::new(|value| {
+                if value == 0 {
+                    String::from("Main")
+                } else {
+                    format!("Bus {}", value)
+                }
+            }))
+            .non_automatable(),
+
+            send_gain: FloatParam::new(
+                "Send",
+                0.0, // Nothing is sent by default
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: util::db_to_gain(6.0),
+                    factor: FloatRange::gain_skew_factor(-30.0, 6.0),
+                },
+            )
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
+            .with_string_to_value(formatters::s2v_f32_gain_to_db()),
+
             root_note: IntParam::new("Root Note", 0, IntRange::Linear { min: 0, max: 11 })
                 .with_value_to_string(Arc::new(utils::semitones_to_note)),
 
@@ -108,6 +393,27 @@ impl Default for SamplePlayerParams {
             .with_unit(" semitones")
             .non_automatable(),
 
+            fine_tune: FloatParam::new(
+                "Fine Tune",
+                0.,
+                FloatRange::Linear {
+                    min: -100.,
+                    max: 100.,
+                },
+            )
+            .with_unit(" cents")
+            .with_value_to_string(formatters::v2s_f32_rounded(0)),
+
+            midi_channel: IntParam::new("MIDI Channel", 0, IntRange::Linear { min: 0, max: 16 })
+                .with_value_to_string(Arc::new(|value| {
+                    if value == 0 {
+                        String::from("Omni")
+                    } else {
+                        format!("Ch {}", value)
+                    }
+                }))
+                .non_automatable(),
+
             // ADSR Parameters
             attack: FloatParam::new(
                 "Attack",
@@ -122,6 +428,18 @@ impl Default for SamplePlayerParams {
             .with_unit(" s")
             .with_value_to_string(formatters::v2s_f32_rounded(3)),
 
+            hold: FloatParam::new(
+                "Hold",
+                0.0, // No hold, straight to the decay
+                FloatRange::Skewed {
+                    min: 0.,
+                    max: 2.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" s")
+            .with_value_to_string(formatters::v2s_f32_rounded(3)),
+
             decay: FloatParam::new(
                 "Decay",
                 0.1, // 100ms default
@@ -158,6 +476,23 @@ impl Default for SamplePlayerParams {
             .with_unit(" s")
             .with_value_to_string(formatters::v2s_f32_rounded(3)),
 
+            adsr_curve: FloatParam::new(
+                "Curve",
+                1.0, // Linear by default
+                FloatRange::Skewed {
+                    min: 1.0,
+                    max: 8.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_value_to_string(Arc::new(|value| {
+                if value <= 1.0 {
+                    String::from("Linear")
+                } else {
+                    format!("{:.2}", value)
+                }
+            })),
+
             start_offset: FloatParam::new(
                 "Start Offset",
                 0.0,
@@ -169,29 +504,292 @@ impl Default for SamplePlayerParams {
             .with_unit(" s")
             .with_value_to_string(formatters::v2s_f32_rounded(3)),
 
+            start_jitter: FloatParam::new(
+                "Start Jitter",
+                0.0,
+                FloatRange::Skewed {
+                    min: 0.,
+                    max: 20.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+
+            trim_end: FloatParam::new(
+                "Trim End",
+                0.0,
+                FloatRange::Skewed {
+                    min: 0.,
+                    max: 5.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" s")
+            .with_value_to_string(formatters::v2s_f32_rounded(3)),
+
+            fade_in: FloatParam::new(
+                "Fade In",
+                1.0, // Declicks the start, short enough to keep the transient
+                FloatRange::Skewed {
+                    min: 0.,
+                    max: 50.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+
+            fade_out: FloatParam::new(
+                "Fade Out",
+                1.0, // Declicks the end
+                FloatRange::Skewed {
+                    min: 0.,
+                    max: 50.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+
+            retrigger_fade: FloatParam::new(
+                "Retrigger Fade",
+                0.005, // 5ms is enough to avoid the clic
+                FloatRange::Skewed {
+                    min: 0.,
+                    max: 0.1,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" s")
+            .with_value_to_string(formatters::v2s_f32_rounded(3)),
+
+            loop_enabled: BoolParam::new("Loop", false),
+
+            loop_start: FloatParam::new(
+                "Loop Start",
+                0.1,
+                FloatRange::Skewed {
+                    min: 0.,
+                    max: 5.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" s")
+            .with_value_to_string(formatters::v2s_f32_rounded(3)),
+
+            loop_end: FloatParam::new(
+                "Loop End",
+                0.3,
+                FloatRange::Skewed {
+                    min: 0.,
+                    max: 5.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" s")
+            .with_value_to_string(formatters::v2s_f32_rounded(3)),
+
+            loop_crossfade: FloatParam::new(
+                "Loop Crossfade",
+                10.0,
+                FloatRange::Skewed {
+                    min: 0.,
+                    max: 200.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+
+            pump_amount: FloatParam::new(
+                "Pump",
+                0.0, // No ducking by default
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(1))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            pump_time: FloatParam::new(
+                "Pump Time",
+                0.15,
+                FloatRange::Skewed {
+                    min: 0.01,
+                    max: 1.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" s")
+            .with_value_to_string(formatters::v2s_f32_rounded(3)),
+
+            pitch_env_amount: FloatParam::new(
+                "Pitch Env",
+                0.,
+                FloatRange::Linear {
+                    min: -24.,
+                    max: 24.,
+                },
+            )
+            .with_unit(" semitones")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+
+            pitch_env_time: FloatParam::new(
+                "Pitch Env Time",
+                0.05,
+                FloatRange::Skewed {
+                    min: 0.001,
+                    max: 1.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" s")
+            .with_value_to_string(formatters::v2s_f32_rounded(3)),
+
             pitch_shift_kind: EnumParam::<PitchShiftKind>::new(
                 "Pitch Shift Kind",
                 PitchShiftKind::Classic,
             ),
 
+            preserve_formants: BoolParam::new("Preserve Formants", false),
+
+            interpolation: EnumParam::new("Interpolation", Interpolation::Linear),
+
             blend_group: EnumParam::<BlendGroup>::new("Blend Group", BlendGroup::None),
 
+            blend_layer: IntParam::new(
+                "Blend Layer",
+                0,
+                IntRange::Linear {
+                    min: 0,
+                    max: MAX_BLEND_POINTS as i32 + 1,
+                },
+            )
+            .with_value_to_string(Arc::new(|value| {
+                if value == 0 {
+                    String::from("Off")
+                } else {
+                    value.to_string()
+                }
+            })),
+
+            filter_mode: EnumParam::<FilterMode>::new("Filter Mode", FilterMode::Off),
+
+            filter_cutoff: FloatParam::new(
+                "Cutoff",
+                1000.0,
+                FloatRange::Skewed {
+                    min: 20.0,
+                    max: 20000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(1))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
+
+            dry_wet: FloatParam::new("Dry/Wet", 1.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_smoother(SmoothingStyle::Linear(20.0))
+                .with_unit("%")
+                .with_value_to_string(formatters::v2s_f32_percentage(1))
+                .with_string_to_value(formatters::s2v_f32_percentage()),
+
             show_blend: BoolParam::new("Show blend", false),
 
             show_adsr: BoolParam::new("Show adsr", false),
 
+            show_rms: BoolParam::new("Show rms", false),
+
+            show_spectrum: BoolParam::new("Show spectrum", false),
+
             show_indicator: BoolParam::new("Show indicator", true),
+
+            smooth_indicator: BoolParam::new("Smooth indicator", false),
         }
     }
 }
 
-#[derive(Debug, Enum, PartialEq)]
+#[derive(Debug, Enum, PartialEq, Clone, Copy)]
 pub enum BlendGroup {
     None,
     Start,
     End,
 }
 
+/// Shape of the blend transitions
+#[derive(Debug, Enum, PartialEq, Clone, Copy)]
+pub enum BlendCurve {
+    Linear,
+    #[name = "Equal Power"]
+    EqualPower,
+    Smooth,
+}
+
+#[derive(Debug, Enum, PartialEq, Clone, Copy)]
+pub enum TriggerMode {
+    All,
+    #[name = "Round Robin"]
+    RoundRobin,
+}
+
+/// A blend point after the first one, the first is the global blend time and transition
+#[derive(Params, Debug)]
+pub struct BlendPointParams {
+    #[id = "blend_point_time"]
+    pub time: FloatParam,
+
+    #[id = "blend_point_transition"]
+    pub transition: FloatParam,
+
+    #[id = "blend_point_time_beats"]
+    pub time_beats: FloatParam,
+
+    #[id = "blend_point_transition_beats"]
+    pub transition_beats: FloatParam,
+}
+
+impl BlendPointParams {
+    /// The `index`-th extra point, placed after the previous ones
+    fn new(index: usize) -> Self {
+        let offset = (index + 1) as f32;
+        Self {
+            time: FloatParam::new(
+                "Blend Point Time",
+                0.2 + 0.1 * offset,
+                FloatRange::Linear { min: 0., max: 1. },
+            )
+            .with_unit(" s")
+            .with_value_to_string(formatters::v2s_f32_rounded(3)),
+
+            transition: FloatParam::new(
+                "Blend Point Transition",
+                0.05,
+                FloatRange::Linear {
+                    min: 0.01,
+                    max: 0.5,
+                },
+            )
+            .with_unit(" s")
+            .with_value_to_string(formatters::v2s_f32_rounded(3)),
+
+            time_beats: FloatParam::new(
+                "Blend Point Time Beats",
+                0.5 + 0.25 * offset,
+                FloatRange::Linear { min: 0., max: 4. },
+            )
+            .with_unit(" beats")
+            .with_value_to_string(formatters::v2s_f32_rounded(3)),
+
+            transition_beats: FloatParam::new(
+                "Blend Point Transition Beats",
+                0.125,
+                FloatRange::Linear { min: 0.01, max: 2. },
+            )
+            .with_unit(" beats")
+            .with_value_to_string(formatters::v2s_f32_rounded(3)),
+        }
+    }
+}
+
 #[derive(Params, Debug)]
 pub struct HardKickSamplerParams {
     /// The parameter's ID is used to identify the parameter in the wrappred plugin API. As long as
@@ -201,16 +799,149 @@ pub struct HardKickSamplerParams {
     #[id = "gain"]
     pub gain: FloatParam,
 
+    // The MIDI CCs bound to the master params with MIDI learn
+    #[persist = "cc_mappings"]
+    pub cc_mappings: Arc<RwLock<CcMappings>>,
+
+    // Saturation applied on the master output, after the gain
+    #[id = "drive"]
+    pub drive: FloatParam,
+
+    #[id = "saturation_mode"]
+    pub saturation_mode: EnumParam<SaturationMode>,
+
+    // Rate the saturation runs at, higher rates reduce its aliasing
+    #[id = "oversampling"]
+    pub oversampling: EnumParam<Oversampling>,
+
+    // Brickwall limiter on the master output, after the saturation
+    #[id = "limiter_enabled"]
+    pub limiter_enabled: BoolParam,
+
+    #[id = "ceiling"]
+    pub ceiling: FloatParam,
+
     #[id = "blend_time"]
     pub blend_time: FloatParam,
 
     #[id = "blend_transition"]
     pub blend_transition: FloatParam,
 
+    // Blend time and transition in beats, used instead of the seconds when synced
+    #[id = "blend_sync"]
+    pub blend_sync: BoolParam,
+
+    #[id = "blend_time_beats"]
+    pub blend_time_beats: FloatParam,
+
+    #[id = "blend_transition_beats"]
+    pub blend_transition_beats: FloatParam,
+
+    #[id = "blend_curve"]
+    pub blend_curve: EnumParam<BlendCurve>,
+
+    // Number of blend points used by the slots with a blend layer
+    #[id = "blend_points"]
+    pub blend_points: IntParam,
+
+    #[nested(array, group = "Blend Points")]
+    pub extra_blend_points: [BlendPointParams; MAX_BLEND_POINTS - 1],
+
+    // Each tonal sample keeps its own root note instead of
+    // being tuned to the played note
+    #[id = "stack_mode"]
+    pub stack_mode: BoolParam,
+
+    // Which slots a note triggers, all of them or the next one in turn
+    #[id = "trigger_mode"]
+    pub trigger_mode: EnumParam<TriggerMode>,
+
+    // The MIDI note playing tonal samples at their root note
+    #[id = "base_note"]
+    pub base_note: IntParam,
+
+    // Semitones reached at full pitch bend
+    #[id = "pitch_bend_range"]
+    pub pitch_bend_range: IntParam,
+
+    // Load stereo files as two mono samples in adjacent slots
+    #[id = "split_stereo"]
+    pub split_stereo: BoolParam,
+
+    // The previous / next file buttons browse the whole pack folder, subfolders included
+    #[id = "recursive_browse"]
+    pub recursive_browse: BoolParam,
+
+    // Slower but cleaner processing, e.g. anti-aliasing when pitching up
+    #[id = "high_quality"]
+    pub high_quality: BoolParam,
+
+    // Resample the loaded samples to the host rate instead of reading them faster or slower
+    #[id = "resample_to_host"]
+    pub resample_to_host: BoolParam,
+
+    // Monitoring helpers, applied on the master output
+    #[id = "mute"]
+    pub mute: BoolParam,
+
+    #[id = "dim"]
+    pub dim: BoolParam,
+
+    // For gui, only shows the essential controls of the selected slot
+    #[id = "mini_editor"]
+    pub mini_editor: BoolParam,
+
     #[nested(array, group = "Samples")]
     pub samples: [SamplePlayerParams; MAX_SAMPLES],
 }
 
+impl HardKickSamplerParams {
+    /// Returns the blend time and transition in seconds,
+    /// converted from beats at `bpm` when the blend is synced
+    pub fn get_blend_seconds(&self, bpm: f32) -> (f32, f32) {
+        if self.blend_sync.value() {
+            let seconds_per_beat = 60. / utils::effective_bpm(Some(bpm));
+            (
+                self.blend_time_beats.value() * seconds_per_beat,
+                self.blend_transition_beats.value() * seconds_per_beat,
+            )
+        } else {
+            (self.blend_time.value(), self.blend_transition.value())
+        }
+    }
+
+    /// Returns the time and transition of a blend point in seconds, the first one is
+    /// the global blend time and transition
+    pub fn get_blend_point_seconds(&self, index: usize, bpm: f32) -> (f32, f32) {
+        let Some(point) = index
+            .checked_sub(1)
+            .and_then(|index| self.extra_blend_points.get(index))
+        else {
+            return self.get_blend_seconds(bpm);
+        };
+
+        if self.blend_sync.value() {
+            let seconds_per_beat = 60. / utils::effective_bpm(Some(bpm));
+            (
+                point.time_beats.value() * seconds_per_beat,
+                point.transition_beats.value() * seconds_per_beat,
+            )
+        } else {
+            (point.time.value(), point.transition.value())
+        }
+    }
+
+    /// Returns the time and transition of every blend point in seconds, ordered by time.
+    ///
+    /// Only the first `blend_points` entries are used, they are returned with their count.
+    pub fn get_blend_points(&self, bpm: f32) -> ([(f32, f32); MAX_BLEND_POINTS], usize) {
+        let count = (self.blend_points.value() as usize).clamp(1, MAX_BLEND_POINTS);
+        let mut points = std::array::from_fn(|index| self.get_blend_point_seconds(index, bpm));
+        points[..count].sort_by(|a, b| a.0.total_cmp(&b.0));
+        (points, count)
+    }
+}
+
 impl Default for HardKickSamplerParams {
     fn default() -> Self {
         Self {
@@ -228,6 +959,34 @@ impl Default for HardKickSamplerParams {
             .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
             .with_string_to_value(formatters::s2v_f32_gain_to_db()),
 
+            cc_mappings: Arc::new(RwLock::new(CcMappings::default())),
+
+            drive: FloatParam::new("Drive", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_smoother(SmoothingStyle::Linear(20.0))
+                .with_unit("%")
+                .with_value_to_string(formatters::v2s_f32_percentage(1))
+                .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            saturation_mode: EnumParam::new("Saturation", SaturationMode::Off),
+
+            oversampling: EnumParam::new("Oversampling", Oversampling::Off),
+
+            limiter_enabled: BoolParam::new("Limiter", false),
+
+            ceiling: FloatParam::new(
+                "Ceiling",
+                util::db_to_gain(0.0),
+                FloatRange::Skewed {
+                    min: util::db_to_gain(-12.0),
+                    max: util::db_to_gain(0.0),
+                    factor: FloatRange::gain_skew_factor(-12.0, 0.0),
+                },
+            )
+            .with_smoother(SmoothingStyle::Logarithmic(50.0))
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
+            .with_string_to_value(formatters::s2v_f32_gain_to_db()),
+
             blend_time: FloatParam::new(
                 "Blend Time",
                 0.2,
@@ -247,6 +1006,82 @@ impl Default for HardKickSamplerParams {
             .with_unit(" s")
             .with_value_to_string(formatters::v2s_f32_rounded(3)),
 
+            blend_sync: BoolParam::new("Blend Sync", false),
+
+            blend_time_beats: FloatParam::new(
+                "Blend Time Beats",
+                0.5,
+                FloatRange::Linear { min: 0., max: 2. },
+            )
+            .with_unit(" beats")
+            .with_value_to_string(formatters::v2s_f32_rounded(3)),
+
+            blend_transition_beats: FloatParam::new(
+                "Blend Transition Beats",
+                0.125,
+                FloatRange::Linear { min: 0.01, max: 2. },
+            )
+            .with_unit(" beats")
+            .with_value_to_string(formatters::v2s_f32_rounded(3)),
+
+            blend_curve: EnumParam::new("Blend Curve", BlendCurve::Linear),
+
+            blend_points: IntParam::new(
+                "Blend Points",
+                1,
+                IntRange::Linear {
+                    min: 1,
+                    max: MAX_BLEND_POINTS as i32,
+                },
+            )
+            .non_automatable(),
+
+            extra_blend_points: std::array::from_fn(BlendPointParams::new),
+
+            stack_mode: BoolParam::new("Stack Mode", false).with_value_to_string(Arc::new(
+                |value| {
+                    if value {
+                        String::from("Stack")
+                    } else {
+                        String::from("Unison")
+                    }
+                },
+            )),
+
+            trigger_mode: EnumParam::new("Trigger Mode", TriggerMode::All).non_automatable(),
+
+            base_note: IntParam::new(
+                "Base Note",
+                DEFAULT_BASE_NOTE,
+                IntRange::Linear { min: 0, max: 127 },
+            )
+            .with_value_to_string(Arc::new(|value| {
+                // Same octave convention as the middle C (C3)
+                format!("{}{}", utils::semitones_to_note(value), value / 12 - 2)
+            }))
+            .non_automatable(),
+
+            pitch_bend_range: IntParam::new(
+                "Pitch Bend Range",
+                2,
+                IntRange::Linear { min: 0, max: 24 },
+            )
+            .with_unit(" semitones"),
+
+            split_stereo: BoolParam::new("Split Stereo", false).non_automatable(),
+
+            recursive_browse: BoolParam::new("Browse Pack", false).non_automatable(),
+
+            high_quality: BoolParam::new("High Quality", false).non_automatable(),
+
+            resample_to_host: BoolParam::new("Resample To Host", false).non_automatable(),
+
+            mute: BoolParam::new("Mute", false),
+
+            dim: BoolParam::new("Dim", false),
+
+            mini_editor: BoolParam::new("Mini", false).non_automatable(),
+
             samples: [(); MAX_SAMPLES].map(|_| SamplePlayerParams::default()),
         }
     }
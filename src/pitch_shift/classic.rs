@@ -1,15 +1,40 @@
+use std::sync::Arc;
+
 use crate::{
-    pitch_shift::{FrameOutput, PitchShiftKind, PitchShifter},
+    params::BlendCurve,
+    pitch_shift::{
+        FrameOutput, Interpolation, LoopRegion, PitchShiftError, PitchShiftKind, PitchShifter,
+    },
     utils,
 };
 
+/// Number of channels a `FrameOutput` carries
+const MAX_CHANNELS: usize = 2;
+
+/// Zero crossings of the anti-aliasing kernel on each side, at the source rate
+const ANTI_ALIAS_ZERO_CROSSINGS: f32 = 4.;
+
 pub struct ClassicShifter {
-    sample_buffer: Option<Vec<f32>>,
+    sample_buffer: Option<Arc<Vec<f32>>>,
     channel_number: usize,
     sample_rate: f32,
     playback_rate: f32,
     sr_correction: f32,
     is_loaded: bool,
+    anti_alias: bool,
+    interpolation: Interpolation,
+
+    // Playback rate of the note, before the pitch bend
+    note_rate: f32,
+
+    // Last position read, the playhead stays there when the rate is bent
+    last_position: f32,
+
+    // Looped region
+    loop_region: Option<LoopRegion>,
+
+    // How far back the loop and the bends moved the playhead (in output frames)
+    rewind: f32,
 }
 
 impl ClassicShifter {
@@ -21,6 +46,12 @@ impl ClassicShifter {
             playback_rate: 1.0,
             sr_correction: 1.0,
             is_loaded: false,
+            anti_alias: false,
+            interpolation: Interpolation::Linear,
+            note_rate: 1.0,
+            last_position: 0.0,
+            loop_region: None,
+            rewind: 0.0,
         }
     }
 
@@ -28,13 +59,125 @@ impl ClassicShifter {
     /// Uses the same utility function as the main SamplePlayer for consistency.
     fn get_playback_position(&self, process_count: f32, channel_index: usize) -> (usize, f32) {
         utils::get_stretch_playback_position(
-            process_count,
+            process_count - self.rewind,
             self.sr_correction,
             self.playback_rate,
             self.channel_number,
             channel_index,
         )
     }
+
+    /// Reads the frame at `position` (in output frames), one value per channel.
+    ///
+    /// Returns `None` past the end of the sample. Only the channels a `FrameOutput`
+    /// carries are read, so nothing is allocated.
+    fn read_frame(&self, buffer: &[f32], position: f32) -> Option<[f32; MAX_CHANNELS]> {
+        // Bounds are checked per frame so every channel ends on the same frame
+        let (frame_start, fraction) = self.get_playback_position(position, 0);
+        let frame_end = frame_start + self.channel_number;
+        if frame_end > buffer.len() {
+            return None;
+        }
+
+        // Reading faster than the source rate aliases, filter it if requested
+        let read_rate = self.sr_correction * self.playback_rate;
+        if self.anti_alias && read_rate > 1. {
+            let pitched_position = self.get_position(position);
+            let mut frame = [0.; MAX_CHANNELS];
+            for (channel_index, sample) in frame.iter_mut().enumerate().take(self.channel_number) {
+                *sample =
+                    self.get_filtered_sample(buffer, pitched_position, channel_index, read_rate);
+            }
+            return Some(frame);
+        }
+
+        // The last frame has nothing to interpolate with, it is held as is
+        let step = self.channel_number;
+        let has_previous_frame = frame_start >= step;
+        let has_next_frame = frame_end + step <= buffer.len();
+        let has_second_next_frame = frame_end + 2 * step <= buffer.len();
+
+        let mut frame = [0.; MAX_CHANNELS];
+
+        for (sample, sample_index) in frame.iter_mut().zip(frame_start..frame_end) {
+            let current = buffer[sample_index];
+            let next = if has_next_frame {
+                buffer[sample_index + step]
+            } else {
+                current
+            };
+
+            let sample_value = match self.interpolation {
+                Interpolation::Linear => utils::interpolate(current, next, fraction),
+                Interpolation::Hermite => {
+                    // Frames outside of the sample repeat the edge frames
+                    let previous = if has_previous_frame {
+                        buffer[sample_index - step]
+                    } else {
+                        current
+                    };
+                    let second_next = if has_second_next_frame {
+                        buffer[sample_index + 2 * step]
+                    } else {
+                        next
+                    };
+                    utils::interpolate_hermite(previous, current, next, second_next, fraction)
+                }
+            };
+
+            *sample = sample_value;
+        }
+
+        Some(frame)
+    }
+
+    /// Wraps a frame from `read_frame` for the channels of the sample
+    fn to_frame_output(&self, frame: [f32; MAX_CHANNELS]) -> FrameOutput {
+        match self.channel_number {
+            1 => FrameOutput::Mono(frame[0]),
+            2 => FrameOutput::Stereo(frame),
+            _ => FrameOutput::Unsupported,
+        }
+    }
+
+    /// Reads a sample through a windowed sinc lowpass.
+    ///
+    /// When the source is read faster than its own rate, everything above
+    /// `1 / read_rate` of its nyquist folds back, so the cutoff follows the read rate.
+    fn get_filtered_sample(
+        &self,
+        buffer: &[f32],
+        pitched_position: f32,
+        channel_index: usize,
+        read_rate: f32,
+    ) -> f32 {
+        let cutoff = 1. / read_rate;
+        let half_width = ANTI_ALIAS_ZERO_CROSSINGS * read_rate;
+        let num_frames = buffer.len() / self.channel_number;
+
+        let first_frame = (pitched_position - half_width).ceil().max(0.) as usize;
+        let last_frame = ((pitched_position + half_width) as usize).min(num_frames - 1);
+
+        let mut sum = 0.;
+        let mut weight_sum = 0.;
+        for frame_index in first_frame..=last_frame {
+            let distance = frame_index as f32 - pitched_position;
+
+            // Hann window over the kernel
+            let window = 0.5 + 0.5 * (std::f32::consts::PI * distance / half_width).cos();
+            let weight = utils::sinc(distance * cutoff) * window;
+
+            sum += buffer[frame_index * self.channel_number + channel_index] * weight;
+            weight_sum += weight;
+        }
+
+        // Normalized so the kernel has unity gain, also near the edges of the sample
+        if weight_sum.abs() > f32::EPSILON {
+            sum / weight_sum
+        } else {
+            0.
+        }
+    }
 }
 
 impl PitchShifter for ClassicShifter {
@@ -45,18 +188,32 @@ impl PitchShifter for ClassicShifter {
         self.playback_rate = 1.0;
         self.sr_correction = 1.0;
         self.is_loaded = false;
+        self.anti_alias = false;
+        self.note_rate = 1.0;
+        self.last_position = 0.0;
+        self.loop_region = None;
+        self.rewind = 0.0;
     }
 
-    fn load_sample(&mut self, sample_buffer: &[f32], channel_number: usize, sample_rate: f32) {
-        self.sample_buffer = Some(sample_buffer.to_vec());
+    fn load_sample(
+        &mut self,
+        sample_buffer: &Arc<Vec<f32>>,
+        channel_number: usize,
+        sample_rate: f32,
+    ) -> Result<(), PitchShiftError> {
+        self.sample_buffer = Some(Arc::clone(sample_buffer));
         self.channel_number = channel_number;
         self.sample_rate = sample_rate;
         self.is_loaded = true;
+        Ok(())
     }
 
     fn trigger(&mut self, sr_correction: f32, semitone_offset: f32) {
         self.sr_correction = sr_correction;
         self.playback_rate = utils::semitone_offset_to_playback_rate(semitone_offset);
+        self.note_rate = self.playback_rate;
+        self.last_position = 0.0;
+        self.rewind = 0.0;
     }
 
     fn ready(&self) -> bool {
@@ -64,31 +221,44 @@ impl PitchShifter for ClassicShifter {
     }
 
     fn get_frame(&mut self, position: f32) -> Option<FrameOutput> {
-        let buffer = self.sample_buffer.as_ref()?;
+        self.last_position = position;
+        let read_rate = self.sr_correction * self.playback_rate;
 
-        let mut frame = Vec::with_capacity(self.channel_number);
+        // Wrap back into the loop once the playhead passed its end
+        if let Some(region) = self.loop_region {
+            let pitched_position = self.get_position(position);
+            if pitched_position >= region.end {
+                let loop_length = region.end - region.start;
+                let loops = ((pitched_position - region.start) / loop_length).floor();
+                self.rewind += loops * loop_length / read_rate;
+            }
+        }
 
-        for channel_index in 0..self.channel_number {
-            // Use the existing get_playback_position method which handles all the pitch shifting logic
-            let (sample_index, fraction) = self.get_playback_position(position, channel_index);
+        let buffer = self.sample_buffer.as_ref()?;
+        let mut frame = self.read_frame(buffer, position)?;
 
-            // Get current and next sample for interpolation
-            let current_sample = buffer.get(sample_index);
-            let next_sample = buffer.get(sample_index + self.channel_number);
+        // Before the loop end, the tail is blended with what precedes the loop start,
+        // so the wrap lands on the same content it fades into
+        if let Some(region) = self.loop_region {
+            let loop_length = region.end - region.start;
+            let crossfade = region.crossfade.min(loop_length).min(region.start);
+            let fade_start = region.end - crossfade;
+            let pitched_position = self.get_position(position);
 
-            let sample_value = match (current_sample, next_sample) {
-                // Both samples available - interpolate
-                (Some(&current), Some(&next)) => utils::interpolate(current, next, fraction),
-                // Only current sample available
-                (Some(&current), None) => current,
-                // No samples available - end of buffer
-                _ => return None,
-            };
+            if crossfade > 0. && pitched_position >= fade_start {
+                let progress = ((pitched_position - fade_start) / crossfade).min(1.);
+                let fade_in = utils::apply_blend_curve(BlendCurve::EqualPower, progress);
+                let fade_out = utils::apply_blend_curve(BlendCurve::EqualPower, 1. - progress);
 
-            frame.push(sample_value);
+                if let Some(lead_in) = self.read_frame(buffer, position - loop_length / read_rate) {
+                    for (tail, lead_in) in frame.iter_mut().zip(lead_in) {
+                        *tail = *tail * fade_out + lead_in * fade_in;
+                    }
+                }
+            }
         }
 
-        Some(frame.into())
+        Some(self.to_frame_output(frame))
     }
 
     fn kind(&self) -> PitchShiftKind {
@@ -96,6 +266,193 @@ impl PitchShifter for ClassicShifter {
     }
 
     fn get_position(&self, position: f32) -> f32 {
-        self.sr_correction * position * self.playback_rate
+        self.sr_correction * (position - self.rewind) * self.playback_rate
+    }
+
+    fn set_high_quality(&mut self, enabled: bool) {
+        self.anti_alias = enabled;
+    }
+
+    fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.interpolation = interpolation;
+    }
+
+    fn bend(&mut self, semitones: f32) {
+        let playback_rate = self.note_rate * utils::semitone_offset_to_playback_rate(semitones);
+        if playback_rate == self.playback_rate {
+            return;
+        }
+
+        // The playhead must not jump, only the speed from here changes
+        self.rewind = self.last_position
+            - (self.last_position - self.rewind) * self.playback_rate / playback_rate;
+        self.playback_rate = playback_rate;
+    }
+
+    fn set_loop(&mut self, region: Option<LoopRegion>) {
+        self.loop_region = region.filter(|region| region.start < region.end);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Gain of both sides halfway through an equal power crossfade
+    const HALF_POWER_GAIN: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+    fn assert_mono(frame: Option<FrameOutput>, expected: f32) {
+        match frame {
+            Some(FrameOutput::Mono(v)) => {
+                assert!((v - expected).abs() < 1e-4, "{} != {}", v, expected)
+            }
+            other => panic!("Unexpected frame {:?}", other),
+        }
+    }
+
+    fn ramp_shifter() -> ClassicShifter {
+        let mut shifter = ClassicShifter::new();
+        let ramp: Vec<f32> = (0..100).map(|i| i as f32).collect();
+        shifter.load_sample(&Arc::new(ramp), 1, 48000.).unwrap();
+        shifter.trigger(1., 0.);
+        shifter
+    }
+
+    #[test]
+    fn test_loop_wraps_to_start() {
+        let mut shifter = ramp_shifter();
+        shifter.set_loop(Some(LoopRegion::new(10., 20., 0.)));
+
+        assert_eq!(shifter.get_frame(15.), Some(FrameOutput::Mono(15.)));
+        assert_eq!(shifter.get_frame(20.), Some(FrameOutput::Mono(10.)));
+        assert_eq!(shifter.get_frame(47.), Some(FrameOutput::Mono(17.)));
+        assert_eq!(shifter.get_position(47.), 17.);
+    }
+
+    #[test]
+    fn test_loop_released_continues_from_playhead() {
+        let mut shifter = ramp_shifter();
+        shifter.set_loop(Some(LoopRegion::new(10., 20., 0.)));
+        shifter.get_frame(25.);
+
+        // Released, the playback goes on to the end of the sample
+        shifter.set_loop(None);
+        assert_eq!(shifter.get_frame(26.), Some(FrameOutput::Mono(16.)));
+        assert_eq!(shifter.get_frame(109.), Some(FrameOutput::Mono(99.)));
+        assert_eq!(shifter.get_frame(110.), None);
+    }
+
+    #[test]
+    fn test_empty_loop_is_disabled() {
+        let mut shifter = ramp_shifter();
+        shifter.set_loop(Some(LoopRegion::new(20., 20., 0.)));
+        assert_eq!(shifter.get_frame(30.), Some(FrameOutput::Mono(30.)));
+    }
+
+    #[test]
+    fn test_loop_crossfade_blends_lead_in() {
+        let mut shifter = ramp_shifter();
+        shifter.set_loop(Some(LoopRegion::new(10., 20., 4.)));
+
+        // Untouched before the fade, halfway it is an equal power mix of 18 and 8
+        assert_eq!(shifter.get_frame(15.), Some(FrameOutput::Mono(15.)));
+        assert_mono(shifter.get_frame(18.), (18. + 8.) * HALF_POWER_GAIN);
+    }
+
+    #[test]
+    fn test_loop_crossfade_blends_stereo() {
+        let mut shifter = ClassicShifter::new();
+        let ramp: Vec<f32> = (0..100).flat_map(|i| [i as f32, -(i as f32)]).collect();
+        shifter.load_sample(&Arc::new(ramp), 2, 48000.).unwrap();
+        shifter.trigger(1., 0.);
+        shifter.set_loop(Some(LoopRegion::new(10., 20., 4.)));
+
+        // Each channel is blended with its own lead in
+        match shifter.get_frame(18.) {
+            Some(FrameOutput::Stereo([left, right])) => {
+                let expected = (18. + 8.) * HALF_POWER_GAIN;
+                assert!((left - expected).abs() < 1e-4, "{} != {}", left, expected);
+                assert!(
+                    (right + expected).abs() < 1e-4,
+                    "{} != {}",
+                    right,
+                    -expected
+                );
+            }
+            other => panic!("Unexpected frame {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_loop_crossfade_shrinks_to_region() {
+        let mut shifter = ramp_shifter();
+
+        // Nothing before the loop start to fade from
+        shifter.set_loop(Some(LoopRegion::new(0., 20., 4.)));
+        assert_eq!(shifter.get_frame(18.), Some(FrameOutput::Mono(18.)));
+
+        // Longer than the loop, the fade covers the whole region
+        shifter.set_loop(Some(LoopRegion::new(30., 32., 10.)));
+        assert_mono(shifter.get_frame(30.), 30.);
+        assert_mono(shifter.get_frame(31.), (31. + 29.) * HALF_POWER_GAIN);
+    }
+
+    #[test]
+    fn test_hermite_keeps_dc_constant() {
+        let mut shifter = ClassicShifter::new();
+        shifter
+            .load_sample(&Arc::new(vec![0.5; 32]), 1, 48000.)
+            .unwrap();
+        shifter.set_interpolation(Interpolation::Hermite);
+        shifter.trigger(1., 7.);
+
+        // Every fraction, including next to both edges
+        for position in [0., 0.3, 1.7, 10.25, 19.9, 20.6] {
+            assert_mono(shifter.get_frame(position), 0.5);
+        }
+    }
+
+    #[test]
+    fn test_hermite_goes_through_samples() {
+        let mut shifter = ramp_shifter();
+        shifter.set_interpolation(Interpolation::Hermite);
+        assert_mono(shifter.get_frame(10.), 10.);
+
+        // A ramp is a cubic too
+        assert_mono(shifter.get_frame(10.5), 10.5);
+    }
+
+    #[test]
+    fn test_high_quality_filters_pitch_up() {
+        // Nyquist of the source, an octave up it folds back to DC
+        let nyquist: Vec<f32> = (0..200)
+            .map(|i| if i % 2 == 0 { 1. } else { -1. })
+            .collect();
+        let mut shifter = ClassicShifter::new();
+        shifter.load_sample(&Arc::new(nyquist), 1, 48000.).unwrap();
+        shifter.trigger(1., 12.);
+        assert_mono(shifter.get_frame(50.), 1.);
+
+        shifter.set_high_quality(true);
+        shifter.trigger(1., 12.);
+        match shifter.get_frame(50.) {
+            Some(FrameOutput::Mono(v)) => assert!(v.abs() < 0.1, "{} isn't filtered", v),
+            other => panic!("Unexpected frame {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_bend_keeps_playhead() {
+        let mut shifter = ramp_shifter();
+        assert_eq!(shifter.get_frame(10.), Some(FrameOutput::Mono(10.)));
+
+        // An octave up from there reads twice as fast
+        shifter.bend(12.);
+        assert_mono(shifter.get_frame(11.), 12.);
+        assert_mono(shifter.get_frame(15.), 20.);
+
+        // Back to the note rate, from where it is
+        shifter.bend(0.);
+        assert_mono(shifter.get_frame(16.), 21.);
     }
 }
@@ -1,15 +1,46 @@
 pub mod classic;
 pub mod psola;
 
+use std::sync::Arc;
+
 use nih_plug::prelude::Enum;
 
+use crate::pitch_shift::psola::PsolaAnalysis;
+
 #[derive(Debug, PartialEq, Enum)]
 pub enum PitchShiftKind {
     Classic,
     Psola,
 }
 
-#[derive(Debug, PartialEq)]
+/// Why a shifter couldn't process a sample as intended
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PitchShiftError {
+    /// No fundamental was found, the sample is played with the classic playback rate
+    PitchNotDetected,
+}
+
+impl std::fmt::Display for PitchShiftError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PitchShiftError::PitchNotDetected => {
+                write!(f, "pitch detection failed, using classic")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PitchShiftError {}
+
+/// How the classic shifter reads between two samples
+#[derive(Debug, PartialEq, Clone, Copy, Enum)]
+pub enum Interpolation {
+    Linear,
+    #[name = "Cubic"]
+    Hermite,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum FrameOutput {
     Mono(f32),
     Stereo([f32; 2]),
@@ -28,6 +59,26 @@ impl From<Vec<f32>> for FrameOutput {
     }
 }
 
+/// A region of the sample played in loop, in frames of the sample.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct LoopRegion {
+    pub start: f32,
+    pub end: f32,
+
+    /// Length of the equal power crossfade before the loop end
+    pub crossfade: f32,
+}
+
+impl LoopRegion {
+    pub fn new(start: f32, end: f32, crossfade: f32) -> Self {
+        Self {
+            start,
+            end,
+            crossfade,
+        }
+    }
+}
+
 /// A trait for audio pitch shifting implementations.
 ///
 /// This trait defines the interface for pitch shifting algorithms that can load audio samples,
@@ -61,8 +112,9 @@ pub trait PitchShifter {
     /// # Behavior
     ///
     /// - Analyzes the input audio for pitch detection and preprocessing
-    /// - May fail silently if pitch detection fails (implementation-dependent)
-    /// - After successful loading, the shifter is ready to be triggered
+    /// - Returns an error if the sample can't be processed as the algorithm intends,
+    ///   the shifter may still play it another way (implementation-dependent)
+    /// - After loading, the shifter is ready to be triggered
     /// - Calling this method replaces any previously loaded sample
     ///
     /// # Notes
@@ -72,7 +124,27 @@ pub trait PitchShifter {
     /// - `sample_buffer[1]` = first right channel sample  
     /// - `sample_buffer[2]` = second left channel sample
     /// - And so on...
-    fn load_sample(&mut self, sample_buffer: &[f32], channel_number: usize, sample_rate: f32);
+    fn load_sample(
+        &mut self,
+        sample_buffer: &Arc<Vec<f32>>,
+        channel_number: usize,
+        sample_rate: f32,
+    ) -> Result<(), PitchShiftError>;
+
+    /// Loads a sample along with its analysis, computed beforehand off the audio thread.
+    ///
+    /// Shifters that don't use the analysis just load its sample,
+    /// the analysis can be shared with other shifters playing the same sample.
+    fn load_analysed_sample(
+        &mut self,
+        analysis: &Arc<PsolaAnalysis>,
+    ) -> Result<(), PitchShiftError> {
+        self.load_sample(
+            analysis.sample_buffer(),
+            analysis.channel_number(),
+            analysis.sample_rate(),
+        )
+    }
 
     /// Triggers playback with specified pitch and timing parameters.
     ///
@@ -161,4 +233,47 @@ pub trait PitchShifter {
     /// Return the position in frame number of the pitch shifter
     /// since sample started to play
     fn get_position(&self, position: f32) -> f32;
+
+    /// Returns the output gain correction for this algorithm.
+    ///
+    /// Algorithms don't have the same inherent gain, this trim is applied to
+    /// the output so switching the pitch shift kind doesn't change the loudness.
+    fn gain_trim(&self) -> f32 {
+        1.0
+    }
+
+    /// Enables the more expensive processing of the algorithm, if it has any.
+    ///
+    /// Called before `trigger()`, so the setting is fixed for a whole playback.
+    fn set_high_quality(&mut self, _enabled: bool) {}
+
+    /// Keeps the spectral envelope of the sample when shifting, if the algorithm can.
+    ///
+    /// Called before `trigger()`, like `set_high_quality()`.
+    fn set_preserve_formants(&mut self, _enabled: bool) {}
+
+    /// Sets how the samples are read between two frames of the sample, if the algorithm reads them.
+    ///
+    /// Called before `trigger()`, like `set_high_quality()`.
+    fn set_interpolation(&mut self, _interpolation: Interpolation) {}
+
+    /// Returns the fundamental frequency found in the sample, for the algorithms that detect it.
+    fn detected_frequency(&self) -> Option<f32> {
+        None
+    }
+
+    /// Bends the pitch of the playing note by `semitones`, without retriggering it.
+    ///
+    /// The playhead doesn't jump, only the speed it moves at changes. Algorithms that
+    /// render the whole note when triggered read it faster or slower, so the bend
+    /// changes its length too.
+    fn bend(&mut self, _semitones: f32) {}
+
+    /// Sets the region looped while the note is held.
+    ///
+    /// The playhead wraps back to the start of the region each time it passes its end,
+    /// the crossfade is shortened if the region can't fit it.
+    /// With `None` the playback continues straight from where it is. Algorithms
+    /// without loop support ignore it.
+    fn set_loop(&mut self, _region: Option<LoopRegion>) {}
 }
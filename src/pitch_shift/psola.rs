@@ -1,47 +1,183 @@
+use std::sync::Arc;
+
 use nih_plug::nih_error;
 use pitch_detection::detector::mcleod::McLeodDetector;
 use pitch_detection::detector::PitchDetector;
 use tdpsola::{AlternatingHann, Speed, TdpsolaAnalysis, TdpsolaSynthesis};
 
 use crate::{
-    pitch_shift::{FrameOutput, PitchShiftKind, PitchShifter},
+    pitch_shift::{
+        classic::ClassicShifter, FrameOutput, Interpolation, LoopRegion, PitchShiftError,
+        PitchShiftKind, PitchShifter,
+    },
     utils,
 };
 
-const POWER_THRESHOLD: f32 = 5.0;
-const CLARITY_THRESHOLD: f32 = 0.1;
+/// Default thresholds of the pitch detection
+const DEFAULT_POWER_THRESHOLD: f32 = 5.0;
+const DEFAULT_CLARITY_THRESHOLD: f32 = 0.1;
 
-pub struct PsolaShifter {
+/// Bounds of the measured gain trim, avoid huge corrections on near silent outputs
+const MIN_GAIN_TRIM: f32 = 0.25;
+const MAX_GAIN_TRIM: f32 = 4.0;
+
+/// Highest upward shift with its own formant analysis, in semitones,
+/// higher notes use the analysis of this shift
+pub const MAX_FORMANT_SEMITONES: usize = 12;
+
+/// Largest shift, up or down in semitones, with its own measured gain trim,
+/// further shifts use the trim of this one
+const MAX_TRIM_SEMITONES: i32 = 24;
+
+/// Analysis of every channel of the sample, for one wavelength
+struct Analysis {
     _hanns: Vec<AlternatingHann>,
     analysis: Vec<TdpsolaAnalysis>,
-    synthesis: Option<Vec<TdpsolaSynthesis>>,
-    iter_samples: Option<Vec<Vec<f32>>>,
-    source_length: f32,
-    is_loaded: bool,
-    sr_correction: f32,
-    playback_rate: f32,
+
+    /// Number of silent samples pushed before the sample
+    padding_length: usize,
 }
 
-impl PsolaShifter {
-    pub fn new() -> Self {
+impl Analysis {
+    /// Synthesizes every channel at the target period, without the padding
+    fn render(&self, sr_correction: f32, target_length: f32) -> Vec<Vec<f32>> {
+        self.analysis
+            .iter()
+            .map(|analysis| {
+                TdpsolaSynthesis::new(Speed::from_f32(sr_correction), target_length)
+                    .iter(analysis)
+                    .skip(self.padding_length)
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Gain bringing the output at the target period back to the level of the source,
+    /// so PSOLA plays at the same loudness as the classic shifter
+    fn measure_gain_trim(&self, source_rms: f32, target_length: f32) -> f32 {
+        let output = self.render(1., target_length);
+        let trim = source_rms / utils::rms(output.iter().flatten());
+        if trim.is_finite() {
+            trim.clamp(MIN_GAIN_TRIM, MAX_GAIN_TRIM)
+        } else {
+            1.0
+        }
+    }
+
+    fn new(sample_buffer: &[f32], channel_number: usize, wavelength: f32) -> Self {
+        let padding_length = wavelength as usize + 1;
+
+        let mut hanns: Vec<AlternatingHann> = (0..channel_number)
+            .map(|_| AlternatingHann::new(wavelength))
+            .collect();
+
+        let mut analysis = hanns.iter().map(TdpsolaAnalysis::new).collect::<Vec<_>>();
+
+        for (channel, (analys, hann)) in analysis.iter_mut().zip(hanns.iter_mut()).enumerate() {
+            for _ in 0..padding_length {
+                analys.push_sample(0.0, hann);
+            }
+            for sample in sample_buffer.iter().skip(channel).step_by(channel_number) {
+                analys.push_sample(*sample, hann);
+            }
+        }
+
         Self {
-            _hanns: Vec::new(),
-            analysis: Vec::new(),
-            synthesis: None,
-            iter_samples: None,
-            source_length: 0.0,
-            is_loaded: false,
-            sr_correction: 1.0,
-            playback_rate: 1.0,
+            _hanns: hanns,
+            analysis,
+            padding_length,
         }
     }
+}
 
-    fn build_internal(
-        &mut self,
-        sample_buffer: &[f32],
+/// A pitch detected in the sample and the analysis at its wavelength
+struct DetectedPitch {
+    frequency: f32,
+    wavelength: f32,
+    analysis: Analysis,
+
+    /// Analyses cut at the target period of each semitone shifted up, starting at one,
+    /// empty unless they were precomputed with `PsolaAnalysis::with_formants`
+    formants: Vec<Analysis>,
+
+    /// Gain trim of each semitone shift from `-MAX_TRIM_SEMITONES`, measured once with the
+    /// analysis so the notes don't render their output twice
+    gain_trims: Vec<f32>,
+
+    /// Gain trim of each formant analysis, at its own shift
+    formant_trims: Vec<f32>,
+}
+
+impl DetectedPitch {
+    /// Returns the formant analysis of the nearest semitone to an upward shift, if any
+    fn formant_analysis(&self, playback_rate: f32) -> Option<&Analysis> {
+        let semitones = (12. * playback_rate.log2()).round().max(1.) as usize;
+        self.formants
+            .get(semitones.min(self.formants.len()).checked_sub(1)?)
+    }
+
+    /// Returns the gain trim of a shift, interpolated between the measured semitones.
+    ///
+    /// The notes using a formant analysis take its own trim instead.
+    fn gain_trim(&self, playback_rate: f32, formants: bool) -> f32 {
+        let semitones = 12. * playback_rate.log2();
+        if formants {
+            let index = (semitones.round().max(1.) as usize).min(self.formant_trims.len());
+            if let Some(trim) = index.checked_sub(1).and_then(|i| self.formant_trims.get(i)) {
+                return *trim;
+            }
+        }
+
+        let max = MAX_TRIM_SEMITONES as f32;
+        let position = semitones.clamp(-max, max) + max;
+        let index = position as usize;
+        let Some(&current) = self.gain_trims.get(index) else {
+            return 1.0;
+        };
+        let next = self.gain_trims.get(index + 1).copied().unwrap_or(current);
+        utils::interpolate(current, next, position.fract())
+    }
+}
+
+/// Pitch detection and analysis of a sample.
+///
+/// This is the expensive part of loading a sample, so it can be computed off the
+/// audio thread and shared by every shifter playing the sample.
+pub struct PsolaAnalysis {
+    /// Interleaved sample, kept to analyse it again when preserving formants
+    sample_buffer: Arc<Vec<f32>>,
+    channel_number: usize,
+    sample_rate: f32,
+
+    /// Pitch detection thresholds, see `McLeodDetector::get_pitch`
+    power_threshold: f32,
+    clarity_threshold: f32,
+
+    /// `None` when the pitch couldn't be detected
+    pitch: Option<DetectedPitch>,
+    source_rms: f32,
+}
+
+impl PsolaAnalysis {
+    /// Analyses a sample with the default pitch detection thresholds
+    pub fn new(sample_buffer: &Arc<Vec<f32>>, channel_number: usize, sample_rate: f32) -> Self {
+        Self::with_thresholds(
+            sample_buffer,
+            channel_number,
+            sample_rate,
+            DEFAULT_POWER_THRESHOLD,
+            DEFAULT_CLARITY_THRESHOLD,
+        )
+    }
+
+    fn with_thresholds(
+        sample_buffer: &Arc<Vec<f32>>,
         channel_number: usize,
         sample_rate: f32,
-    ) -> bool {
+        power_threshold: f32,
+        clarity_threshold: f32,
+    ) -> Self {
+        let source_rms = utils::rms(sample_buffer.iter());
         let scratch_size = sample_buffer.len() * 2;
         let single_channel = sample_buffer
             .iter()
@@ -51,105 +187,352 @@ impl PsolaShifter {
 
         let mut detector = McLeodDetector::new(single_channel.len(), scratch_size);
 
-        if let Some(pitch) = detector.get_pitch(
-            &single_channel,
-            sample_rate as usize,
-            POWER_THRESHOLD,
-            CLARITY_THRESHOLD,
-        ) {
-            nih_plug::nih_log!("Detected frequency {}", pitch.frequency);
-            let source_wavelength = sample_rate / pitch.frequency;
-            let padding_length = source_wavelength as usize + 1;
-
-            let mut hanns: Vec<AlternatingHann> = (0..channel_number)
-                .map(|_| AlternatingHann::new(source_wavelength))
-                .collect();
-
-            let mut analysis = hanns.iter().map(TdpsolaAnalysis::new).collect::<Vec<_>>();
-
-            for (channel, (analys, hann)) in analysis.iter_mut().zip(hanns.iter_mut()).enumerate() {
-                for _ in 0..padding_length {
-                    analys.push_sample(0.0, hann);
-                }
-                for sample in sample_buffer.iter().skip(channel).step_by(channel_number) {
-                    analys.push_sample(*sample, hann);
+        let pitch = detector
+            .get_pitch(
+                &single_channel,
+                sample_rate as usize,
+                power_threshold,
+                clarity_threshold,
+            )
+            .map(|pitch| {
+                nih_plug::nih_log!("Detected frequency {}", pitch.frequency);
+                let wavelength = sample_rate / pitch.frequency;
+                let analysis = Analysis::new(sample_buffer, channel_number, wavelength);
+                let gain_trims = (-MAX_TRIM_SEMITONES..=MAX_TRIM_SEMITONES)
+                    .map(|semitones| {
+                        let playback_rate =
+                            utils::semitone_offset_to_playback_rate(semitones as f32);
+                        analysis.measure_gain_trim(source_rms, wavelength / playback_rate)
+                    })
+                    .collect();
+                DetectedPitch {
+                    frequency: pitch.frequency,
+                    wavelength,
+                    analysis,
+                    formants: Vec::new(),
+                    gain_trims,
+                    formant_trims: Vec::new(),
                 }
-            }
+            });
 
-            self._hanns = hanns;
-            self.analysis = analysis;
-            self.synthesis = None;
-            self.iter_samples = None;
-            self.source_length = source_wavelength;
-            self.is_loaded = true;
+        Self {
+            sample_buffer: Arc::clone(sample_buffer),
+            channel_number,
+            sample_rate,
+            power_threshold,
+            clarity_threshold,
+            pitch,
+            source_rms,
+        }
+    }
 
-            true
-        } else {
-            // Pitch detection failed
-            self.clear_sample();
-            nih_error!("Error: couldn't detect pitch");
-            false
+    /// Precomputes the analyses used to preserve the formants when shifting up,
+    /// one per semitone up to `MAX_FORMANT_SEMITONES`. This is as slow as the
+    /// analysis itself for each of them.
+    pub fn with_formants(mut self) -> Self {
+        if let Some(pitch) = self.pitch.as_mut() {
+            let wavelength = pitch.wavelength;
+            (pitch.formants, pitch.formant_trims) = (1..=MAX_FORMANT_SEMITONES)
+                .map(|semitones| {
+                    let playback_rate = utils::semitone_offset_to_playback_rate(semitones as f32);
+                    let target_length = wavelength / playback_rate;
+                    let analysis =
+                        Analysis::new(&self.sample_buffer, self.channel_number, target_length);
+                    let trim = analysis.measure_gain_trim(self.source_rms, target_length);
+                    (analysis, trim)
+                })
+                .unzip();
         }
+        self
+    }
+
+    /// Returns whether this is the analysis of the buffer, with the same settings
+    fn matches(
+        &self,
+        sample_buffer: &Arc<Vec<f32>>,
+        channel_number: usize,
+        sample_rate: f32,
+    ) -> bool {
+        Arc::ptr_eq(&self.sample_buffer, sample_buffer)
+            && self.channel_number == channel_number
+            && self.sample_rate == sample_rate
+    }
+
+    pub fn sample_buffer(&self) -> &Arc<Vec<f32>> {
+        &self.sample_buffer
+    }
+
+    pub fn channel_number(&self) -> usize {
+        self.channel_number
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    /// Returns the detected frequency, `None` if the detection failed
+    pub fn frequency(&self) -> Option<f32> {
+        self.pitch.as_ref().map(|pitch| pitch.frequency)
+    }
+}
+
+impl std::fmt::Debug for PsolaAnalysis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PsolaAnalysis")
+            .field("channel_number", &self.channel_number)
+            .field("sample_rate", &self.sample_rate)
+            .field("frequency", &self.frequency())
+            .finish_non_exhaustive()
+    }
+}
+
+pub struct PsolaShifter {
+    /// Analysis of the loaded sample, may be shared with other shifters
+    analysis: Option<Arc<PsolaAnalysis>>,
+    iter_samples: Option<Vec<Vec<f32>>>,
+    preserve_formants: bool,
+
+    /// Pitch detection thresholds, see `McLeodDetector::get_pitch`
+    power_threshold: f32,
+    clarity_threshold: f32,
+
+    /// Plays the sample like the classic shifter when its pitch can't be detected
+    fallback: Option<ClassicShifter>,
+    sr_correction: f32,
+    playback_rate: f32,
+    gain_trim: f32,
+
+    /// Speed the rendered note is read at, bent with `bend`
+    bend_rate: f32,
+
+    /// Last position read, the bend keeps the playhead there
+    last_position: f32,
+
+    /// Shift of the positions keeping the playhead continuous across bends
+    rewind: f32,
+}
+
+impl PsolaShifter {
+    pub fn new() -> Self {
+        Self::with_thresholds(DEFAULT_POWER_THRESHOLD, DEFAULT_CLARITY_THRESHOLD)
+    }
+
+    /// Creates a shifter with custom thresholds for the pitch detection,
+    /// lower values detect a pitch on noisier samples but may pick a wrong one
+    pub fn with_thresholds(power_threshold: f32, clarity_threshold: f32) -> Self {
+        Self {
+            analysis: None,
+            iter_samples: None,
+            preserve_formants: false,
+            power_threshold,
+            clarity_threshold,
+            fallback: None,
+            sr_correction: 1.0,
+            playback_rate: 1.0,
+            gain_trim: 1.0,
+            bend_rate: 1.0,
+            last_position: 0.0,
+            rewind: 0.0,
+        }
+    }
+
+    /// Returns whether an analysis was made with the thresholds of this shifter
+    fn accepts(&self, analysis: &PsolaAnalysis) -> bool {
+        analysis.power_threshold == self.power_threshold
+            && analysis.clarity_threshold == self.clarity_threshold
+    }
+
+    /// Returns the status of the loaded sample
+    fn status(&self) -> Result<(), PitchShiftError> {
+        match self.fallback {
+            Some(_) => Err(PitchShiftError::PitchNotDetected),
+            None => Ok(()),
+        }
+    }
+
+    /// Replaces the loaded sample by an analysed one
+    fn set_analysis(&mut self, analysis: Arc<PsolaAnalysis>) -> Result<(), PitchShiftError> {
+        self.clear_sample();
+        if analysis.pitch.is_none() {
+            // Pitch detection failed, the sample is still played with the classic
+            // playback rate rather than staying silent
+            nih_error!("Error: couldn't detect pitch, falling back to classic playback");
+            let mut fallback = ClassicShifter::new();
+            fallback
+                .load_sample(
+                    analysis.sample_buffer(),
+                    analysis.channel_number(),
+                    analysis.sample_rate(),
+                )
+                .ok();
+            self.fallback = Some(fallback);
+        }
+        self.analysis = Some(analysis);
+
+        let status = self.status();
+        if status.is_err() {
+            nih_error!("Error while setting up pitch shifter {:?}", self.kind());
+        }
+        status
     }
 }
 
 impl PitchShifter for PsolaShifter {
     fn clear_sample(&mut self) {
-        self._hanns.clear();
-        self.analysis.clear();
-        self.synthesis = None;
+        self.fallback = None;
+        self.analysis = None;
         self.iter_samples = None;
-        self.source_length = 0.0;
-        self.is_loaded = false;
+        self.gain_trim = 1.0;
     }
 
-    fn load_sample(&mut self, sample_buffer: &[f32], channel_number: usize, sample_rate: f32) {
-        if !self.build_internal(sample_buffer, channel_number, sample_rate) {
-            nih_error!("Error while setting up pitch shifter {:?}", self.kind());
+    fn load_sample(
+        &mut self,
+        sample_buffer: &Arc<Vec<f32>>,
+        channel_number: usize,
+        sample_rate: f32,
+    ) -> Result<(), PitchShiftError> {
+        // The same buffer keeps its analysis
+        if let Some(analysis) = self.analysis.as_ref() {
+            if analysis.matches(sample_buffer, channel_number, sample_rate)
+                && self.accepts(analysis)
+            {
+                return self.status();
+            }
+        }
+
+        let analysis = PsolaAnalysis::with_thresholds(
+            sample_buffer,
+            channel_number,
+            sample_rate,
+            self.power_threshold,
+            self.clarity_threshold,
+        );
+        self.set_analysis(Arc::new(analysis))
+    }
+
+    fn load_analysed_sample(
+        &mut self,
+        analysis: &Arc<PsolaAnalysis>,
+    ) -> Result<(), PitchShiftError> {
+        if !self.accepts(analysis) {
+            return self.load_sample(
+                analysis.sample_buffer(),
+                analysis.channel_number(),
+                analysis.sample_rate(),
+            );
         }
+        if let Some(current) = self.analysis.as_ref() {
+            if Arc::ptr_eq(current, analysis) {
+                return self.status();
+            }
+        }
+        self.set_analysis(Arc::clone(analysis))
     }
 
     fn trigger(&mut self, sr_correction: f32, semitone_offset: f32) {
-        if !self.is_loaded {
+        if let Some(fallback) = self.fallback.as_mut() {
+            fallback.trigger(sr_correction, semitone_offset);
             return;
         }
+        let Some(pitch) = self
+            .analysis
+            .as_ref()
+            .and_then(|source| source.pitch.as_ref())
+        else {
+            return;
+        };
 
         self.playback_rate = utils::semitone_offset_to_playback_rate(semitone_offset);
         self.sr_correction = sr_correction;
+        self.bend_rate = 1.0;
+        self.last_position = 0.0;
+        self.rewind = 0.0;
+        let target_length = pitch.wavelength / self.playback_rate;
 
-        // Create NEW synthesis objects each time - analysis stays intact!
-        let mut synthesis: Vec<TdpsolaSynthesis> = (0..self._hanns.len())
-            .map(|_| {
-                TdpsolaSynthesis::new(
-                    Speed::from_f32(sr_correction),
-                    self.source_length / self.playback_rate,
-                )
-            })
-            .collect();
+        // Grains two source periods long overlap more and more when shifting up,
+        // their sum brings the source periodicity back and the formants follow the pitch.
+        // Cutting grains at the target period keeps only the spectral envelope, these
+        // analyses are precomputed with the sample, the formants follow until they are.
+        let formant_analysis = if self.preserve_formants && self.playback_rate > 1. {
+            pitch.formant_analysis(self.playback_rate)
+        } else {
+            None
+        };
+        let analysis = formant_analysis.unwrap_or(&pitch.analysis);
 
-        // Now you can use the same analysis with new synthesis
-        self.iter_samples = Some(
-            synthesis
-                .iter_mut()
-                .zip(self.analysis.iter())
-                .map(|(s, a)| s.iter(a).skip(self.source_length as usize + 1).collect())
-                .collect(),
-        );
-        self.synthesis = Some(synthesis);
+        // The analysis stays intact, only the synthesis is rendered again
+        self.iter_samples = Some(analysis.render(sr_correction, target_length));
+        self.gain_trim = pitch.gain_trim(self.playback_rate, formant_analysis.is_some());
+    }
+
+    fn set_preserve_formants(&mut self, enabled: bool) {
+        self.preserve_formants = enabled;
+    }
+
+    fn set_high_quality(&mut self, enabled: bool) {
+        if let Some(fallback) = self.fallback.as_mut() {
+            fallback.set_high_quality(enabled);
+        }
+    }
+
+    fn set_interpolation(&mut self, interpolation: Interpolation) {
+        if let Some(fallback) = self.fallback.as_mut() {
+            fallback.set_interpolation(interpolation);
+        }
+    }
+
+    fn bend(&mut self, semitones: f32) {
+        if let Some(fallback) = self.fallback.as_mut() {
+            fallback.bend(semitones);
+            return;
+        }
+
+        // The note is already rendered, it is read faster or slower
+        let bend_rate = utils::semitone_offset_to_playback_rate(semitones);
+        if bend_rate == self.bend_rate {
+            return;
+        }
+
+        // The playhead must not jump, only the speed from here changes
+        self.rewind =
+            self.last_position - (self.last_position - self.rewind) * self.bend_rate / bend_rate;
+        self.bend_rate = bend_rate;
+    }
+
+    fn set_loop(&mut self, region: Option<LoopRegion>) {
+        if let Some(fallback) = self.fallback.as_mut() {
+            fallback.set_loop(region);
+        }
+    }
+
+    fn detected_frequency(&self) -> Option<f32> {
+        self.analysis.as_ref()?.frequency()
     }
 
     fn ready(&self) -> bool {
-        self.is_loaded && self.iter_samples.is_some()
+        if let Some(fallback) = self.fallback.as_ref() {
+            return fallback.ready();
+        }
+        self.iter_samples.is_some()
     }
 
     fn get_frame(&mut self, position: f32) -> Option<FrameOutput> {
+        if let Some(fallback) = self.fallback.as_mut() {
+            return fallback.get_frame(position);
+        }
+        self.last_position = position;
+
+        // A bent note is read between the rendered samples
+        let read_position = self.get_position(position).max(0.);
+        let index = read_position as usize;
+        let fraction = read_position.fract();
         self.iter_samples
             .as_ref()?
             .iter()
             .map(|channels| {
-                channels
-                    .get((position * self.sr_correction) as usize)
-                    .copied()
+                let current = *channels.get(index)?;
+                let next = channels.get(index + 1).copied().unwrap_or(current);
+                Some(current + (next - current) * fraction)
             })
             .collect::<Option<Vec<_>>>()
             .map(|v| v.into())
@@ -160,6 +543,177 @@ impl PitchShifter for PsolaShifter {
     }
 
     fn get_position(&self, position: f32) -> f32 {
-        self.sr_correction * position
+        if let Some(fallback) = self.fallback.as_ref() {
+            return fallback.get_position(position);
+        }
+        self.sr_correction * (position - self.rewind) * self.bend_rate
+    }
+
+    fn gain_trim(&self) -> f32 {
+        if let Some(fallback) = self.fallback.as_ref() {
+            return fallback.gain_trim();
+        }
+        self.gain_trim
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.;
+
+    /// A 110Hz tone with a resonance around 1kHz, like a vowel
+    fn formant_tone() -> Arc<Vec<f32>> {
+        let fundamental = 110.;
+        let envelope = |frequency: f32| 1. + 4. * (-((frequency - 1000.) / 300.).powi(2)).exp();
+        Arc::new(
+            (0..24000)
+                .map(|i| {
+                    let time = i as f32 / SAMPLE_RATE;
+                    (1..=40)
+                        .map(|harmonic| {
+                            let frequency = fundamental * harmonic as f32;
+                            envelope(frequency) / harmonic as f32
+                                * (std::f32::consts::TAU * frequency * time).sin()
+                        })
+                        .sum::<f32>()
+                        * 0.1
+                })
+                .collect(),
+        )
+    }
+
+    /// Magnitude weighted mean frequency, on a 25Hz grid up to 5kHz
+    fn spectral_centroid(samples: &[f32]) -> f32 {
+        let (weighted, total) = (2..=200)
+            .map(|bin| {
+                let frequency = bin as f32 * 25.;
+                let (re, im) = samples
+                    .iter()
+                    .enumerate()
+                    .fold((0., 0.), |(re, im), (i, v)| {
+                        let phase = std::f32::consts::TAU * frequency * i as f32 / SAMPLE_RATE;
+                        (re + v * phase.cos(), im + v * phase.sin())
+                    });
+                (frequency, (re * re + im * im).sqrt())
+            })
+            .fold((0., 0.), |(weighted, total), (frequency, magnitude)| {
+                (weighted + frequency * magnitude, total + magnitude)
+            });
+        weighted / total
+    }
+
+    /// Plays the whole note an octave up
+    fn render_octave_up(analysis: &Arc<PsolaAnalysis>, preserve_formants: bool) -> Vec<f32> {
+        let mut shifter = PsolaShifter::new();
+        shifter.load_analysed_sample(analysis).unwrap();
+        shifter.set_preserve_formants(preserve_formants);
+        shifter.trigger(1., 12.);
+        (0..)
+            .map_while(|position| match shifter.get_frame(position as f32) {
+                Some(FrameOutput::Mono(v)) => Some(v),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_preserve_formants_keeps_spectral_envelope() {
+        let source = formant_tone();
+        let analysis = Arc::new(PsolaAnalysis::new(&source, 1, SAMPLE_RATE).with_formants());
+        assert!(analysis.frequency().is_some());
+
+        let source_centroid = spectral_centroid(&source);
+        let shifted = spectral_centroid(&render_octave_up(&analysis, false));
+        let preserved = spectral_centroid(&render_octave_up(&analysis, true));
+
+        // The formants follow the pitch unless they are preserved
+        assert!(
+            (preserved - source_centroid).abs() < (shifted - source_centroid).abs(),
+            "source {} shifted {} preserved {}",
+            source_centroid,
+            shifted,
+            preserved
+        );
+    }
+
+    #[test]
+    fn test_bend_keeps_playhead() {
+        let analysis = Arc::new(PsolaAnalysis::new(&formant_tone(), 1, SAMPLE_RATE));
+        let mut shifter = PsolaShifter::new();
+        shifter.load_analysed_sample(&analysis).unwrap();
+        shifter.trigger(1., 0.);
+        assert!(shifter.get_frame(100.).is_some());
+
+        // An octave up reads the rendered note twice as fast from where it was
+        shifter.bend(12.);
+        assert!((shifter.get_position(100.) - 100.).abs() < 1e-3);
+        assert!((shifter.get_position(110.) - 120.).abs() < 1e-3);
+
+        // Retriggering the note clears the bend
+        shifter.trigger(1., 0.);
+        assert_eq!(shifter.get_position(110.), 110.);
+    }
+
+    #[test]
+    fn test_formant_analysis_is_nearest_semitone() {
+        let source = formant_tone();
+        let analysis = PsolaAnalysis::new(&source, 1, SAMPLE_RATE);
+        let pitch = analysis.pitch.as_ref().unwrap();
+        assert!(pitch.formant_analysis(2.).is_none());
+
+        let analysis = analysis.with_formants();
+        let pitch = analysis.pitch.as_ref().unwrap();
+        let expected = |semitones: usize| &pitch.formants[semitones - 1] as *const Analysis;
+        let found =
+            |playback_rate: f32| pitch.formant_analysis(playback_rate).unwrap() as *const Analysis;
+        assert_eq!(found(2.), expected(12));
+        assert_eq!(
+            found(utils::semitone_offset_to_playback_rate(3.2)),
+            expected(3)
+        );
+        assert_eq!(found(8.), expected(MAX_FORMANT_SEMITONES));
+    }
+
+    #[test]
+    fn test_reloading_the_same_buffer_keeps_the_analysis() {
+        let source = formant_tone();
+        let mut shifter = PsolaShifter::new();
+        shifter.load_sample(&source, 1, SAMPLE_RATE).unwrap();
+        let analysis = shifter.analysis.clone().unwrap();
+
+        shifter.load_sample(&source, 1, SAMPLE_RATE).unwrap();
+        assert!(Arc::ptr_eq(&analysis, shifter.analysis.as_ref().unwrap()));
+
+        // Another buffer is analysed again, even with the same content
+        let copy = Arc::new(source.to_vec());
+        shifter.load_sample(&copy, 1, SAMPLE_RATE).unwrap();
+        assert!(!Arc::ptr_eq(&analysis, shifter.analysis.as_ref().unwrap()));
+    }
+
+    #[test]
+    fn test_gain_trim_is_measured_with_the_analysis() {
+        let analysis = Arc::new(PsolaAnalysis::new(&formant_tone(), 1, SAMPLE_RATE));
+        let pitch = analysis.pitch.as_ref().unwrap();
+        let unshifted = MAX_TRIM_SEMITONES as usize;
+        assert_eq!(pitch.gain_trims.len(), 2 * unshifted + 1);
+
+        // A note only looks its trim up, between semitones it is interpolated
+        let mut shifter = PsolaShifter::new();
+        shifter.load_analysed_sample(&analysis).unwrap();
+        shifter.trigger(1., 0.);
+        assert_eq!(shifter.gain_trim(), pitch.gain_trims[unshifted]);
+        shifter.trigger(1., 0.5);
+        let between = (pitch.gain_trims[unshifted] + pitch.gain_trims[unshifted + 1]) / 2.;
+        assert!((shifter.gain_trim() - between).abs() < 1e-4);
+
+        // The unshifted output is measured at the level of the source
+        let output = pitch.analysis.render(1., pitch.wavelength);
+        let measured = analysis.source_rms / utils::rms(output.iter().flatten());
+        assert!(
+            (pitch.gain_trims[unshifted] - measured.clamp(MIN_GAIN_TRIM, MAX_GAIN_TRIM)).abs()
+                < 1e-6
+        );
     }
 }
@@ -1,16 +1,33 @@
 use nih_plug::prelude::*;
 use std::num::NonZero;
-use std::sync::atomic::Ordering;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::Arc;
 
-use crate::params::{HardKickSamplerParams, MAX_SAMPLES};
-use crate::sample_wrapper::SamplePlayer;
+use crate::limiter::Limiter;
+use crate::midi_learn::{CcTarget, MidiLearn};
+use crate::params::{HardKickSamplerParams, TriggerMode, MAX_SAMPLES, NUM_OUTPUT_BUSES};
+use crate::presets;
+use crate::sample_wrapper::{push_load_history, SamplePlayer};
+use crate::saturation::Saturator;
 use crate::shared_states::SharedStates;
 use crate::tasks::{TaskRequests, TaskResults};
 use crate::utils;
 
 pub const DEFAULT_BPM: f64 = 150.;
 
+/// Output gain when dim is enabled (-20dB)
+const DIM_GAIN: f32 = 0.1;
+
+/// Time for the mute / dim to fade in and out, in ms
+const MONITOR_SMOOTHING_MS: f32 = 20.;
+
+/// The send, then the output buses
+const NUM_AUX_OUTPUTS: usize = 1 + NUM_OUTPUT_BUSES;
+
+/// Names of the aux outputs, the array length makes it fail to compile when a bus is added
+const AUX_OUTPUT_NAMES: [&str; NUM_AUX_OUTPUTS] = ["Send", "Bus 1", "Bus 2", "Bus 3", "Bus 4"];
+
 pub struct HardKickSampler {
     // Params of the plugin
     params: Arc<HardKickSamplerParams>,
@@ -21,14 +38,43 @@ pub struct HardKickSampler {
     // The task receiver
     receiver: Option<std::sync::mpsc::Receiver<TaskResults>>,
 
-    // the number of blocks processed since note started
-    // This technically could be problematic to have f32
-    // If samples last more than 5 minutes but since
-    // It's mean to get kick parts, it should be okay
-    process_count: f32,
-
     // The BPM given by the host
     host_bpm: Arc<AtomicF32>,
+
+    // Whether any sample is playing, shared with the GUI
+    active: Arc<AtomicBool>,
+
+    // Set by the GUI, plays a tick at the start of every sample.
+    // This is a monitoring aid, it is never rendered offline
+    alignment_tick: Arc<AtomicBool>,
+
+    // Realtime or offline rendering
+    process_mode: ProcessMode,
+
+    // Host sample rate, shared with the GUI
+    sample_rate: Arc<AtomicF32>,
+
+    // Held peak level of the main output, shared with the GUI
+    master_peak: Arc<AtomicF32>,
+
+    // Number of samples of the last processed block, shared with the GUI
+    block_size: Arc<AtomicU32>,
+
+    // Mute and dim are toggles, the resulting gain is smoothed here
+    monitor_gain: Smoother<f32>,
+    monitor_target: f32,
+
+    // Master saturation, it keeps the oversampling state
+    saturator: Saturator,
+
+    // Master limiter, the last stage before mute and dim
+    limiter: Limiter,
+
+    // The slot the next note starts from in round robin
+    next_rr_index: usize,
+
+    // CC values received for the learned params, applied by the GUI
+    midi_learn: Arc<MidiLearn>,
 }
 
 impl Default for HardKickSampler {
@@ -37,52 +83,316 @@ impl Default for HardKickSampler {
         let sample_wrappers = (0..MAX_SAMPLES)
             .map(|index| SamplePlayer::new(params.clone(), index))
             .collect();
+        let monitor_gain = Smoother::new(SmoothingStyle::Linear(MONITOR_SMOOTHING_MS));
+        monitor_gain.reset(1.);
         Self {
             params: params.clone(),
             sample_players: sample_wrappers,
             receiver: None,
-            process_count: 0.,
             host_bpm: Arc::new(AtomicF32::default()),
+            active: Arc::new(AtomicBool::new(false)),
+            alignment_tick: Arc::new(AtomicBool::new(false)),
+            process_mode: ProcessMode::Realtime,
+            sample_rate: Arc::new(AtomicF32::new(48000.)),
+            master_peak: Arc::new(AtomicF32::new(0.)),
+            block_size: Arc::new(AtomicU32::new(0)),
+            monitor_gain,
+            monitor_target: 1.,
+            saturator: Saturator::default(),
+            limiter: Limiter::new(),
+            next_rr_index: 0,
+            midi_learn: Arc::new(MidiLearn::default()),
         }
     }
 }
 
 impl HardKickSampler {
-    /// Process midi events
-    fn handle_context(&mut self, context: &mut impl ProcessContext<Self>) {
-        // Process MIDI events
-        while let Some(event) = context.next_event() {
-            match event {
-                NoteEvent::NoteOn { note, velocity, .. } => {
-                    // Trigger a sample
-                    self.start_sample(note, velocity);
-                }
-                NoteEvent::NoteOff { .. } => {
-                    // Stop a sample
-                    self.stop_sample();
-                }
-                _ => {}
+    /// Process a midi event
+    fn handle_event(&mut self, event: PluginNoteEvent<Self>) {
+        match event {
+            NoteEvent::NoteOn {
+                note,
+                velocity,
+                channel,
+                voice_id,
+                ..
+            } => {
+                // Trigger a sample
+                self.start_sample(note, velocity, channel, voice_id);
+            }
+            NoteEvent::NoteOff {
+                note,
+                channel,
+                voice_id,
+                ..
+            } => {
+                // Only the matching note is released
+                self.stop_sample(note, channel, voice_id);
             }
+            NoteEvent::MidiPitchBend { channel, value, .. } => {
+                self.pitch_bend(channel, value);
+            }
+            NoteEvent::MidiCC { cc, value, .. } => {
+                self.midi_cc(cc, value);
+            }
+            _ => {}
+        }
+    }
+
+    /// Binds the CC while learning, else forwards its value if it is bound.
+    ///
+    /// The plugin can't set its own params, the GUI applies the values through the
+    /// host so the CCs only control the params while the editor is open.
+    fn midi_cc(&mut self, cc: u8, value: f32) {
+        // Learning goes on until the CC is bound, a busy lock waits for the next CC
+        if let Some(target) = self.midi_learn.learning() {
+            if let Ok(mut mappings) = self.params.cc_mappings.try_write() {
+                mappings.bind(target, cc);
+                self.midi_learn.finish_learning(target);
+            }
+        }
+
+        let target = self
+            .params
+            .cc_mappings
+            .try_read()
+            .ok()
+            .and_then(|mappings| mappings.target_of(cc));
+        if let Some(target) = target {
+            self.midi_learn.push_value(target, value);
         }
     }
 
     /// Trigger the samples to play for all the ones that are loaded
-    fn start_sample(&mut self, note: u8, velocity: f32) {
-        self.process_count = 0.;
-        for sample in self.sample_players.iter_mut().filter(|sp| !sp.is_muted()) {
-            sample.start_playing(note, velocity);
+    /// and listen to the note's MIDI channel.
+    ///
+    /// In round robin, consecutive notes cycle through the samples one at a time.
+    /// The triggered samples choke the other samples of their choke groups,
+    /// samples triggered by the same note never choke each other.
+    fn start_sample(&mut self, note: u8, velocity: f32, channel: u8, voice_id: Option<i32>) {
+        let any_soloed = self.any_soloed();
+        let mut triggered: [bool; MAX_SAMPLES] = std::array::from_fn(|index| {
+            let sp = &self.sample_players[index];
+            sp.is_audible(any_soloed) && sp.accepts_channel(channel) && sp.get_num_frames() > 0
+        });
+
+        // In round robin only the next slot that can play is triggered
+        if self.params.trigger_mode.value() == TriggerMode::RoundRobin {
+            let next = (0..MAX_SAMPLES)
+                .map(|offset| (self.next_rr_index + offset) % MAX_SAMPLES)
+                .find(|&index| triggered[index]);
+            triggered = std::array::from_fn(|index| Some(index) == next);
+            if let Some(index) = next {
+                self.next_rr_index = (index + 1) % MAX_SAMPLES;
+            }
+        }
+
+        // Bit mask of the choke groups
+        let choke_groups = self
+            .sample_players
+            .iter()
+            .zip(triggered)
+            .filter(|(_, triggered)| *triggered)
+            .filter_map(|(sp, _)| sp.get_choke_group())
+            .fold(0u32, |groups, group| groups | (1 << group));
+
+        for (sample, triggered) in self.sample_players.iter_mut().zip(triggered) {
+            if triggered {
+                sample.start_playing(note, velocity, voice_id);
+            } else if sample
+                .get_choke_group()
+                .is_some_and(|group| choke_groups & (1 << group) != 0)
+            {
+                sample.choke();
+            }
         }
     }
 
-    /// Just stop playing, we don't have to specify the notes
-    /// because we don't handle multi notes playing in the same
-    /// time anyway
-    fn stop_sample(&mut self) {
-        for sample in self.sample_players.iter_mut() {
-            sample.stop_playing();
+    /// Bend the samples listening to this MIDI channel, `value` is centered on 0.5
+    fn pitch_bend(&mut self, channel: u8, value: f32) {
+        for sample in self
+            .sample_players
+            .iter_mut()
+            .filter(|sp| sp.accepts_channel(channel))
+        {
+            sample.set_pitch_bend((value - 0.5) * 2.);
         }
     }
 
+    /// Release the voices playing this note, the other notes keep playing
+    fn stop_sample(&mut self, note: u8, channel: u8, voice_id: Option<i32>) {
+        for sample in self
+            .sample_players
+            .iter_mut()
+            .filter(|sp| sp.accepts_channel(channel))
+        {
+            sample.stop_playing(note, voice_id);
+        }
+    }
+
+    /// Returns whether any sample is currently playing
+    pub fn is_active(&self) -> bool {
+        self.sample_players.iter().any(|sp| !sp.is_silent())
+    }
+
+    /// Output gain requested by the mute and dim toggles
+    fn get_monitor_target(&self) -> f32 {
+        if self.params.mute.value() {
+            0.
+        } else if self.params.dim.value() {
+            DIM_GAIN
+        } else {
+            1.
+        }
+    }
+
+    /// Starts a new ramp when mute or dim changed
+    fn update_monitor_gain(&mut self) {
+        let target = self.get_monitor_target();
+        if target != self.monitor_target {
+            self.monitor_target = target;
+            self.monitor_gain
+                .set_target(self.sample_rate.load(Ordering::Relaxed), target);
+        }
+    }
+
+    fn clear_buffer(buffer: &mut Buffer) {
+        for channel_samples in buffer.iter_samples() {
+            for sample in channel_samples.into_iter() {
+                *sample = 0.;
+            }
+        }
+    }
+
+    /// Renders the playing samples in the buffer, the whole block at once.
+    ///
+    /// This is everything `process` does once MIDI events and messages
+    /// are handled, independently of the plugin format.
+    /// The first aux output gets the slots sends, the next ones the slots routed
+    /// to an output bus, when the host provides them. The aux outputs are taken
+    /// before the master section, only mute and dim apply to them.
+    fn render(&mut self, buffer: &mut Buffer, aux_outputs: &mut [Buffer]) {
+        self.begin_block(buffer, aux_outputs);
+        self.render_range(buffer, aux_outputs, 0..buffer.samples());
+        self.finish_block(buffer, aux_outputs);
+    }
+
+    /// Returns whether at least one slot is soloed, then only the soloed slots play
+    fn any_soloed(&self) -> bool {
+        self.sample_players.iter().any(|sp| sp.is_soloed())
+    }
+
+    /// Clears the buffers before the ranges of the block are rendered
+    fn begin_block(&mut self, buffer: &mut Buffer, aux_outputs: &mut [Buffer]) {
+        self.update_monitor_gain();
+
+        Self::clear_buffer(buffer);
+        for output in aux_outputs.iter_mut() {
+            Self::clear_buffer(output);
+        }
+    }
+
+    /// Renders the playing samples in a range of the block
+    fn render_range(
+        &mut self,
+        buffer: &mut Buffer,
+        aux_outputs: &mut [Buffer],
+        range: Range<usize>,
+    ) {
+        // It also checks is all samples finished to play
+        // The solos are additive, every soloed slot plays
+        let any_soloed = self.any_soloed();
+        let is_active = |sp: &SamplePlayer| !sp.is_silent() && sp.is_audible(any_soloed);
+
+        if !self.sample_players.iter().any(is_active) {
+            // If all samples are silent, we can just return as it is
+            return;
+        }
+
+        // The alignment tick is excluded from any render / export
+        let tick = self.alignment_tick.load(Ordering::Relaxed)
+            && self.process_mode != ProcessMode::Offline;
+
+        let (mut send, buses) = match aux_outputs.split_first_mut() {
+            Some((send, buses)) => (Some(send), buses),
+            None => (None, &mut [][..]),
+        };
+
+        // every active player fill the buffe one by one
+        // Slots routed to a bus the host doesn't provide stay on the main output
+        for sample_player in self.sample_players.iter_mut().filter(|sp| is_active(sp)) {
+            let range = range.clone();
+            match sample_player.get_output_bus() {
+                Some(bus) if bus < buses.len() => {
+                    sample_player.process(&mut buses[bus], range, tick, send.as_deref_mut())
+                }
+                _ => sample_player.process(buffer, range, tick, send.as_deref_mut()),
+            }
+        }
+    }
+
+    /// Applies the master section once the whole block is rendered
+    fn finish_block(&mut self, buffer: &mut Buffer, aux_outputs: &mut [Buffer]) {
+        // Apply gain, saturation and the limiter to the main output. The aux outputs are
+        // pre-master, they're processed on their own tracks, so only mute and dim apply to them
+        let saturation_mode = self.params.saturation_mode.value();
+        let oversampling = self.params.oversampling.value();
+        let limiter_enabled = self.params.limiter_enabled.value();
+        let mut block_peak: f32 = 0.;
+        let mut aux_samples: [_; NUM_AUX_OUTPUTS] = std::array::from_fn(|_| None);
+        for (samples, output) in aux_samples.iter_mut().zip(aux_outputs.iter_mut()) {
+            *samples = Some(output.iter_samples());
+        }
+        for channel_samples in buffer.iter_samples() {
+            let monitor_gain = self.monitor_gain.next();
+            let gain = self.params.gain.smoothed.next();
+            let drive = self.params.drive.smoothed.next();
+            let ceiling = self.params.ceiling.smoothed.next();
+            for (channel, sample) in channel_samples.into_iter().enumerate() {
+                let mut value = self.saturator.process_sample(
+                    channel,
+                    saturation_mode,
+                    drive,
+                    oversampling,
+                    *sample * gain,
+                );
+                if limiter_enabled {
+                    value = self.limiter.process_sample(channel, ceiling, value);
+                }
+                *sample = value * monitor_gain;
+                block_peak = block_peak.max(sample.abs());
+            }
+            for aux_channel_samples in aux_samples.iter_mut().flatten().flat_map(|s| s.next()) {
+                for sample in aux_channel_samples.into_iter() {
+                    *sample *= monitor_gain;
+                }
+            }
+        }
+
+        // Update the position once per processed block
+        // Allowing the GUI to see where we are in the buffer playback
+        let bpm = self.host_bpm.load(Ordering::Relaxed);
+        self.sample_players
+            .iter_mut()
+            .for_each(|sp| sp.update_shared_position(bpm));
+        self.block_size
+            .store(buffer.samples() as u32, Ordering::Relaxed);
+
+        // The meters hold their peak and decay between blocks
+        let block_duration = buffer.samples() as f32 / self.sample_rate.load(Ordering::Relaxed);
+        self.sample_players
+            .iter_mut()
+            .for_each(|sp| sp.update_shared_peak(block_duration));
+        let previous = self.master_peak.load(Ordering::Relaxed);
+        self.master_peak.store(
+            utils::decay_peak(previous, block_peak, block_duration),
+            Ordering::Relaxed,
+        );
+
+        // Some samples may have finished during this block
+        self.active.store(self.is_active(), Ordering::Relaxed);
+    }
+
     fn handle_messages(&mut self) {
         // Get the receiver
         let receiver = match &self.receiver {
@@ -98,11 +408,31 @@ impl HardKickSampler {
                         .get_mut(index)
                         .map(|sample| sample.load_and_set_audio_file(&path, data));
                 }
+                TaskResults::RestoredFile(index, path, data) => {
+                    self.sample_players
+                        .get_mut(index)
+                        .map(|sample| sample.restore_audio_file(&path, data));
+                }
+                TaskResults::LoadedChannel(index, path, channel, data) => {
+                    self.sample_players
+                        .get_mut(index)
+                        .map(|sample| sample.load_and_set_audio_channel(&path, channel, data));
+                }
+                TaskResults::PreparedPlayback(index, data) => {
+                    if let Some(sample) = self.sample_players.get_mut(index) {
+                        sample.set_playback(data);
+                    }
+                }
                 TaskResults::ClearSample(index) => {
                     self.sample_players
                         .get_mut(index)
                         .map(|sample| sample.clear_sample());
                 }
+                TaskResults::ClearAll => {
+                    for sample in self.sample_players.iter_mut() {
+                        let _ = sample.clear_sample();
+                    }
+                }
             };
         }
     }
@@ -123,15 +453,20 @@ impl Plugin for HardKickSampler {
         main_output_channels: NonZeroU32::new(2),
 
         aux_input_ports: &[],
-        aux_output_ports: &[],
+        // Receives each slot scaled by its send gain, for parallel processing,
+        // then the output buses the slots can be routed to
+        aux_output_ports: &[new_nonzero_u32(2); NUM_AUX_OUTPUTS],
 
         // Individual ports and the layout as a whole can be named here. By default these names
         // are generated as needed. This layout will be called 'Stereo', while a layout with
         // only one input and output channel would be called 'Mono'.
-        names: PortNames::const_default(),
+        names: PortNames {
+            aux_outputs: &AUX_OUTPUT_NAMES,
+            ..PortNames::const_default()
+        },
     }];
 
-    const MIDI_INPUT: MidiConfig = MidiConfig::Basic;
+    const MIDI_INPUT: MidiConfig = MidiConfig::MidiCCs;
     const MIDI_OUTPUT: MidiConfig = MidiConfig::None;
 
     const SAMPLE_ACCURATE_AUTOMATION: bool = true;
@@ -161,6 +496,13 @@ impl Plugin for HardKickSampler {
             .unwrap_or(const { NonZero::new(2).unwrap() })
             .get();
 
+        // Offline rendering must not include monitoring aids
+        self.process_mode = buffer_config.process_mode;
+        self.sample_rate
+            .store(buffer_config.sample_rate, Ordering::Relaxed);
+
+        self.limiter.set_sample_rate(buffer_config.sample_rate);
+
         // init a bool that knows if everything went well
         let mut success = true;
 
@@ -181,66 +523,68 @@ impl Plugin for HardKickSampler {
     fn reset(&mut self) {
         // Reset buffers and envelopes here. This can be called from the audio thread and may not
         // allocate. You can remove this function if you do not need it.
+        self.monitor_target = self.get_monitor_target();
+        self.monitor_gain.reset(self.monitor_target);
+        self.saturator.reset();
+        self.limiter.reset();
+
         for sample_wrapper in self.sample_players.iter_mut() {
             sample_wrapper.reset();
         }
+        self.next_rr_index = 0;
     }
 
     fn process(
         &mut self,
         buffer: &mut Buffer,
-        _aux: &mut AuxiliaryBuffers,
+        aux: &mut AuxiliaryBuffers,
         context: &mut impl ProcessContext<Self>,
     ) -> ProcessStatus {
         // Handle messages
         self.handle_messages();
 
-        // Handle the context
-        self.handle_context(context);
+        // Set host bpm, before rendering since the synced blend depends on it
+        let tempo = utils::effective_bpm(context.transport().tempo.map(|t| t as f32));
+        self.host_bpm.store(tempo, Ordering::Relaxed);
+        for (index, sample_player) in self.sample_players.iter_mut().enumerate() {
+            sample_player.change_bpm(tempo);
 
-        // Clear the buffer
-        for channel_samples in buffer.iter_samples() {
-            for sample in channel_samples.into_iter() {
-                *sample = 0.;
+            // The sample is resampled in the background, the audio thread only swaps it in
+            if sample_player.should_prepare_playback() {
+                context.execute_background(TaskRequests::PreparePlayback(index));
             }
         }
 
-        // It also checks is all samples finished to play
-        let active_players: Vec<_> = self
-            .sample_players
-            .iter_mut()
-            .filter(|sp| !sp.is_silent())
-            .collect();
+        // Render the active samples, the block is split at each MIDI event
+        // so the notes start on their own sample
+        let num_samples = buffer.samples();
+        self.begin_block(buffer, aux.outputs);
 
-        if active_players.is_empty() {
-            // If all samples are silent, we can just return as it is
-            return ProcessStatus::Normal;
-        }
+        let mut block_start = 0;
+        let mut next_event = context.next_event();
+        while block_start < num_samples {
+            while let Some(event) = next_event {
+                if event.timing() as usize > block_start {
+                    break;
+                }
+                self.handle_event(event);
+                next_event = context.next_event();
+            }
 
-        // every active player fill the buffe one by one
-        for sample_player in active_players {
-            sample_player.process(buffer, self.process_count);
+            let block_end = next_event.map_or(num_samples, |event| {
+                (event.timing() as usize).min(num_samples)
+            });
+            self.render_range(buffer, aux.outputs, block_start..block_end);
+            block_start = block_end;
         }
 
-        self.process_count += buffer.samples() as f32;
-
-        // Apply gain
-        for channel_samples in buffer.iter_samples() {
-            let gain = self.params.gain.smoothed.next();
-            for sample in channel_samples.into_iter() {
-                *sample *= gain;
-            }
+        // Events past the end of the block, if the host sends any
+        while let Some(event) = next_event {
+            self.handle_event(event);
+            next_event = context.next_event();
         }
 
-        // Update the position once per processed block
-        // Allowing the GUI to see where we are in the buffer playback
-        self.sample_players
-            .iter_mut()
-            .for_each(|sp| sp.update_shared_position(self.process_count));
-
-        // Set host bpm
-        let tempo = context.transport().tempo.unwrap_or(DEFAULT_BPM) as f32;
-        self.host_bpm.store(tempo, Ordering::Relaxed);
+        self.finish_block(buffer, aux.outputs);
 
         ProcessStatus::Normal
     }
@@ -258,7 +602,38 @@ impl Plugin for HardKickSampler {
                 .iter()
                 .map(|s| s.get_shared_position())
                 .collect(),
+            detected_frequencies: self
+                .sample_players
+                .iter()
+                .map(|s| s.get_shared_detected_frequency())
+                .collect(),
+            pitch_detection_failed: self
+                .sample_players
+                .iter()
+                .map(|s| s.get_shared_pitch_detection_failed())
+                .collect(),
+            load_histories: self
+                .sample_players
+                .iter()
+                .map(|s| s.get_shared_load_history())
+                .collect(),
+            peaks: self
+                .sample_players
+                .iter()
+                .map(|s| s.get_shared_peak())
+                .collect(),
+            playback_sample_rates: self
+                .sample_players
+                .iter()
+                .map(|s| s.get_shared_sample_rate())
+                .collect(),
+            master_peak: self.master_peak.clone(),
             host_bpm: self.host_bpm.clone(),
+            host_sample_rate: self.sample_rate.clone(),
+            block_size: self.block_size.clone(),
+            is_active: self.active.clone(),
+            alignment_tick: self.alignment_tick.clone(),
+            midi_learn: self.midi_learn.clone(),
         };
         crate::editor_vizia::create_editor(Arc::new(state), async_executor)
     }
@@ -266,18 +641,657 @@ impl Plugin for HardKickSampler {
     fn task_executor(&mut self) -> TaskExecutor<Self> {
         let (sender, receiver) = std::sync::mpsc::channel();
         self.receiver = Some(receiver);
+        let params = self.params.clone();
+        let host_sample_rate = self.sample_rate.clone();
+        let load_histories: Vec<_> = self
+            .sample_players
+            .iter()
+            .map(|s| s.get_shared_load_history())
+            .collect();
+        let shared_buffers: Vec<_> = self
+            .sample_players
+            .iter()
+            .map(|s| s.get_shared_audio_data())
+            .collect();
+
+        // The samples are prepared here, the audio thread only swaps them in
+        let send_params = params.clone();
+        let send_histories = load_histories.clone();
+        let send = move |result: TaskResults| {
+            // The file is decoded, the sample it replaces can now be restored with an undo
+            if let TaskResults::LoadedFile(index, path, _) = &result {
+                let replaced = send_params
+                    .samples
+                    .get(*index)
+                    .and_then(|sample| sample.sample_path.read().ok()?.clone());
+                if let (Some(replaced), Some(history)) = (replaced, send_histories.get(*index)) {
+                    push_load_history(history, &replaced, path);
+                }
+            }
+
+            let host_sample_rate = host_sample_rate.load(Ordering::Relaxed);
+            let _ = sender.send(result.prepared(&send_params, host_sample_rate));
+        };
 
         Box::new(move |task| match task {
             TaskRequests::TransfertTask(task) => {
                 // Actually load the file
-                let _ = sender.send(task);
+                send(task);
             }
             TaskRequests::LoadFile(index, path) => {
                 // Actually load the file
                 if let Ok(audio_data) = utils::load_audio_file(&path) {
-                    let _ = sender.send(TaskResults::LoadedFile(index, path, audio_data));
+                    send(TaskResults::LoadedFile(index, path, audio_data));
+                }
+            }
+            TaskRequests::UndoLoad(index) => {
+                // The path only leaves the history once it's decoded, a file that
+                // can't be read anymore stays there
+                let Some(history) = load_histories.get(index) else {
+                    return;
+                };
+                let path = history
+                    .read()
+                    .ok()
+                    .and_then(|history| history.last().cloned());
+                if let Some(path) = path {
+                    match utils::load_audio_file(&path) {
+                        Ok(audio_data) => {
+                            if let Ok(mut history) = history.write() {
+                                history.pop();
+                            }
+                            send(TaskResults::RestoredFile(index, path, audio_data));
+                        }
+                        Err(e) => nih_error!("Failed to restore {:?}: {}", path, e),
+                    }
+                }
+            }
+            TaskRequests::PreparePlayback(index) => {
+                let loaded = shared_buffers
+                    .get(index)
+                    .and_then(|buffer| buffer.read().ok()?.clone());
+                if let Some(audio_data) = loaded {
+                    send(TaskResults::PreparedPlayback(index, audio_data));
+                }
+            }
+            TaskRequests::DuplicateSample(source, target) => {
+                let Some(sample) = params.samples.get(source) else {
+                    return;
+                };
+                let path = sample.sample_path.read().ok().and_then(|path| path.clone());
+                let channel = sample
+                    .sample_channel
+                    .read()
+                    .ok()
+                    .and_then(|channel| *channel);
+                if let Some(path) = path {
+                    match TaskResults::load_sample(target, path.clone(), channel) {
+                        Ok(result) => {
+                            send(result);
+                        }
+                        Err(e) => nih_error!("Failed to duplicate {:?}: {}", path, e),
+                    }
+                }
+            }
+            TaskRequests::SavePreset(path) => {
+                if let Err(e) = presets::save_preset(&params, &path) {
+                    nih_error!("Failed to save preset {:?}: {}", path, e);
+                }
+            }
+            TaskRequests::LoadPreset(path) => {
+                // The param values are restored by the GUI, only the samples are loaded
+                // here, the same way `load_preset_sample` does when the host restores a state
+                let preset = match presets::read_preset(&path) {
+                    Ok(preset) => preset,
+                    Err(e) => {
+                        nih_error!("Failed to read preset {:?}: {}", path, e);
+                        return;
+                    }
+                };
+                let preset_dir = path.parent().unwrap_or(std::path::Path::new(""));
+
+                for index in 0..MAX_SAMPLES {
+                    let Some(sample) = preset.samples.get(index).cloned().flatten() else {
+                        send(TaskResults::ClearSample(index));
+                        continue;
+                    };
+
+                    let sample_path = sample.resolve(preset_dir);
+                    match TaskResults::load_sample(index, sample_path.clone(), sample.channel) {
+                        Ok(result) => {
+                            send(result);
+                        }
+                        Err(e) => {
+                            nih_error!("Failed to load preset sample {:?}: {}", sample_path, e)
+                        }
+                    }
+                }
+            }
+            TaskRequests::LoadFileSplit(index, path) => {
+                // Stereo files are split into two mono samples (left to the given slot,
+                // right to the next one). At the last slot, or if the file isn't stereo,
+                // the file is loaded as it is.
+                if let Ok(audio_data) = utils::load_audio_file(&path) {
+                    let split = match (audio_data.extract_channel(0), audio_data.extract_channel(1))
+                    {
+                        (Some(left), Some(right))
+                            if audio_data.spec.channels == 2 && index + 1 < MAX_SAMPLES =>
+                        {
+                            Some((left, right))
+                        }
+                        _ => None,
+                    };
+
+                    if let Some((left, right)) = split {
+                        send(TaskResults::LoadedChannel(index, path.clone(), 0, left));
+                        send(TaskResults::LoadedChannel(index + 1, path, 1, right));
+                    } else {
+                        send(TaskResults::LoadedFile(index, path, audio_data));
+                    }
                 }
             }
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use hound::{SampleFormat, WavSpec};
+
+    use super::*;
+    use crate::sample_wrapper::MAX_VOICES;
+    use crate::tasks::{AudioData, PlaybackSettings};
+
+    const BLOCK_SIZE: usize = 64;
+    const SAMPLE_RATE: f32 = 48000.;
+
+    fn test_sample() -> Vec<f32> {
+        (0..4800).map(|i| (i as f32 * 0.05).sin() * 0.5).collect()
+    }
+
+    /// The mono test sample, recorded at `sample_rate`
+    fn test_audio_data(sample_rate: f32) -> AudioData {
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: sample_rate as u32,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        AudioData::new(spec, test_sample())
+    }
+
+    /// Builds a plugin with a mono sample loaded in the first slot,
+    /// the same way the wrapper would have initialized it
+    fn loaded_plugin() -> HardKickSampler {
+        let mut plugin = HardKickSampler::default();
+        plugin
+            .params
+            .gain
+            .smoothed
+            .reset(plugin.params.gain.value());
+
+        for sample in plugin.params.samples.iter() {
+            sample.dry_wet.smoothed.reset(sample.dry_wet.value());
+        }
+
+        for player in plugin.sample_players.iter_mut() {
+            player.change_sample_rate_output(SAMPLE_RATE);
+            player.change_channel_number(2);
+        }
+
+        plugin.sample_players[0]
+            .load_and_set_audio_file(Path::new("test.wav"), test_audio_data(SAMPLE_RATE))
+            .unwrap();
+        plugin
+    }
+
+    /// Removes the declicking fades of the first slot, so it plays the sample as it is
+    fn disable_fades(plugin: &HardKickSampler) {
+        let sample = &plugin.params.samples[0];
+        for fade in [&sample.fade_in, &sample.fade_out] {
+            unsafe { fade.as_ptr().set_normalized_value(0.) };
+        }
+    }
+
+    fn render_blocks(plugin: &mut HardKickSampler, num_blocks: usize) -> Vec<Vec<f32>> {
+        let mut output = vec![Vec::new(); 2];
+        for _ in 0..num_blocks {
+            let mut channels = vec![vec![0.0; BLOCK_SIZE]; 2];
+            {
+                let mut buffer = Buffer::default();
+                unsafe {
+                    buffer.set_slices(BLOCK_SIZE, |output_slices| {
+                        *output_slices = channels.iter_mut().map(|c| c.as_mut_slice()).collect();
+                    });
+                }
+                plugin.render(&mut buffer, &mut []);
+            }
+            for (output, channel) in output.iter_mut().zip(channels) {
+                output.extend(channel);
+            }
+        }
+        output
+    }
+
+    #[test]
+    fn test_render_is_deterministic() {
+        let mut first = loaded_plugin();
+        let mut second = loaded_plugin();
+        first.start_sample(60, 1.0, 0, None);
+        second.start_sample(60, 1.0, 0, None);
+
+        assert_eq!(render_blocks(&mut first, 8), render_blocks(&mut second, 8));
+    }
+
+    #[test]
+    fn test_render_unity_gain_and_polarity() {
+        let mut plugin = loaded_plugin();
+        disable_fades(&plugin);
+        plugin.start_sample(60, 1.0, 0, None);
+        let output = render_blocks(&mut plugin, 4);
+
+        // Default state plays the sample untouched on both channels
+        for channel in output.iter() {
+            for (out, expected) in channel.iter().zip(test_sample()) {
+                assert!((out - expected).abs() < 1e-5, "{} != {}", out, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_note_starts_on_its_own_sample() {
+        let mut plugin = loaded_plugin();
+        disable_fades(&plugin);
+        let note_timing = BLOCK_SIZE / 2;
+
+        let mut channels = vec![vec![0.0; BLOCK_SIZE]; 2];
+        {
+            let mut buffer = Buffer::default();
+            unsafe {
+                buffer.set_slices(BLOCK_SIZE, |output_slices| {
+                    *output_slices = channels.iter_mut().map(|c| c.as_mut_slice()).collect();
+                });
+            }
+
+            // What process does for a note event in the middle of the block
+            plugin.begin_block(&mut buffer, &mut []);
+            plugin.render_range(&mut buffer, &mut [], 0..note_timing);
+            plugin.start_sample(60, 1.0, 0, None);
+            plugin.render_range(&mut buffer, &mut [], note_timing..BLOCK_SIZE);
+            plugin.finish_block(&mut buffer, &mut []);
+        }
+
+        for channel in channels.iter() {
+            assert!(channel[..note_timing].iter().all(|v| *v == 0.));
+            for (out, expected) in channel[note_timing..].iter().zip(test_sample()) {
+                assert!((out - expected).abs() < 1e-5, "{} != {}", out, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_velocity_ignored_by_default() {
+        let mut soft = loaded_plugin();
+        let mut hard = loaded_plugin();
+        soft.start_sample(60, 0.2, 0, None);
+        hard.start_sample(60, 1.0, 0, None);
+
+        assert_eq!(render_blocks(&mut soft, 4), render_blocks(&mut hard, 4));
+    }
+
+    #[test]
+    fn test_pitch_bend_follows_playing_note() {
+        let mut centered = loaded_plugin();
+        let mut bent = loaded_plugin();
+        let mut reference = loaded_plugin();
+        for plugin in [&mut centered, &mut bent, &mut reference] {
+            plugin.start_sample(60, 1.0, 0, None);
+            render_blocks(plugin, 1);
+        }
+
+        centered.pitch_bend(0, 0.5);
+        bent.pitch_bend(0, 1.0);
+
+        let expected = render_blocks(&mut reference, 2);
+        assert_eq!(render_blocks(&mut centered, 2), expected);
+        assert_ne!(render_blocks(&mut bent, 2), expected);
+    }
+
+    #[test]
+    fn test_trim_end_fades_out_without_click() {
+        let mut plugin = loaded_plugin();
+        disable_fades(&plugin);
+        set_plain(&plugin.params.samples[0].trim_end, 0.05);
+        plugin.start_sample(60, 1.0, 0, None);
+        let output = render_blocks(&mut plugin, 60);
+
+        // Half of the sample is trimmed, the cut ramps down to it instead of jumping
+        let end = test_sample().len() / 2;
+        for channel in output.iter() {
+            assert!(channel[end..].iter().all(|v| v.abs() < 1e-3));
+            for pair in channel[end - 200..=end].windows(2) {
+                assert!((pair[1] - pair[0]).abs() < 0.05, "{:?}", pair);
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_silent_without_note() {
+        let mut plugin = loaded_plugin();
+        let output = render_blocks(&mut plugin, 2);
+        assert!(output.iter().flatten().all(|v| *v == 0.));
+        assert!(!plugin.is_active());
+    }
+
+    #[test]
+    fn test_solo_silences_other_slots() {
+        let solo = |plugin: &HardKickSampler, index: usize| unsafe {
+            plugin.params.samples[index]
+                .soloed
+                .as_ptr()
+                .set_normalized_value(1.);
+        };
+
+        // Another slot is soloed, the loaded one is silent
+        let mut other_soloed = loaded_plugin();
+        solo(&other_soloed, 1);
+        other_soloed.start_sample(60, 1.0, 0, None);
+        assert!(render_blocks(&mut other_soloed, 2)
+            .iter()
+            .flatten()
+            .all(|v| *v == 0.));
+
+        // Solos add up, the loaded slot plays along with the other soloed one
+        let mut both_soloed = loaded_plugin();
+        solo(&both_soloed, 0);
+        solo(&both_soloed, 1);
+        both_soloed.start_sample(60, 1.0, 0, None);
+        let mut reference = loaded_plugin();
+        reference.start_sample(60, 1.0, 0, None);
+        assert_eq!(
+            render_blocks(&mut both_soloed, 2),
+            render_blocks(&mut reference, 2)
+        );
+    }
+
+    /// Sets a param of the tests from its plain value
+    fn set_plain<P: Param>(param: &P, value: P::Plain) {
+        unsafe {
+            param
+                .as_ptr()
+                .set_normalized_value(param.preview_normalized(value))
+        };
+    }
+
+    /// Loads the test sample in the first `count` slots
+    fn load_slots(plugin: &mut HardKickSampler, count: usize) {
+        for player in plugin.sample_players[1..count].iter_mut() {
+            player
+                .load_and_set_audio_file(Path::new("test.wav"), test_audio_data(SAMPLE_RATE))
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_choke_group_silences_other_slots() {
+        let mut plugin = loaded_plugin();
+        load_slots(&mut plugin, 3);
+
+        // The first two slots choke each other, the third one is in its own group.
+        // The second slot listens to another MIDI channel so it's triggered alone.
+        for (index, (group, channel)) in [(1, 1), (1, 2), (2, 1)].into_iter().enumerate() {
+            let sample = &plugin.params.samples[index];
+            set_plain(&sample.choke_group, group);
+            set_plain(&sample.midi_channel, channel);
+        }
+
+        plugin.start_sample(60, 1.0, 0, None);
+        render_blocks(&mut plugin, 1);
+        assert!(!plugin.sample_players[0].is_silent());
+        assert!(plugin.sample_players[1].is_silent());
+        assert!(!plugin.sample_players[2].is_silent());
+
+        plugin.start_sample(60, 1.0, 1, None);
+        render_blocks(&mut plugin, 1);
+        assert!(plugin.sample_players[0].is_silent());
+        assert!(!plugin.sample_players[1].is_silent());
+        assert!(!plugin.sample_players[2].is_silent());
+    }
+
+    #[test]
+    fn test_round_robin_cycles_through_loaded_slots() {
+        let mut plugin = loaded_plugin();
+        load_slots(&mut plugin, 3);
+        set_plain(&plugin.params.trigger_mode, TriggerMode::RoundRobin);
+
+        // The empty slots are skipped, the cycle starts over after the last loaded one
+        for expected in [0, 1, 2, 0, 1] {
+            plugin.start_sample(60, 1.0, 0, None);
+            let playing: Vec<usize> = (0..MAX_SAMPLES)
+                .filter(|&index| !plugin.sample_players[index].is_silent())
+                .collect();
+            assert_eq!(playing, vec![expected]);
+
+            for player in plugin.sample_players.iter_mut() {
+                player.choke();
+            }
+        }
+    }
+
+    #[test]
+    fn test_resample_to_host_applies_to_loaded_sample() {
+        let mut plugin = loaded_plugin();
+        plugin.sample_players[1]
+            .load_and_set_audio_file(Path::new("test.wav"), test_audio_data(44100.))
+            .unwrap();
+        let player = &mut plugin.sample_players[1];
+        let playback_rate = player.get_shared_sample_rate();
+        assert_eq!(playback_rate.load(Ordering::Relaxed), 44100.);
+        assert!(!player.should_prepare_playback());
+
+        unsafe {
+            plugin
+                .params
+                .resample_to_host
+                .as_ptr()
+                .set_normalized_value(1.);
+        }
+
+        // The playback is requested once, then swapped in when the background task is done
+        let wanted = PlaybackSettings::new(&plugin.params, 1, 44100., SAMPLE_RATE);
+        let player = &mut plugin.sample_players[1];
+        assert!(player.should_prepare_playback());
+        assert!(!player.should_prepare_playback());
+        assert_eq!(playback_rate.load(Ordering::Relaxed), 44100.);
+
+        // A playback prepared for another sample is dropped
+        player.set_playback(test_audio_data(44100.).with_playback(wanted));
+        assert_eq!(playback_rate.load(Ordering::Relaxed), 44100.);
+
+        let loaded = player
+            .get_shared_audio_data()
+            .read()
+            .unwrap()
+            .clone()
+            .unwrap();
+        player.set_playback(loaded.with_playback(wanted));
+        assert_eq!(playback_rate.load(Ordering::Relaxed), SAMPLE_RATE);
+    }
+
+    #[test]
+    fn test_alignment_tick_is_not_rendered_offline() {
+        let mut realtime = loaded_plugin();
+        let mut offline = loaded_plugin();
+        let mut reference = loaded_plugin();
+        realtime.alignment_tick.store(true, Ordering::Relaxed);
+        offline.alignment_tick.store(true, Ordering::Relaxed);
+        offline.process_mode = ProcessMode::Offline;
+
+        for plugin in [&mut realtime, &mut offline, &mut reference] {
+            plugin.start_sample(60, 1.0, 0, None);
+        }
+
+        let expected = render_blocks(&mut reference, 1);
+        assert_eq!(render_blocks(&mut offline, 1), expected);
+        assert_ne!(render_blocks(&mut realtime, 1), expected);
+    }
+
+    #[test]
+    fn test_active_state_is_published() {
+        let mut plugin = loaded_plugin();
+        plugin.start_sample(60, 1.0, 0, None);
+        render_blocks(&mut plugin, 1);
+        assert!(plugin.is_active());
+        assert!(plugin.active.load(Ordering::Relaxed));
+
+        // The sample is 4800 frames long, the release ends it
+        plugin.stop_sample(60, 0, None);
+        render_blocks(&mut plugin, 100);
+        assert!(!plugin.is_active());
+        assert!(!plugin.active.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_note_off_only_releases_matching_note() {
+        let mut plugin = loaded_plugin();
+        plugin.start_sample(60, 1.0, 0, None);
+        render_blocks(&mut plugin, 1);
+        plugin.start_sample(64, 1.0, 0, None);
+        plugin.stop_sample(64, 0, None);
+
+        // Longer than the release, the first note is still held
+        render_blocks(&mut plugin, 10);
+        assert!(plugin.is_active());
+
+        plugin.stop_sample(60, 0, None);
+        render_blocks(&mut plugin, 10);
+        assert!(!plugin.is_active());
+    }
+
+    #[test]
+    fn test_note_off_matches_voice_id() {
+        let mut plugin = loaded_plugin();
+        plugin.start_sample(60, 1.0, 0, Some(1));
+        plugin.start_sample(60, 1.0, 0, Some(2));
+        plugin.stop_sample(60, 0, Some(2));
+
+        // Same note, but the first voice is still held
+        render_blocks(&mut plugin, 10);
+        assert!(plugin.is_active());
+    }
+
+    #[test]
+    fn test_load_history_keeps_replaced_samples() {
+        let mut plugin = loaded_plugin();
+        let history = plugin.sample_players[0].get_shared_load_history();
+
+        push_load_history(&history, Path::new("test.wav"), Path::new("other.wav"));
+        assert_eq!(*history.read().unwrap(), vec![PathBuf::from("test.wav")]);
+
+        // Reloading the same file doesn't fill the history
+        push_load_history(&history, Path::new("other.wav"), Path::new("other.wav"));
+        assert_eq!(history.read().unwrap().len(), 1);
+
+        // Loading a file doesn't touch the history from the audio thread
+        let player = &mut plugin.sample_players[0];
+        player
+            .load_and_set_audio_file(Path::new("other.wav"), test_audio_data(SAMPLE_RATE))
+            .unwrap();
+        assert_eq!(history.read().unwrap().len(), 1);
+
+        // A restore doesn't push the replaced sample back
+        let previous = history.write().unwrap().pop().unwrap();
+        player
+            .restore_audio_file(&previous, test_audio_data(SAMPLE_RATE))
+            .unwrap();
+        assert!(history.read().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_midi_learn_binds_next_cc() {
+        let mut plugin = loaded_plugin();
+
+        // Unbound CCs are ignored
+        plugin.midi_cc(7, 0.5);
+        assert_eq!(plugin.midi_learn.take_value(CcTarget::Gain), None);
+
+        plugin.midi_learn.set_learning(Some(CcTarget::Gain));
+        plugin.midi_cc(7, 0.5);
+        assert_eq!(plugin.midi_learn.learning(), None);
+        assert_eq!(
+            plugin
+                .params
+                .cc_mappings
+                .read()
+                .unwrap()
+                .get(CcTarget::Gain),
+            Some(7)
+        );
+        assert_eq!(plugin.midi_learn.take_value(CcTarget::Gain), Some(0.5));
+
+        plugin.midi_cc(7, 0.25);
+        assert_eq!(plugin.midi_learn.take_value(CcTarget::Gain), Some(0.25));
+    }
+
+    #[test]
+    fn test_midi_learn_waits_for_the_mappings_lock() {
+        let mut plugin = loaded_plugin();
+        plugin.midi_learn.set_learning(Some(CcTarget::Drive));
+
+        // The GUI holds the mappings, the CC can't be bound yet
+        let params = plugin.params.clone();
+        let mappings = params.cc_mappings.read().unwrap();
+        plugin.midi_cc(1, 0.5);
+        assert_eq!(plugin.midi_learn.learning(), Some(CcTarget::Drive));
+        drop(mappings);
+
+        plugin.midi_cc(1, 0.5);
+        assert_eq!(plugin.midi_learn.learning(), None);
+        assert_eq!(
+            plugin
+                .params
+                .cc_mappings
+                .read()
+                .unwrap()
+                .get(CcTarget::Drive),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn test_midi_cc_leaves_host_params_untouched() {
+        let mut plugin = loaded_plugin();
+        plugin
+            .params
+            .cc_mappings
+            .write()
+            .unwrap()
+            .bind(CcTarget::Drive, 1);
+        plugin.midi_cc(1, 0.5);
+
+        // The host sets the param after the CC, only the GUI applies the pushed value
+        let drive = &plugin.params.drive;
+        set_plain(drive, 0.25);
+        drive.smoothed.reset(drive.value());
+        render_blocks(&mut plugin, 4);
+        assert_eq!(drive.value(), 0.25);
+        assert_eq!(drive.smoothed.next(), 0.25);
+        assert_eq!(plugin.midi_learn.take_value(CcTarget::Drive), Some(0.5));
+    }
+
+    #[test]
+    fn test_voice_stealing_keeps_playing() {
+        let mut plugin = loaded_plugin();
+        for note in 0..(MAX_VOICES as u8 + 2) {
+            plugin.start_sample(60 + note, 1.0, 0, None);
+            render_blocks(&mut plugin, 1);
+        }
+        assert!(plugin.is_active());
+        assert!(render_blocks(&mut plugin, 1)
+            .iter()
+            .flatten()
+            .any(|v| *v != 0.));
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use nih_plug::prelude::Params;
+use serde::{Deserialize, Serialize};
+
+use crate::params::HardKickSamplerParams;
+
+/// Extension of the preset files
+pub const PRESET_EXTENSION: &str = "json";
+
+/// The sample of a slot as stored in a preset
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PresetSample {
+    /// Absolute path of the sample when the preset was saved
+    pub path: PathBuf,
+
+    /// Path relative to the preset directory, if the sample is inside it
+    pub relative_path: Option<PathBuf>,
+
+    /// The only channel of the file that is used, or None for all of them
+    pub channel: Option<usize>,
+}
+
+/// A portable snapshot of every param and of the loaded samples
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Preset {
+    /// Normalized value of each param, by param id
+    pub params: BTreeMap<String, f32>,
+
+    /// The sample of each slot, `None` for an empty slot
+    pub samples: Vec<Option<PresetSample>>,
+}
+
+impl Preset {
+    /// Takes a snapshot of the params, the sample paths are stored relative to
+    /// `preset_dir` when the samples are inside it
+    pub fn from_params(params: &HardKickSamplerParams, preset_dir: &Path) -> Self {
+        let values = params
+            .param_map()
+            .into_iter()
+            // SAFETY: the pointers come from `param_map()` of `params`, which is borrowed
+            // for the duration of the call so the params outlive them
+            .map(|(id, ptr, _)| (id, unsafe { ptr.unmodulated_normalized_value() }))
+            .collect();
+
+        let samples = params
+            .samples
+            .iter()
+            .map(|sample| {
+                let path = sample.sample_path.read().ok()?.clone()?;
+                let channel = *sample.sample_channel.read().ok()?;
+                let relative_path = path.strip_prefix(preset_dir).ok().map(Path::to_path_buf);
+                Some(PresetSample {
+                    path,
+                    relative_path,
+                    channel,
+                })
+            })
+            .collect();
+
+        Self {
+            params: values,
+            samples,
+        }
+    }
+}
+
+impl PresetSample {
+    /// Finds the sample on disk for a preset stored in `preset_dir`.
+    ///
+    /// The relative path is tried first so presets shared with their samples keep
+    /// working, then the absolute path, then a file with the same name next to the preset.
+    /// Falls back to the absolute path if none exists, loading it reports the error.
+    pub fn resolve(&self, preset_dir: &Path) -> PathBuf {
+        let candidates = [
+            self.relative_path
+                .as_ref()
+                .map(|path| preset_dir.join(path)),
+            Some(self.path.clone()),
+            self.path.file_name().map(|name| preset_dir.join(name)),
+        ];
+
+        candidates
+            .into_iter()
+            .flatten()
+            .find(|path| path.is_file())
+            .unwrap_or_else(|| self.path.clone())
+    }
+}
+
+/// Writes the current params to a preset file
+pub fn save_preset(
+    params: &HardKickSamplerParams,
+    file_path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let preset_dir = file_path.parent().unwrap_or(Path::new(""));
+    let preset = Preset::from_params(params, preset_dir);
+    std::fs::write(file_path, serde_json::to_string_pretty(&preset)?)?;
+    Ok(())
+}
+
+/// Reads a preset file
+pub fn read_preset(file_path: &Path) -> Result<Preset, Box<dyn std::error::Error>> {
+    Ok(serde_json::from_str(&std::fs::read_to_string(file_path)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::MAX_SAMPLES;
+
+    #[test]
+    fn test_resolve_prefers_relative_path() {
+        let dir = std::env::temp_dir().join("hks_preset_resolve");
+        std::fs::create_dir_all(dir.join("kicks")).unwrap();
+        let moved = dir.join("kicks").join("kick.wav");
+        std::fs::write(&moved, []).unwrap();
+
+        // The samples moved along with the preset
+        let sample = PresetSample {
+            path: PathBuf::from("/nowhere/kicks/kick.wav"),
+            relative_path: Some(PathBuf::from("kicks/kick.wav")),
+            channel: None,
+        };
+        assert_eq!(sample.resolve(&dir), moved);
+
+        // Nothing found, the stored path is kept
+        let missing = PresetSample {
+            relative_path: None,
+            ..sample
+        };
+        assert_eq!(
+            missing.resolve(&dir),
+            PathBuf::from("/nowhere/kicks/kick.wav")
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_param_ids_are_unique() {
+        let params = HardKickSamplerParams::default();
+        let preset = Preset::from_params(&params, Path::new("/nowhere"));
+
+        // Every slot gets its own suffixed ids, nothing is overwritten in the preset
+        assert_eq!(preset.params.len(), params.param_map().len());
+        assert!(preset.params.contains_key("is_tonal_1"));
+        assert!(preset
+            .params
+            .contains_key(&format!("is_tonal_{}", MAX_SAMPLES)));
+        assert_eq!(preset.samples.len(), MAX_SAMPLES);
+    }
+
+    #[test]
+    fn test_preset_roundtrip() {
+        let preset = Preset {
+            params: BTreeMap::from([("gain".to_string(), 0.5)]),
+            samples: vec![
+                None,
+                Some(PresetSample {
+                    path: PathBuf::from("/tmp/kick.wav"),
+                    relative_path: None,
+                    channel: Some(1),
+                }),
+            ],
+        };
+
+        let json = serde_json::to_string(&preset).unwrap();
+        let read: Preset = serde_json::from_str(&json).unwrap();
+        assert_eq!(read.params, preset.params);
+        assert_eq!(read.samples, preset.samples);
+    }
+}
@@ -1,29 +1,226 @@
-use std::path::Path;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, RwLock};
 
-use nih_plug::buffer::Buffer;
+use nih_plug::buffer::{Buffer, ChannelSamples};
+use nih_plug::prelude::AtomicF32;
 use nih_plug::{nih_error, nih_log};
 
 use crate::adsr::Adsr;
+use crate::filter::StateVariableFilter;
 use crate::params::{HardKickSamplerParams, SamplePlayerParams};
 use crate::pitch_shift::classic::ClassicShifter;
-use crate::pitch_shift::psola::PsolaShifter;
-use crate::pitch_shift::{FrameOutput, PitchShiftKind, PitchShifter};
-use crate::tasks::AudioData;
+use crate::pitch_shift::psola::{PsolaAnalysis, PsolaShifter};
+use crate::pitch_shift::{FrameOutput, LoopRegion, PitchShiftError, PitchShiftKind, PitchShifter};
+use crate::plugin::DEFAULT_BPM;
+use crate::tasks::{AudioData, Playback, PlaybackSettings};
 use crate::utils;
 
-/// MIDI note number for middle C (C3), used as the base note for pitch calculations
-const BASE_NOTE: u8 = 60;
+/// Number of notes a slot can play at the same time
+pub const MAX_VOICES: usize = 4;
+
+/// Number of previously loaded paths kept to undo a load
+const MAX_LOAD_HISTORY: usize = 16;
 
 /// Default sample rate used for initialization
 const DEFAULT_SAMPLE_RATE: f32 = 48000.;
 
+/// Level of the alignment tick played at the start of the sample
+const TICK_GAIN: f32 = 0.25;
+
+/// Length of the transient the pump listens to before ducking, in s
+const PUMP_WINDOW: f32 = 0.01;
+
+/// Time for the pump to reach its full depth after the window, in s
+const PUMP_ATTACK: f32 = 0.005;
+
+/// Shortest fade out before a trimmed end, in ms, so cutting the tail doesn't click
+const TRIM_FADE_MS: f32 = 2.;
+
+/// Frames between two updates of the pitch envelope
+const PITCH_ENV_INTERVAL: usize = 32;
+
+/// Number of cents in one semitone
+const CENTS_PER_SEMITONE: f32 = 100.;
+
+/// Self ducking of the slot output, triggered by its own transient.
+///
+/// The level is followed during the first `PUMP_WINDOW`, the tail is then
+/// reduced by the followed level and recovers over the pump time.
+/// The reduction ramps in over `PUMP_ATTACK` so it doesn't click.
+#[derive(Debug, Clone, Copy, Default)]
+struct Pump {
+    /// Followed level, relative to the transient peak once ducking
+    envelope: f32,
+
+    /// Peak level of the transient
+    peak: f32,
+}
+
+impl Pump {
+    fn reset(&mut self) {
+        self.envelope = 0.;
+        self.peak = 0.;
+    }
+
+    /// Returns the gain to apply to the frame.
+    ///
+    /// * `level` - Absolute level of the frame, before the pump
+    /// * `time` - Time since the sample started, in s
+    /// * `recovery` - Per frame decay of the envelope
+    #[inline]
+    fn next(&mut self, level: f32, time: f32, amount: f32, recovery: f32) -> f32 {
+        if time < PUMP_WINDOW {
+            self.peak = self.peak.max(level);
+            self.envelope = self.peak;
+            return 1.;
+        }
+
+        self.envelope *= recovery;
+        if self.peak > 0. {
+            let attack = ((time - PUMP_WINDOW) / PUMP_ATTACK).min(1.);
+            1. - amount * attack * self.envelope / self.peak
+        } else {
+            1.
+        }
+    }
+}
+
+/// A xorshift random generator, cheap and allocation free for the audio thread.
+#[derive(Debug, Clone, Copy)]
+struct XorShift {
+    state: u32,
+}
+
+impl XorShift {
+    fn new(seed: u32) -> Self {
+        // The state must never be 0
+        Self { state: seed.max(1) }
+    }
+
+    /// Returns a random value in [0, 1)
+    #[inline]
+    fn next_f32(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        (self.state >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+/// The playback of a stolen voice, still fading out.
+#[derive(Debug, Clone, Copy)]
+struct RetriggerTail {
+    /// Frames elapsed in the stolen playback
+    position: f32,
+
+    /// Random start offset of the stolen voice, in frames
+    jitter: f32,
+
+    /// Envelope and velocity gain of the voice when it was stolen
+    gain: f32,
+
+    /// The filter of the stolen voice, with its state
+    filter: StateVariableFilter,
+
+    /// Remaining frames of the fade out
+    remaining: f32,
+
+    /// Total length of the fade out in frames
+    length: f32,
+}
+
+/// A single note played by a slot.
+struct Voice {
+    /// The adsr envelope
+    adsr: Adsr,
+
+    /// Reads the sample at the pitch of the note
+    pitch_shifter: Box<dyn PitchShifter + Send>,
+
+    /// The MIDI note that triggered the voice
+    note: Option<u8>,
+
+    /// The host voice id of the note, if the host provides them
+    voice_id: Option<i32>,
+
+    /// Velocity of the note (0.0-1.0)
+    velocity: f32,
+
+    /// Number of frames processed since the note started
+    elapsed: f32,
+
+    /// Random start offset of the note, in frames
+    jitter: f32,
+
+    /// Self ducking after the transient
+    pump: Pump,
+
+    /// Per voice filter, applied after the shifter
+    filter: StateVariableFilter,
+}
+
+impl Voice {
+    fn new() -> Self {
+        Self {
+            adsr: Adsr::new(DEFAULT_SAMPLE_RATE),
+            pitch_shifter: Box::new(ClassicShifter::new()),
+            note: None,
+            voice_id: None,
+            velocity: 1.,
+            elapsed: 0.,
+            jitter: 0.,
+            pump: Pump::default(),
+            filter: StateVariableFilter::new(),
+        }
+    }
+
+    #[inline]
+    fn is_idling(&self) -> bool {
+        self.adsr.is_idling()
+    }
+
+    /// Gain of the note velocity, at 0 sensitivity the velocity is ignored
+    #[inline]
+    fn velocity_gain(&self, sensitivity: f32) -> f32 {
+        utils::interpolate(1., self.velocity, sensitivity)
+    }
+
+    /// Attack time of the note, harder hits get a shorter attack
+    #[inline]
+    fn attack_time(&self, attack: f32, vel_to_attack: f32) -> f32 {
+        attack * (1. - vel_to_attack * self.velocity)
+    }
+
+    /// Returns whether a note off event is meant for this voice.
+    ///
+    /// Voice ids are more precise when the host sends them, two voices can play the same note.
+    #[inline]
+    fn matches(&self, note: u8, voice_id: Option<i32>) -> bool {
+        match (self.voice_id, voice_id) {
+            (Some(own_id), Some(voice_id)) => own_id == voice_id,
+            _ => self.note == Some(note),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.adsr.reset();
+        self.note = None;
+        self.voice_id = None;
+        self.elapsed = 0.;
+        self.jitter = 0.;
+        self.pump.reset();
+        self.filter.reset();
+    }
+}
+
 /// A multi-channel audio sample player with pitch shifting, ADSR envelope, and real-time parameter control.
 ///
 /// `SampleWrapper` handles loading and playback of audio samples with support for:
 /// - Multi-channel audio (mono, stereo, surround)
 /// - Real-time pitch shifting based on MIDI notes
+/// - Up to `MAX_VOICES` overlapping notes
 /// - ADSR envelope shaping
 /// - Smooth parameter interpolation
 /// - Sample rate conversion
@@ -35,35 +232,78 @@ pub struct SamplePlayer {
     index: usize,
 
     /// Holds the values of the sample
-    buffer: Option<Vec<f32>>,
+    buffer: Option<Arc<Vec<f32>>>,
+
+    /// The loaded data, `buffer` is resampled from it
+    source_buffer: Option<Arc<Vec<f32>>>,
+
+    /// How the played buffer was prepared, `None` without a sample
+    playback_settings: Option<PlaybackSettings>,
+
+    /// Settings of the playback being prepared in the background, if any
+    requested_playback: Option<PlaybackSettings>,
+
+    /// PSOLA analysis of the buffer, if it was computed when loading
+    analysis: Option<Arc<PsolaAnalysis>>,
 
     /// The target sample rate (i.e. the sample rate of the host)
     host_sample_rate: f32,
 
-    /// Sample rate of the sample itself, not the process sr
+    /// Sample rate of the buffer played, not the process sr
     sample_rate: f32,
 
-    /// Current trigerred note
-    midi_note: Option<i8>,
+    /// Sample rate of the loaded file, differs from `sample_rate` when resampled
+    source_sample_rate: f32,
 
     /// Number of output channels
     host_channels: usize,
 
+    /// Tempo of the host, the synced blend follows it
+    host_bpm: f32,
+
     /// Number of channel of the sample
     sample_channels: usize,
 
-    /// The adsr envelope
-    adsr: Adsr,
+    /// The notes playing, each with its own envelope and shifter
+    voices: Vec<Voice>,
 
-    /// For PSOLA
-    pitch_shifter: Box<dyn PitchShifter + Send>,
+    /// A second shifter with the same sample loaded, it keeps playing the
+    /// stolen voice while fading out when all voices are busy
+    tail_shifter: Box<dyn PitchShifter + Send>,
+
+    /// The fading out playback, if any
+    tail: Option<RetriggerTail>,
+
+    /// Draws the start jitter of the notes
+    rng: XorShift,
+
+    /// Current MIDI pitch bend (-1.0-1.0), applied to all the voices
+    pitch_bend: f32,
+
+    /// Highest level the slot sent to its output during the current block
+    block_peak: f32,
 
     // HERE ARE THE DATA THAT ARE SHARED WITH THE GUI
     /// A copy of the buffer that the GUI can access for display
     shared_buffer: Arc<RwLock<Option<AudioData>>>,
 
-    /// A copy of the current position in the sample
-    shared_playback_position: Arc<AtomicU64>,
+    /// The playback progress through the displayed region of the sample
+    shared_playback_position: Arc<AtomicF32>,
+
+    /// The held peak level of the slot output, see `utils::decay_peak`
+    shared_peak: Arc<AtomicF32>,
+
+    /// Sample rate of the buffer played, 0 without a sample
+    shared_sample_rate: Arc<AtomicF32>,
+
+    /// The previously loaded paths, the most recent last
+    shared_load_history: Arc<RwLock<Vec<PathBuf>>>,
+
+    /// The fundamental found by the pitch shifter, NaN if it doesn't detect one or failed
+    shared_detected_frequency: Arc<AtomicF32>,
+
+    /// Whether the pitch shifter couldn't process the sample as intended
+    shared_pitch_detection_failed: Arc<AtomicBool>,
 }
 
 impl SamplePlayer {
@@ -97,64 +337,158 @@ impl SamplePlayer {
             params,
             index,
             buffer: None,
+            source_buffer: None,
+            playback_settings: None,
+            requested_playback: None,
+            analysis: None,
             sample_rate: 0.,
+            source_sample_rate: 0.,
             host_sample_rate: DEFAULT_SAMPLE_RATE,
-            midi_note: None,
             host_channels: 0,
+            host_bpm: DEFAULT_BPM as f32,
             sample_channels: 0,
-            adsr: Adsr::new(DEFAULT_SAMPLE_RATE),
-            pitch_shifter: Box::new(ClassicShifter::new()),
+            voices: (0..MAX_VOICES).map(|_| Voice::new()).collect(),
+            tail_shifter: Box::new(ClassicShifter::new()),
+            tail: None,
+            rng: XorShift::new(0x9E37_79B9 ^ index as u32),
+            pitch_bend: 0.,
+            block_peak: 0.,
 
             // THINGS FOR GUI
             shared_buffer: Arc::new(RwLock::new(None)),
-            shared_playback_position: Arc::new(AtomicU64::new(0)),
+            shared_playback_position: Arc::new(AtomicF32::new(0.)),
+            shared_peak: Arc::new(AtomicF32::new(0.)),
+            shared_sample_rate: Arc::new(AtomicF32::new(0.)),
+            shared_detected_frequency: Arc::new(AtomicF32::new(f32::NAN)),
+            shared_pitch_detection_failed: Arc::new(AtomicBool::new(false)),
+            shared_load_history: Arc::new(RwLock::new(Vec::new())),
         }
     }
 
     /// Starts playing the sample with the specified MIDI note.
     ///
     /// The note number is used for pitch calculation if the sample is set to tonal mode.
-    /// Only triggers if a sample buffer is loaded.
+    /// Only triggers if a sample buffer is loaded. The note gets a free voice, if they
+    /// are all busy the oldest one is stolen and faded out.
     ///
     /// # Arguments
     ///
     /// * `note` - MIDI note number (0-127, where 60 is middle C)
-    /// * `_velocity` - Note velocity (0.0-1.0, currently unused)
-    pub fn start_playing(&mut self, note: u8, _velocity: f32) {
+    /// * `velocity` - Note velocity (0.0-1.0), scales the gain by the velocity sensitivity
+    /// * `voice_id` - Host voice id of the note, used to match the note off
+    pub fn start_playing(&mut self, note: u8, velocity: f32, voice_id: Option<i32>) {
         // Only trigger if we have a buffer loaded
-        if self.buffer.is_some() {
-            // Calculate semitone difference from base note
-            let semitone_offset = note as i8 - BASE_NOTE as i8;
-
-            // Set the note that is currently playing
-            self.midi_note = Some(semitone_offset);
-
-            // Trigger the adsr
-            self.adsr.note_on();
-
-            // Trigger the shifters
-            let semitone_offset = self.get_semitone_offset();
-            let sr_correction = self.get_sr_correction();
-            self.pitch_shifter.trigger(sr_correction, semitone_offset);
-
-            // log start playing
-            nih_log!(
-                "{} - Note playing : {} (with base note offset {}) / Playback rate : {} / sr correction {}",
-                self.index,
-                utils::semitones_to_note(note as i32),
-                semitone_offset,
-                semitone_offset,
-                sr_correction
-            );
+        if self.buffer.is_none() {
+            return;
+        }
+
+        // A free voice, or the one that has been playing for the longest time
+        let voice_index = self
+            .voices
+            .iter()
+            .position(|voice| voice.is_idling())
+            .unwrap_or_else(|| {
+                self.voices
+                    .iter()
+                    .enumerate()
+                    .max_by(|(_, a), (_, b)| a.elapsed.total_cmp(&b.elapsed))
+                    .map(|(index, _)| index)
+                    .unwrap_or(0)
+            });
+
+        let fade_frames = self.get_params().retrigger_fade.value() * self.host_sample_rate;
+        let velocity_sensitivity = self.get_params().velocity_sensitivity.value();
+        let semitone_offset = self.get_semitone_offset(Some(note));
+        let sr_correction = self.get_sr_correction();
+        let high_quality = self.params.high_quality.value();
+        let preserve_formants = self.get_params().preserve_formants.value();
+        let interpolation = self.get_params().interpolation.value();
+
+        // Humanize by starting a bit further in the sample, nothing is drawn without jitter
+        let max_jitter = self.get_params().start_jitter.value() / 1000. * self.host_sample_rate;
+        let jitter = if max_jitter > 0. {
+            self.rng.next_f32() * max_jitter
+        } else {
+            0.
+        };
+
+        let voice = &mut self.voices[voice_index];
+
+        // Stealing a playing voice, it is kept on the tail shifter
+        // and faded out instead of being cut
+        if !voice.is_idling() {
+            self.tail = if fade_frames >= 1. {
+                std::mem::swap(&mut voice.pitch_shifter, &mut self.tail_shifter);
+                Some(RetriggerTail {
+                    position: voice.elapsed,
+                    jitter: voice.jitter,
+                    gain: voice.adsr.safe_current_value()
+                        * voice.velocity_gain(velocity_sensitivity),
+                    filter: voice.filter,
+                    remaining: fade_frames,
+                    length: fade_frames,
+                })
+            } else {
+                None
+            };
         }
+
+        voice.note = Some(note);
+        voice.voice_id = voice_id;
+        voice.velocity = velocity;
+        voice.elapsed = 0.;
+        voice.jitter = jitter;
+        voice.pump.reset();
+        voice.filter.reset();
+
+        // Trigger the adsr
+        voice.adsr.note_on();
+
+        // Trigger the shifter
+        voice.pitch_shifter.set_high_quality(high_quality);
+        voice.pitch_shifter.set_preserve_formants(preserve_formants);
+        voice.pitch_shifter.set_interpolation(interpolation);
+        voice.pitch_shifter.trigger(sr_correction, semitone_offset);
+
+        // log start playing
+        nih_log!(
+            "{} - Note playing : {} (voice {}) / Semitone offset : {} / sr correction {}",
+            self.index,
+            utils::semitones_to_note(note as i32),
+            voice_index,
+            semitone_offset,
+            sr_correction
+        );
     }
 
-    /// Stops the sample playback by triggering the ADSR release phase.
+    /// Stops the voices playing `note` by triggering their ADSR release phase.
     ///
+    /// Other notes keep playing, if the host gives a `voice_id` only that voice is released.
     /// The sample will continue playing through its release envelope
     /// before becoming silent.
-    pub fn stop_playing(&mut self) {
-        self.adsr.note_off();
+    pub fn stop_playing(&mut self, note: u8, voice_id: Option<i32>) {
+        for voice in self
+            .voices
+            .iter_mut()
+            .filter(|voice| voice.matches(note, voice_id))
+        {
+            voice.adsr.note_off();
+        }
+    }
+
+    /// Silences all the voices at once, when another slot of the choke group starts.
+    pub fn choke(&mut self) {
+        for voice in self.voices.iter_mut() {
+            voice.reset();
+        }
+        self.tail = None;
+    }
+
+    /// Sets the MIDI pitch bend, from -1.0 (full bend down) to 1.0 (full bend up).
+    ///
+    /// The playing notes follow the bend from the next processed block.
+    pub fn set_pitch_bend(&mut self, pitch_bend: f32) {
+        self.pitch_bend = pitch_bend.clamp(-1., 1.);
     }
 
     /// Updates the target sample rate for proper pitch calculation.
@@ -166,10 +500,74 @@ impl SamplePlayer {
     /// * `sample_rate` - New target sample rate in Hz
     pub fn change_sample_rate_output(&mut self, sample_rate: f32) {
         self.host_sample_rate = sample_rate;
-        self.adsr.set_sample_rate(sample_rate);
+        for voice in self.voices.iter_mut() {
+            voice.adsr.set_sample_rate(sample_rate);
+        }
+
+        // A resampled sample follows the new rate once `should_prepare_playback` asked for it
         nih_log!("Set new host sample rate : {}", sample_rate);
     }
 
+    /// Returns how a sample recorded at `source_sample_rate` should be prepared
+    fn wanted_playback(&self, source_sample_rate: f32) -> PlaybackSettings {
+        PlaybackSettings::new(
+            &self.params,
+            self.index,
+            source_sample_rate,
+            self.host_sample_rate,
+        )
+    }
+
+    /// Returns whether the playback must be prepared again because `resample_to_host`,
+    /// the host rate or `preserve_formants` changed since the sample was loaded.
+    ///
+    /// Called every block, it only returns `true` once per change, the plugin then sends
+    /// a `TaskRequests::PreparePlayback` and the result comes back through `set_playback`.
+    pub fn should_prepare_playback(&mut self) -> bool {
+        let Some(loaded) = self.playback_settings else {
+            return false;
+        };
+
+        let wanted = self.wanted_playback(self.source_sample_rate);
+        if wanted == loaded {
+            self.requested_playback = None;
+            return false;
+        }
+        if self.requested_playback == Some(wanted) {
+            return false;
+        }
+        self.requested_playback = Some(wanted);
+        true
+    }
+
+    /// Swaps in a playback prepared in the background, see `should_prepare_playback`.
+    ///
+    /// It is dropped if another sample was loaded or the settings changed meanwhile.
+    pub fn set_playback(&mut self, audio_data: AudioData) {
+        let Some(playback) = audio_data.playback else {
+            return;
+        };
+        let is_loaded = self
+            .source_buffer
+            .as_ref()
+            .is_some_and(|source| Arc::ptr_eq(source, &audio_data.data));
+        if !is_loaded || playback.settings != self.wanted_playback(self.source_sample_rate) {
+            return;
+        }
+
+        self.requested_playback = None;
+        self.load_playback(playback);
+    }
+
+    /// Sets the tempo of the host, called every block so it doesn't log.
+    ///
+    /// # Arguments
+    ///
+    /// * `bpm` - Tempo in beats per minute
+    pub fn change_bpm(&mut self, bpm: f32) {
+        self.host_bpm = bpm;
+    }
+
     /// Sets the number of output channels for proper buffer indexing.
     ///
     /// # Arguments
@@ -185,34 +583,81 @@ impl SamplePlayer {
     /// # Arguments
     ///
     /// * `file_path` - Path to set, or None to clear
+    /// * `channel` - The only channel of the file that is used, or None for all of them
     ///
     /// # Returns
     ///
     /// * `Ok(())` if successful
     /// * `Err(...)` if the parameter write lock couldn't be acquired
-    fn set_sample_path(&self, file_path: Option<&Path>) -> Result<(), Box<dyn std::error::Error>> {
+    fn set_sample_path(
+        &self,
+        file_path: Option<&Path>,
+        channel: Option<usize>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
         let mut path_guard = self
             .get_params()
             .sample_path
             .write()
             .map_err(|_| "Failed to acquire write lock on sample path")?;
+        let mut channel_guard = self
+            .get_params()
+            .sample_channel
+            .write()
+            .map_err(|_| "Failed to acquire write lock on sample channel")?;
 
         *path_guard = file_path.map(|p| p.to_path_buf());
+        *channel_guard = channel;
         Ok(())
     }
 
+    /// Loads the played buffer in every shifter, they all share its analysis
+    fn load_playback(&mut self, playback: Playback) {
+        let Playback {
+            settings,
+            data,
+            analysis,
+        } = playback;
+        let shifters = self
+            .voices
+            .iter_mut()
+            .map(|voice| &mut voice.pitch_shifter)
+            .chain([&mut self.tail_shifter]);
+        // They all analyse the same sample, so they all fail the same way
+        let mut status = Ok(());
+        for shifter in shifters {
+            status = Self::load_shifter(
+                shifter.as_mut(),
+                &data,
+                Some(&analysis),
+                self.sample_channels,
+                settings.sample_rate,
+            );
+        }
+        self.publish_pitch_status(status);
+        self.buffer = Some(data);
+        self.analysis = Some(analysis);
+        self.sample_rate = settings.sample_rate;
+        self.playback_settings = Some(settings);
+        self.shared_sample_rate
+            .store(settings.sample_rate, Ordering::Relaxed);
+        self.tail = None;
+    }
+
     /// Updates both internal and shared audio buffers with new data.
     ///
     /// This method handles updating the internal buffer for audio processing
     /// and the shared buffer for GUI display. If updating the shared buffer fails,
-    /// audio processing continues uninterrupted.
+    /// audio processing continues uninterrupted. The shared buffer always keeps
+    /// the original data, only the internal one may be resampled.
+    ///
+    /// The playback should have been prepared in the background, it is only
+    /// prepared here for data that wasn't.
     ///
     /// # Arguments
     ///
     /// * `audio_data` - New audio data to set, or None to clear buffers
     fn update_buffers(&mut self, audio_data: Option<AudioData>) {
         // Update internal buffer and metadata
-        self.buffer = audio_data.as_ref().map(|data| data.data.clone());
         self.sample_channels = audio_data
             .as_ref()
             .map(|data| data.spec.channels as usize)
@@ -220,15 +665,29 @@ impl SamplePlayer {
 
         // Update sample rate if we have audio data
         if let Some(data) = audio_data.as_ref() {
-            self.pitch_shifter.load_sample(
-                &data.data,
-                data.spec.channels as usize,
-                data.spec.sample_rate as f32,
-            );
-            self.sample_rate = data.spec.sample_rate as f32;
+            let source_sample_rate = data.spec.sample_rate as f32;
+            let playback = data
+                .playback
+                .clone()
+                .unwrap_or_else(|| Playback::new(data, self.wanted_playback(source_sample_rate)));
+            self.source_sample_rate = source_sample_rate;
+            self.source_buffer = Some(Arc::clone(&data.data));
+            self.requested_playback = None;
+            self.load_playback(playback);
         } else {
-            self.pitch_shifter.clear_sample();
+            self.buffer = None;
+            self.source_buffer = None;
+            self.playback_settings = None;
+            self.requested_playback = None;
+            self.shared_sample_rate.store(0., Ordering::Relaxed);
+            self.analysis = None;
+            for voice in self.voices.iter_mut() {
+                voice.pitch_shifter.clear_sample();
+            }
+            self.tail_shifter.clear_sample();
+            self.publish_pitch_status(Ok(()));
         }
+        self.tail = None;
 
         // Update shared buffer for GUI (non-critical operation)
         if let Ok(mut shared_guard) = self.shared_buffer.write() {
@@ -258,7 +717,59 @@ impl SamplePlayer {
         self.update_buffers(Some(audio_data));
 
         // Set the file path in parameters
-        self.set_sample_path(Some(file_path))?;
+        self.set_sample_path(Some(file_path), None)?;
+
+        Ok(())
+    }
+
+    /// Sets a previously loaded audio file back as the current sample.
+    ///
+    /// The history is kept by the task executor, the restored path was already taken
+    /// out of it so successive undos walk back through it.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_path` - Path to the restored audio file
+    /// * `audio_data` - Loaded audio data
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` if successful
+    /// * `Err(...)` if there was an error setting the file path
+    pub fn restore_audio_file(
+        &mut self,
+        file_path: &Path,
+        audio_data: AudioData,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.update_buffers(Some(audio_data));
+        self.set_sample_path(Some(file_path), None)?;
+
+        Ok(())
+    }
+
+    /// Loads a single channel of an audio file and sets it as the current sample.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_path` - Path to the audio file to load
+    /// * `channel` - The channel of the file the audio data was extracted from
+    /// * `audio_data` - Loaded mono audio data
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` if successful
+    /// * `Err(...)` if there was an error setting the file path
+    pub fn load_and_set_audio_channel(
+        &mut self,
+        file_path: &Path,
+        channel: usize,
+        audio_data: AudioData,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // Update buffers with new audio data
+        self.update_buffers(Some(audio_data));
+
+        // Set the file path and channel in parameters
+        self.set_sample_path(Some(file_path), Some(channel))?;
 
         Ok(())
     }
@@ -273,10 +784,13 @@ impl SamplePlayer {
         // Clear buffers and reset state
         self.update_buffers(None);
         self.sample_rate = 0.;
-        self.adsr.reset();
+        self.source_sample_rate = 0.;
+        for voice in self.voices.iter_mut() {
+            voice.reset();
+        }
 
         // Clear the file path
-        self.set_sample_path(None)?;
+        self.set_sample_path(None, None)?;
 
         Ok(())
     }
@@ -305,33 +819,56 @@ impl SamplePlayer {
             }
         };
 
+        // Get the stored channel, if the sample only uses one of them
+        let channel = *self
+            .get_params()
+            .sample_channel
+            .read()
+            .map_err(|_| "Failed to acquire read lock on sample channel")?;
+
         // Load and set the audio data
-        let audio_data = utils::load_audio_file(&file_path)?;
-        self.update_buffers(Some(audio_data));
+        let mut audio_data = utils::load_audio_file(&file_path)?;
+        if let Some(channel) = channel {
+            audio_data = audio_data
+                .extract_channel(channel)
+                .ok_or("Stored sample channel doesn't exist in the file")?;
+        }
+        let settings = self.wanted_playback(audio_data.spec.sample_rate as f32);
+        self.update_buffers(Some(audio_data.prepared(settings)));
 
         Ok(())
     }
 
-    /// Calculates the current semitone offset.
+    /// Calculates the semitone offset of a note.
     ///
     /// semitone_offset combines:
-    /// - Semitone offset parameter (user fine tuning adjustment)
+    /// - Semitone offset parameter (user tuning adjustment)
+    /// - Fine tune parameter, in cents
     /// - MIDI note offset from root note (if tonal mode is enabled)
     ///
     /// If `is_tonal` parameter is false, MIDI note AND the root note has no influence on pitch.
+    /// In stack mode, the root note is kept so every slot plays relative to its own root,
+    /// only the distance between the MIDI note and the base note transposes the stack.
     #[inline]
-    pub fn get_semitone_offset(&self) -> f32 {
+    pub fn get_semitone_offset(&self, note: Option<u8>) -> f32 {
         // Cache params
         let params = self.get_params();
 
         // Parameter offset (user tuning adjustment)
-        let param_note_offset = params.semitone_offset.value() as f32;
+        let param_note_offset =
+            params.semitone_offset.value() as f32 + params.fine_tune.value() / CENTS_PER_SEMITONE;
 
         // MIDI note offset from root note
         let midi_note_offset = if params.is_tonal.value() {
-            let midi_offset = self.midi_note.unwrap_or(0) as f32;
-            let root_note = params.root_note.value() as f32;
-            midi_offset - root_note
+            // Semitone difference from base note
+            let base_note = self.params.base_note.value() as f32;
+            let midi_offset = note.map_or(0., |note| note as f32 - base_note);
+            if self.params.stack_mode.value() {
+                midi_offset
+            } else {
+                let root_note = params.root_note.value() as f32;
+                midi_offset - root_note
+            }
         } else {
             0.
         };
@@ -339,6 +876,55 @@ impl SamplePlayer {
         param_note_offset + midi_note_offset
     }
 
+    /// Returns the pitch bend in semitones, scaled by the pitch bend range.
+    #[inline]
+    pub fn get_pitch_bend_offset(&self) -> f32 {
+        self.pitch_bend * self.params.pitch_bend_range.value() as f32
+    }
+
+    /// Returns the number of frames of the loaded sample, 0 if there is none.
+    #[inline]
+    pub fn get_num_frames(&self) -> usize {
+        match (self.buffer.as_ref(), self.sample_channels) {
+            (Some(buffer), channels) if channels > 0 => buffer.len() / channels,
+            _ => 0,
+        }
+    }
+
+    /// Returns the loop region in frames of the sample, clamped to the sample length.
+    ///
+    /// `None` if looping is disabled or the loop start isn't before its end.
+    fn get_loop_region(&self) -> Option<LoopRegion> {
+        let params = self.get_params();
+        if !params.loop_enabled.value() {
+            return None;
+        }
+
+        let num_frames = self.get_num_frames() as f32;
+        let loop_start = (params.loop_start.value() * self.sample_rate).clamp(0., num_frames);
+        let loop_end = (params.loop_end.value() * self.sample_rate).clamp(0., num_frames);
+        let crossfade = params.loop_crossfade.value() / 1000. * self.sample_rate;
+        (loop_start < loop_end).then(|| LoopRegion::new(loop_start, loop_end, crossfade))
+    }
+
+    /// Returns the constant power gains of the pan, for the left and right channels.
+    ///
+    /// They are normalized so the center is unity gain, `None` when centered
+    /// or when the output isn't stereo.
+    fn get_pan_gains(&self) -> Option<[f32; 2]> {
+        let pan = self.get_params().pan.value();
+        if pan == 0. || self.host_channels != 2 {
+            return None;
+        }
+
+        let angle = (pan + 1.) * std::f32::consts::FRAC_PI_4;
+        let (right, left) = angle.sin_cos();
+        Some([
+            left * std::f32::consts::SQRT_2,
+            right * std::f32::consts::SQRT_2,
+        ])
+    }
+
     /// Returns the sample rate correction factor.
     ///
     /// This accounts for differences between the sample's original sample rate
@@ -356,23 +942,25 @@ impl SamplePlayer {
         // Clear sample data
         self.update_buffers(None);
 
-        // Reset playback state
-        self.midi_note = None;
-
-        // Reset ADSR envelope
-        self.adsr.reset();
-
-        // clear pitch_shifter
-        self.pitch_shifter.clear_sample();
+        // Reset playback state and clear the shifters
+        for voice in self.voices.iter_mut() {
+            voice.reset();
+            voice.pitch_shifter.clear_sample();
+        }
+        self.tail_shifter.clear_sample();
+        self.tail = None;
     }
 
     /// Resets the playback state without clearing the loaded sample.
     ///
-    /// This resets the ADSR envelope, clears the current note, and
-    /// returns the playback position to the beginning.
+    /// This resets the ADSR envelopes, clears the current notes, and
+    /// returns the playback positions to the beginning.
     pub fn reset(&mut self) {
-        self.adsr.reset();
-        self.midi_note = None;
+        for voice in self.voices.iter_mut() {
+            voice.reset();
+        }
+        self.tail = None;
+        self.pitch_bend = 0.;
     }
 
     /// Returns whether this sample is currently muted.
@@ -385,103 +973,499 @@ impl SamplePlayer {
         self.get_params().muted.value()
     }
 
+    /// Returns whether this sample is soloed
+    #[inline]
+    pub fn is_soloed(&self) -> bool {
+        self.get_params().soloed.value()
+    }
+
+    /// Returns whether this sample can be heard, it is not muted and either
+    /// nothing is soloed (`any_soloed`) or it is soloed too
+    #[inline]
+    pub fn is_audible(&self, any_soloed: bool) -> bool {
+        !self.is_muted() && (!any_soloed || self.is_soloed())
+    }
+
+    /// Returns whether this sample responds to notes on `channel` (0-15)
+    pub fn accepts_channel(&self, channel: u8) -> bool {
+        match self.get_params().midi_channel.value() {
+            0 => true,
+            slot_channel => slot_channel == channel as i32 + 1,
+        }
+    }
+
+    /// Returns the output bus this sample plays on, `None` for the main output
+    pub fn get_output_bus(&self) -> Option<usize> {
+        match self.get_params().output_bus.value() {
+            0 => None,
+            bus => Some(bus as usize - 1),
+        }
+    }
+
+    /// Returns the choke group of this sample, `None` if it doesn't choke
+    pub fn get_choke_group(&self) -> Option<i32> {
+        match self.get_params().choke_group.value() {
+            0 => None,
+            group => Some(group),
+        }
+    }
+
     /// Returns whether this sample should produce silence.
     ///
     /// This is a convenience method that combines all conditions that would
     /// result in no audio output. A sample is considered silent if:
-    /// - The ADSR envelope is in idle state (not playing)
+    /// - All the ADSR envelopes are in idle state and no stolen voice is fading out
     /// - The sample is muted via parameters
     /// - No audio buffer is loaded
     #[inline]
     pub fn is_silent(&self) -> bool {
-        self.adsr.is_idling() || self.is_muted() || self.buffer.is_none()
+        let idling = self.voices.iter().all(|voice| voice.is_idling()) && self.tail.is_none();
+        idling || self.is_muted() || self.buffer.is_none()
+    }
+
+    /// Creates an empty pitch shifter of the given kind
+    fn new_shifter(kind: &PitchShiftKind) -> Box<dyn PitchShifter + Send> {
+        match kind {
+            PitchShiftKind::Classic => Box::new(ClassicShifter::new()),
+            PitchShiftKind::Psola => Box::new(PsolaShifter::new()),
+        }
+    }
+
+    /// Loads the played buffer in a shifter, with its analysis when it was computed beforehand
+    fn load_shifter(
+        shifter: &mut dyn PitchShifter,
+        sample_buffer: &Arc<Vec<f32>>,
+        analysis: Option<&Arc<PsolaAnalysis>>,
+        channel_number: usize,
+        sample_rate: f32,
+    ) -> Result<(), PitchShiftError> {
+        match analysis {
+            Some(analysis) => shifter.load_analysed_sample(analysis),
+            None => shifter.load_sample(sample_buffer, channel_number, sample_rate),
+        }
     }
 
+    /// Adds the sample playback to the `range` of samples of the buffer.
+    ///
+    /// The block is split in ranges at the note events, so notes start on their own sample.
+    /// If `tick` is set, a short click is added at the effective start of
+    /// each note, as a monitoring aid to align the layers.
+    /// If a `send` buffer is given, the playback is also added to it, scaled by `send_gain`.
     #[inline]
-    pub fn process(&mut self, buffer: &mut Buffer, process_count: f32) {
+    pub fn process(
+        &mut self,
+        buffer: &mut Buffer,
+        range: Range<usize>,
+        tick: bool,
+        send: Option<&mut Buffer>,
+    ) {
         if self.is_silent() {
             return;
         }
 
         let desired_kind = self.get_params().pitch_shift_kind.value();
-        let current_kind = self.pitch_shifter.kind();
-
-        if current_kind != desired_kind || !self.pitch_shifter.ready() {
-            nih_log!(
-                "Changing pitch shifter ! {:?} != {:?} and ready : {} ?",
-                current_kind,
-                desired_kind,
-                self.pitch_shifter.ready()
-            );
-            if let Some(buffer) = self.buffer.as_ref() {
-                self.pitch_shifter = match self.get_params().pitch_shift_kind.value() {
-                    PitchShiftKind::Classic => Box::new(ClassicShifter::new()),
-                    PitchShiftKind::Psola => Box::new(PsolaShifter::new()),
-                };
-                self.pitch_shifter
-                    .load_sample(buffer, self.sample_channels, self.sample_rate);
+
+        if let Some(sample_buffer) = self.buffer.as_ref() {
+            for index in 0..self.voices.len() {
+                // Idle voices are only ready once triggered
+                let voice = &self.voices[index];
+                let shifter = &voice.pitch_shifter;
+                let same_kind = shifter.kind() == desired_kind;
+                if same_kind && (shifter.ready() || voice.is_idling()) {
+                    continue;
+                }
+
+                // A shifter of the right kind already analysed the buffer,
+                // only its synthesis is missing so it is just triggered again
+                if !same_kind {
+                    nih_log!(
+                        "Changing pitch shifter of voice {} ! {:?} != {:?}",
+                        index,
+                        shifter.kind(),
+                        desired_kind,
+                    );
+                    // The tail shifter reports the status below
+                    let mut shifter = Self::new_shifter(&desired_kind);
+                    let _ = Self::load_shifter(
+                        shifter.as_mut(),
+                        sample_buffer,
+                        self.analysis.as_ref(),
+                        self.sample_channels,
+                        self.sample_rate,
+                    );
+                    self.voices[index].pitch_shifter = shifter;
+                }
 
                 // If there is a note running, we can trigger!
-                if self.midi_note.is_some() {
-                    let get_semitone_offset = self.get_semitone_offset();
+                if let Some(note) = self.voices[index].note {
+                    let high_quality = self.params.high_quality.value();
+                    let preserve_formants = self.get_params().preserve_formants.value();
+                    let interpolation = self.get_params().interpolation.value();
                     let sr_correction = self.get_sr_correction();
-                    self.pitch_shifter
-                        .trigger(sr_correction, get_semitone_offset);
+                    let semitone_offset = self.get_semitone_offset(Some(note));
+
+                    let shifter = &mut self.voices[index].pitch_shifter;
+                    shifter.set_high_quality(high_quality);
+                    shifter.set_preserve_formants(preserve_formants);
+                    shifter.set_interpolation(interpolation);
+                    shifter.trigger(sr_correction, semitone_offset);
                 }
             }
+
+            // The tail shifter must use the same algorithm
+            if self.tail_shifter.kind() != desired_kind {
+                self.tail_shifter = Self::new_shifter(&desired_kind);
+                let status = Self::load_shifter(
+                    self.tail_shifter.as_mut(),
+                    sample_buffer,
+                    self.analysis.as_ref(),
+                    self.sample_channels,
+                    self.sample_rate,
+                );
+                self.tail = None;
+                self.publish_pitch_status(status);
+            }
         }
 
-        self.process_buffer(buffer, process_count)
+        self.process_buffer(buffer, range, tick, send)
     }
 
     #[inline]
-    fn process_buffer(&mut self, buffer: &mut Buffer, process_count: f32) {
+    fn process_buffer(
+        &mut self,
+        buffer: &mut Buffer,
+        range: Range<usize>,
+        tick: bool,
+        mut send: Option<&mut Buffer>,
+    ) {
         let params = self.get_params();
 
         // We don't want those param to be any smoothed!
         let attack = params.attack.value();
+        let hold = params.hold.value();
         let decay = params.decay.value();
         let sustain = params.sustain.value();
         let release = params.release.value();
+        let adsr_curve = params.adsr_curve.value();
         let gain = params.gain.value();
+        let send_gain = params.send_gain.value();
+        let width = params.width.value();
+        let pan = self.get_pan_gains();
+        let velocity_sensitivity = params.velocity_sensitivity.value();
+        let vel_to_attack = params.vel_to_attack.value();
+        let pump_amount = params.pump_amount.value();
+        let pump_recovery = (-1. / (params.pump_time.value() * self.host_sample_rate)).exp();
         let top_gain = self.params.gain.value();
         let frames_offset = params.start_offset.value() * self.host_sample_rate;
+        let host_sample_rate = self.host_sample_rate;
+        let filter_mode = params.filter_mode.value();
+        let filter_cutoff = params.filter_cutoff.value();
+
+        // The mix is smoothed over the range, each voice follows the same ramp
+        let dry_wet_start = params.dry_wet.smoothed.previous_value();
+        let dry_wet_end = if range.is_empty() {
+            dry_wet_start
+        } else {
+            params.dry_wet.smoothed.next_step(range.len() as u32)
+        };
+        let dry_wet_step = (dry_wet_end - dry_wet_start) / range.len().max(1) as f32;
+        let dry_wet_at = |index: usize| dry_wet_start + dry_wet_step * (index + 1) as f32;
+
+        // The sample effectively starts later with a negative start offset
+        let tick_position = (-frames_offset).max(0.).round();
+
+        // The effective end of the sample (in sample frames), trim_end removes the tail
+        let trim_end = params.trim_end.value();
+        let end_frame = self.get_num_frames() as f32 - trim_end * self.sample_rate;
+
+        // Edge fades, the fade in follows the time played (in host frames)
+        // and the fade out the distance to the end (in sample frames).
+        // A trimmed end always gets at least a short fade, it cuts through the sample.
+        let fade_in = params.fade_in.value() / 1000. * host_sample_rate;
+        let fade_out_ms = if trim_end > 0. {
+            params.fade_out.value().max(TRIM_FADE_MS)
+        } else {
+            params.fade_out.value()
+        };
+        let fade_out = fade_out_ms / 1000. * self.sample_rate;
+
+        // The loop region (in sample frames), only played while the note is held
+        let loop_region = self.get_loop_region();
+        let pitch_bend = self.get_pitch_bend_offset();
+        let pitch_env_amount = params.pitch_env_amount.value();
+        let pitch_env_time = params.pitch_env_time.value();
 
-        // Get the blend value
+        // Blend params, the blend value depends on the time since each note started
         let group = params.blend_group.value();
-        let blend_time = self.params.blend_time.value();
-        let blend_transition = self.params.blend_transition.value();
-        let current_time = process_count / self.host_sample_rate;
-        let blend_gain = utils::get_blend_value(group, current_time, blend_time, blend_transition);
-
-        for (position, frame) in buffer
-            .iter_samples()
-            .enumerate()
-            .map(|(i, sample)| (i as f32 + process_count, sample))
-        {
-            // Get the adrs value
-            let adrs_envelope = self.adsr.next(attack, decay, sustain, release);
-            let offset_position = utils::optional_positive_sub(position, -frames_offset);
-            let all_gains = top_gain * adrs_envelope * blend_gain * gain;
+        let layer = params.get_blend_layer();
+        let (blend_time, blend_transition) = self.params.get_blend_seconds(self.host_bpm);
+        let (blend_points, num_blend_points) = self.params.get_blend_points(self.host_bpm);
+        let blend_curve = self.params.blend_curve.value();
+        let blend_gain_at = |current_time: f32| {
+            let value = match layer {
+                Some(layer) => utils::get_layer_blend_value(
+                    layer,
+                    current_time,
+                    &blend_points[..num_blend_points],
+                ),
+                None => utils::get_blend_value(group, current_time, blend_time, blend_transition),
+            };
+            utils::apply_blend_curve(blend_curve, value)
+        };
 
-            if let Some(frame_output) =
-                offset_position.and_then(|pos| self.pitch_shifter.get_frame(pos))
+        // Level sent to the output, for the meter
+        let mut block_peak: f32 = 0.;
+
+        for voice in self.voices.iter_mut().filter(|voice| !voice.is_idling()) {
+            let elapsed = voice.elapsed;
+            let start_frame = -frames_offset - voice.jitter;
+            let current_time = elapsed / host_sample_rate;
+            let blend_gain = blend_gain_at(current_time);
+            let shifter_trim = voice.pitch_shifter.gain_trim();
+            let velocity_gain = voice.velocity_gain(velocity_sensitivity);
+            let note_attack = voice.attack_time(attack, vel_to_attack);
+            voice
+                .filter
+                .set(filter_mode, filter_cutoff, host_sample_rate);
+            voice
+                .pitch_shifter
+                .set_loop(loop_region.filter(|_| !voice.adsr.is_releasing()));
+            voice.adsr.set_curve(adsr_curve);
+            voice.adsr.set_hold(hold);
+
+            // The send is taken post slot gain and envelope
+            let mut send_frames = send
+                .as_deref_mut()
+                .map(|send| send.iter_samples().skip(range.start));
+
+            // The bend applied to the shifter, it is only bent again when it moves
+            let mut applied_bend = f32::NAN;
+
+            for (index, position, mut frame) in buffer
+                .iter_samples()
+                .skip(range.start)
+                .take(range.len())
+                .enumerate()
+                .map(|(i, sample)| (i, i as f32 + elapsed, sample))
             {
-                match frame_output {
-                    FrameOutput::Mono(v) => {
-                        for sample in frame.into_iter() {
-                            *sample += v * all_gains;
-                        }
+                // The pitch envelope follows the time since the note started, like the blend.
+                // It moves slowly, so it is only computed at a control rate
+                if index % PITCH_ENV_INTERVAL == 0 {
+                    let bend = pitch_bend
+                        + utils::get_pitch_envelope(
+                            pitch_env_amount,
+                            pitch_env_time,
+                            position / host_sample_rate,
+                        );
+                    if bend != applied_bend {
+                        voice.pitch_shifter.bend(bend);
+                        applied_bend = bend;
                     }
-                    FrameOutput::Stereo(stero_v) if frame.len() == 2 => {
-                        for (sample, v) in frame.into_iter().zip(stero_v) {
-                            *sample += v * all_gains;
-                        }
+                }
+
+                // Get the adrs value
+                let adrs_envelope = voice.adsr.next(note_attack, decay, sustain, release);
+                let offset_position = utils::optional_positive_sub(position, start_frame);
+                let all_gains =
+                    top_gain * adrs_envelope * blend_gain * gain * shifter_trim * velocity_gain;
+                let mut send_frame = send_frames.as_mut().and_then(|frames| frames.next());
+
+                // Nothing is played past the trimmed end
+                let offset_position = offset_position
+                    .filter(|&pos| voice.pitch_shifter.get_position(pos) < end_frame);
+
+                if let Some((pos, frame_output)) = offset_position
+                    .and_then(|pos| voice.pitch_shifter.get_frame(pos).map(|frame| (pos, frame)))
+                {
+                    let fade_gain = Self::edge_fade(
+                        pos - (-start_frame).max(0.),
+                        end_frame - voice.pitch_shifter.get_position(pos),
+                        fade_in,
+                        fade_out,
+                    );
+                    let all_gains = all_gains * fade_gain;
+                    let frame_output = Self::mix_dry_wet(
+                        frame_output,
+                        voice.filter.process(frame_output),
+                        dry_wet_at(index),
+                    );
+                    let frame_output = Self::apply_pan(Self::apply_width(frame_output, width), pan);
+                    let level = Self::frame_level(frame_output) * all_gains;
+                    let pump_gain =
+                        voice
+                            .pump
+                            .next(level, pos / host_sample_rate, pump_amount, pump_recovery);
+                    let all_gains = all_gains * pump_gain;
+                    block_peak = block_peak.max(level * pump_gain);
+
+                    Self::mix_frame(&mut frame, frame_output, all_gains);
+                    if let Some(send_frame) = send_frame.as_mut() {
+                        Self::mix_frame(send_frame, frame_output, all_gains * send_gain);
+                    }
+                }
+
+                // Alignment tick, not affected by the envelope
+                if tick && position == tick_position {
+                    for sample in frame.iter_mut() {
+                        *sample += TICK_GAIN;
                     }
-                    _ => {
-                        // Unsupported!
+                }
+            }
+
+            voice.elapsed += range.len() as f32;
+        }
+
+        // The stolen voice fading out
+        if let Some(tail) = self.tail.as_mut() {
+            let current_time = tail.position / host_sample_rate;
+            let blend_gain = blend_gain_at(current_time);
+            let shifter_trim = self.tail_shifter.gain_trim();
+            tail.filter
+                .set(filter_mode, filter_cutoff, host_sample_rate);
+            self.tail_shifter.set_loop(None);
+            let mut send_frames = send
+                .as_deref_mut()
+                .map(|send| send.iter_samples().skip(range.start));
+
+            for (index, mut frame) in buffer
+                .iter_samples()
+                .skip(range.start)
+                .take(range.len())
+                .enumerate()
+            {
+                let tail_gain = tail.gain * (tail.remaining / tail.length).max(0.);
+                let all_gains = top_gain * tail_gain * blend_gain * gain * shifter_trim;
+                let mut send_frame = send_frames.as_mut().and_then(|frames| frames.next());
+
+                let start_frame = -frames_offset - tail.jitter;
+                if let Some((pos, frame_output)) =
+                    utils::optional_positive_sub(tail.position, start_frame)
+                        .filter(|&pos| self.tail_shifter.get_position(pos) < end_frame)
+                        .and_then(|pos| self.tail_shifter.get_frame(pos).map(|frame| (pos, frame)))
+                {
+                    let all_gains = all_gains
+                        * Self::edge_fade(
+                            pos - (-start_frame).max(0.),
+                            end_frame - self.tail_shifter.get_position(pos),
+                            fade_in,
+                            fade_out,
+                        );
+                    let frame_output = Self::mix_dry_wet(
+                        frame_output,
+                        tail.filter.process(frame_output),
+                        dry_wet_at(index),
+                    );
+                    let frame_output = Self::apply_pan(Self::apply_width(frame_output, width), pan);
+                    block_peak = block_peak.max(Self::frame_level(frame_output) * all_gains);
+                    Self::mix_frame(&mut frame, frame_output, all_gains);
+                    if let Some(send_frame) = send_frame.as_mut() {
+                        Self::mix_frame(send_frame, frame_output, all_gains * send_gain);
                     }
                 }
+
+                tail.position += 1.;
+                tail.remaining -= 1.;
+            }
+
+            if tail.remaining <= 0. {
+                self.tail = None;
+            }
+        }
+
+        self.block_peak = self.block_peak.max(block_peak);
+    }
+
+    /// Gain of the fades at the edges of the played sample.
+    ///
+    /// `played` is the number of frames since the sample started to be heard and
+    /// `remaining` the number of frames left before its end, both in the unit of their fade.
+    /// A fade of 0 is bypassed.
+    #[inline]
+    fn edge_fade(played: f32, remaining: f32, fade_in: f32, fade_out: f32) -> f32 {
+        let fade_in_gain = if fade_in > 0. {
+            (played / fade_in).clamp(0., 1.)
+        } else {
+            1.
+        };
+        let fade_out_gain = if fade_out > 0. {
+            (remaining / fade_out).clamp(0., 1.)
+        } else {
+            1.
+        };
+        fade_in_gain * fade_out_gain
+    }
+
+    /// Mixes the raw frame with the processed one, fully processed at a mix of 1
+    #[inline]
+    fn mix_dry_wet(dry: FrameOutput, wet: FrameOutput, mix: f32) -> FrameOutput {
+        match (dry, wet) {
+            // Bypassed when fully wet, no rounding from the mix
+            _ if mix == 1. => wet,
+            (FrameOutput::Mono(dry), FrameOutput::Mono(wet)) => {
+                FrameOutput::Mono(dry + (wet - dry) * mix)
+            }
+            (FrameOutput::Stereo([dry_l, dry_r]), FrameOutput::Stereo([wet_l, wet_r])) => {
+                FrameOutput::Stereo([dry_l + (wet_l - dry_l) * mix, dry_r + (wet_r - dry_r) * mix])
+            }
+            _ => wet,
+        }
+    }
+
+    /// Scales the side of a stereo frame, mono frames are left as they are
+    #[inline]
+    fn apply_width(frame_output: FrameOutput, width: f32) -> FrameOutput {
+        match frame_output {
+            // Bypassed at the original width, no rounding from the matrix
+            FrameOutput::Stereo(_) if width == 1. => frame_output,
+            FrameOutput::Stereo([left, right]) => {
+                let mid = (left + right) * 0.5;
+                let side = (left - right) * 0.5 * width;
+                FrameOutput::Stereo([mid + side, mid - side])
+            }
+            _ => frame_output,
+        }
+    }
+
+    /// Applies the pan gains, mono frames are spread on both channels
+    #[inline]
+    fn apply_pan(frame_output: FrameOutput, pan: Option<[f32; 2]>) -> FrameOutput {
+        match (frame_output, pan) {
+            (FrameOutput::Mono(v), Some([left, right])) => {
+                FrameOutput::Stereo([v * left, v * right])
+            }
+            (FrameOutput::Stereo([l, r]), Some([left, right])) => {
+                FrameOutput::Stereo([l * left, r * right])
+            }
+            _ => frame_output,
+        }
+    }
+
+    /// Absolute peak of a frame of the shifter output
+    #[inline]
+    fn frame_level(frame_output: FrameOutput) -> f32 {
+        match frame_output {
+            FrameOutput::Mono(v) => v.abs(),
+            FrameOutput::Stereo([left, right]) => left.abs().max(right.abs()),
+            FrameOutput::Unsupported => 0.,
+        }
+    }
+
+    /// Adds a frame of the shifter output to the output frame
+    #[inline]
+    fn mix_frame(frame: &mut ChannelSamples, frame_output: FrameOutput, gain: f32) {
+        match frame_output {
+            FrameOutput::Mono(v) => {
+                for sample in frame.iter_mut() {
+                    *sample += v * gain;
+                }
+            }
+            FrameOutput::Stereo(stero_v) if frame.len() == 2 => {
+                for (sample, v) in frame.iter_mut().zip(stero_v) {
+                    *sample += v * gain;
+                }
+            }
+            _ => {
+                // Unsupported!
             }
         }
     }
@@ -490,20 +1474,193 @@ impl SamplePlayer {
         self.shared_buffer.clone()
     }
 
-    pub fn get_shared_position(&self) -> Arc<AtomicU64> {
+    pub fn get_shared_position(&self) -> Arc<AtomicF32> {
         self.shared_playback_position.clone()
     }
 
-    #[inline]
-    pub fn update_shared_position(&mut self, process_count: f32) {
-        // If sample is silent, position is 0
-        if self.is_silent() {
-            self.shared_playback_position.store(0, Ordering::Relaxed);
-            return;
+    pub fn get_shared_peak(&self) -> Arc<AtomicF32> {
+        self.shared_peak.clone()
+    }
+
+    pub fn get_shared_sample_rate(&self) -> Arc<AtomicF32> {
+        self.shared_sample_rate.clone()
+    }
+
+    pub fn get_shared_load_history(&self) -> Arc<RwLock<Vec<PathBuf>>> {
+        self.shared_load_history.clone()
+    }
+
+    pub fn get_shared_detected_frequency(&self) -> Arc<AtomicF32> {
+        self.shared_detected_frequency.clone()
+    }
+
+    pub fn get_shared_pitch_detection_failed(&self) -> Arc<AtomicBool> {
+        self.shared_pitch_detection_failed.clone()
+    }
+
+    /// Shares the fundamental found by the shifters and whether they failed
+    /// to process the sample, they all analysed the same one
+    fn publish_pitch_status(&self, status: Result<(), PitchShiftError>) {
+        if let Err(error) = status {
+            nih_log!("{} - Loading the pitch shifter: {}", self.index, error);
         }
+        self.shared_pitch_detection_failed
+            .store(status.is_err(), Ordering::Relaxed);
+
+        let frequency = self.tail_shifter.detected_frequency().unwrap_or(f32::NAN);
+        self.shared_detected_frequency
+            .store(frequency, Ordering::Relaxed);
+    }
+
+    /// Publishes the peak level of the block for the meter of the GUI, then starts a new block.
+    ///
+    /// `block_duration` is in seconds, the held peak decays over it.
+    #[inline]
+    pub fn update_shared_peak(&mut self, block_duration: f32) {
+        let previous = self.shared_peak.load(Ordering::Relaxed);
+        self.shared_peak.store(
+            utils::decay_peak(previous, self.block_peak, block_duration),
+            Ordering::Relaxed,
+        );
+        self.block_peak = 0.;
+    }
+
+    /// Publishes the playback progress through the displayed region for the GUI.
+    ///
+    /// The displayed region starts at the start offset and lasts `DISPLAYED_BEATS`,
+    /// so both editors just scale the progress to their width. It is 0 when silent.
+    #[inline]
+    pub fn update_shared_position(&mut self, bpm: f32) {
+        // The GUI follows the latest note
+        let latest_voice = self
+            .voices
+            .iter()
+            .filter(|voice| !voice.is_idling())
+            .min_by(|a, b| a.elapsed.total_cmp(&b.elapsed));
+
+        let start_offset = self.get_params().start_offset.value();
+        let display_frames =
+            utils::get_num_displayed_frames(utils::DISPLAYED_BEATS, self.source_sample_rate, bpm)
+                as f32;
+
+        let progress = match latest_voice {
+            Some(voice) if !self.is_muted() && self.sample_rate > 0. && display_frames > 0. => {
+                let frames_offset = start_offset * self.host_sample_rate;
+                let host_to_source = self.source_sample_rate / self.host_sample_rate;
+                match utils::optional_positive_sub(voice.elapsed, -frames_offset - voice.jitter) {
+                    // The GUI displays the original data, not the resampled one
+                    Some(pos) => {
+                        let position = voice.pitch_shifter.get_position(pos)
+                            * self.source_sample_rate
+                            / self.sample_rate;
+                        (position - start_offset * self.source_sample_rate) / display_frames
+                    }
+                    // Still in the silence before the sample starts
+                    None => voice.elapsed * host_to_source / display_frames,
+                }
+            }
+            _ => 0.,
+        };
 
-        let position = self.pitch_shifter.get_position(process_count);
         self.shared_playback_position
-            .store(position as u64, Ordering::Relaxed);
+            .store(progress, Ordering::Relaxed);
+    }
+}
+
+/// Adds the replaced sample path to a load history, unless it is the loaded one.
+///
+/// Called by the task executor once the new file is decoded, so only a successful load
+/// can be undone and the audio thread never touches the history. The oldest path is
+/// dropped once the history is full.
+pub fn push_load_history(history: &RwLock<Vec<PathBuf>>, replaced: &Path, loaded: &Path) {
+    if replaced == loaded {
+        return;
+    }
+    if let Ok(mut history) = history.write() {
+        if history.len() >= MAX_LOAD_HISTORY {
+            history.remove(0);
+        }
+        history.push(replaced.to_path_buf());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pump_ramps_in_without_click() {
+        let sample_rate = 48000.;
+        let recovery = (-1. / (0.1 * sample_rate)).exp();
+        let mut pump = Pump::default();
+
+        // The attack is the steepest part, at most the full depth over its length
+        // on top of the recovery
+        let max_step = 1. / (PUMP_ATTACK * sample_rate) + (1. - recovery);
+        let mut previous = 1.;
+        for frame in 0..4800 {
+            let gain = pump.next(1., frame as f32 / sample_rate, 1., recovery);
+            assert!(
+                (gain - previous).abs() <= max_step,
+                "gain jumped from {} to {} at frame {}",
+                previous,
+                gain,
+                frame
+            );
+            previous = gain;
+        }
+
+        // Still ducking once the attack is over
+        assert!(previous < 1.);
+    }
+
+    #[test]
+    fn test_edge_fade_ramps_from_and_to_zero() {
+        // 1ms fades at 48kHz
+        let fade = 48.;
+        assert_eq!(SamplePlayer::edge_fade(0., 1000., fade, fade), 0.);
+        assert_eq!(SamplePlayer::edge_fade(1000., 0., fade, fade), 0.);
+        assert_eq!(SamplePlayer::edge_fade(24., 1000., fade, fade), 0.5);
+        assert_eq!(SamplePlayer::edge_fade(1000., 24., fade, fade), 0.5);
+        assert_eq!(SamplePlayer::edge_fade(1000., 1000., fade, fade), 1.);
+
+        // The gain only grows during the fade in and only drops during the fade out
+        let ramp_in: Vec<f32> = (0..=48)
+            .map(|frame| SamplePlayer::edge_fade(frame as f32, 1000., fade, fade))
+            .collect();
+        assert!(ramp_in.windows(2).all(|pair| pair[0] < pair[1]));
+        let ramp_out: Vec<f32> = (0..=48)
+            .rev()
+            .map(|frame| SamplePlayer::edge_fade(1000., frame as f32, fade, fade))
+            .collect();
+        assert!(ramp_out.windows(2).all(|pair| pair[0] > pair[1]));
+
+        // Without fades the edges are left untouched
+        assert_eq!(SamplePlayer::edge_fade(0., 0., 0., 0.), 1.);
+    }
+
+    #[test]
+    fn test_harder_hits_shorten_the_attack() {
+        let mut voice = Voice::new();
+        voice.velocity = 0.5;
+        assert_eq!(voice.attack_time(0.1, 0.), 0.1);
+        assert!((voice.attack_time(0.1, 1.) - 0.05).abs() < 1e-6);
+
+        // A full velocity at the full amount removes the attack
+        voice.velocity = 1.;
+        assert_eq!(voice.attack_time(0.1, 1.), 0.);
+    }
+
+    #[test]
+    fn test_width_scales_the_side() {
+        let frame = FrameOutput::Stereo([0.75, 0.25]);
+        let width = |width| SamplePlayer::apply_width(frame, width);
+        assert_eq!(width(1.), frame);
+        assert_eq!(width(0.), FrameOutput::Stereo([0.5, 0.5]));
+        assert_eq!(width(2.), FrameOutput::Stereo([1., 0.]));
+
+        // Mono frames have no side to scale
+        let mono = FrameOutput::Mono(0.5);
+        assert_eq!(SamplePlayer::apply_width(mono, 0.), mono);
     }
 }
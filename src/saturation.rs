@@ -0,0 +1,227 @@
+use nih_plug::prelude::Enum;
+
+/// Input gain reached at full drive
+const MAX_DRIVE: f32 = 20.;
+
+/// Drive under which the saturated signal is faded in
+const DRIVE_FADE: f32 = 0.05;
+
+/// Channels with their own oversampling state, the next ones share the last one
+const MAX_CHANNELS: usize = 2;
+
+#[derive(Debug, PartialEq, Clone, Copy, Enum)]
+pub enum SaturationMode {
+    Off,
+    Tanh,
+    #[name = "Hard Clip"]
+    HardClip,
+    Foldback,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Enum)]
+pub enum Oversampling {
+    Off,
+    #[name = "2x"]
+    X2,
+    #[name = "4x"]
+    X4,
+}
+
+impl Oversampling {
+    /// Number of saturated sub-samples per sample
+    pub fn factor(&self) -> usize {
+        match self {
+            Oversampling::Off => 1,
+            Oversampling::X2 => 2,
+            Oversampling::X4 => 4,
+        }
+    }
+}
+
+/// Runs `saturate` at a higher rate to reduce aliasing.
+///
+/// The input is upsampled with a linear interpolation from the previous sample,
+/// and the saturated sub-samples are averaged back. Each channel keeps its previous sample.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Saturator {
+    previous: [f32; MAX_CHANNELS],
+}
+
+impl Saturator {
+    /// Clears the previous samples
+    pub fn reset(&mut self) {
+        self.previous = [0.; MAX_CHANNELS];
+    }
+
+    /// Saturates a single sample of a channel, see `saturate`
+    #[inline]
+    pub fn process_sample(
+        &mut self,
+        channel: usize,
+        mode: SaturationMode,
+        drive: f32,
+        oversampling: Oversampling,
+        x: f32,
+    ) -> f32 {
+        let previous = std::mem::replace(&mut self.previous[channel.min(MAX_CHANNELS - 1)], x);
+
+        // Nothing to alias, the signal is kept as it is without the interpolation delay.
+        // The drive doesn't bypass it, sweeping it down to 0 would shift the signal.
+        let factor = oversampling.factor();
+        if factor == 1 || mode == SaturationMode::Off {
+            return saturate(mode, drive, x);
+        }
+
+        let step = (x - previous) / factor as f32;
+        let sum: f32 = (1..=factor)
+            .map(|k| saturate(mode, drive, previous + step * k as f32))
+            .sum();
+        sum / factor as f32
+    }
+}
+
+/// Saturates a single sample.
+///
+/// `drive` goes from 0 to 1, at 0 the signal is left unchanged whatever the mode.
+/// The clipping modes change the level of a hot signal as soon as they are driven,
+/// so the shaped signal is faded in over the lowest drives to stay continuous with it.
+#[inline]
+pub fn saturate(mode: SaturationMode, drive: f32, x: f32) -> f32 {
+    let gain = drive * MAX_DRIVE;
+    let shaped = match mode {
+        SaturationMode::Off => return x,
+        SaturationMode::Tanh => {
+            // tanh(g * x) / tanh(g) tends to x when g tends to 0
+            if gain < 1e-4 {
+                x
+            } else {
+                (gain * x).tanh() / gain.tanh()
+            }
+        }
+        SaturationMode::HardClip => ((1. + gain) * x).clamp(-1., 1.),
+        SaturationMode::Foldback => fold((1. + gain) * x),
+    };
+    let mix = (drive / DRIVE_FADE).clamp(0., 1.);
+    x + (shaped - x) * mix
+}
+
+/// Reflects the signal back into [-1, 1] each time it crosses a boundary
+#[inline]
+fn fold(x: f32) -> f32 {
+    // Triangle wave of period 4, equal to x in [-1, 1]
+    let t = (x + 1.).rem_euclid(4.);
+    if t < 2. {
+        t - 1.
+    } else {
+        3. - t
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODES: [SaturationMode; 4] = [
+        SaturationMode::Off,
+        SaturationMode::Tanh,
+        SaturationMode::HardClip,
+        SaturationMode::Foldback,
+    ];
+
+    #[test]
+    fn test_no_drive_is_transparent() {
+        for mode in MODES {
+            for x in [-1.5, -0.5, 0., 0.3, 1.2] {
+                assert_eq!(saturate(mode, 0., x), x);
+            }
+        }
+    }
+
+    #[test]
+    fn test_drive_is_continuous_from_bypass() {
+        // Hot samples too, the clipping modes must not snap them back at the smallest drive
+        for mode in MODES {
+            for x in [-1.5, -0.5, 0.3, 1.2] {
+                let output = saturate(mode, 1e-4, x);
+                assert!(
+                    (output - x).abs() < 1e-2,
+                    "{:?} moved {} to {}",
+                    mode,
+                    x,
+                    output
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_output_is_bounded() {
+        for mode in MODES.into_iter().skip(1) {
+            for x in [-10., -1.5, -0.5, 0.3, 1.2, 10.] {
+                assert!(saturate(mode, 0.7, x).abs() <= 1. + 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn test_oversampling_is_transparent_without_drive() {
+        let mut saturator = Saturator::default();
+        for x in [-0.5, 0.3, 0.8, -0.1] {
+            assert_eq!(
+                saturator.process_sample(0, SaturationMode::Tanh, 0., Oversampling::Off, x),
+                x
+            );
+        }
+    }
+
+    #[test]
+    fn test_oversampling_delay_does_not_depend_on_drive() {
+        let inputs = [-0.5, 0.3, 0.8, -0.1, 0.];
+        for oversampling in [Oversampling::X2, Oversampling::X4] {
+            let mut bypassed = Saturator::default();
+            let mut driven = Saturator::default();
+            for x in inputs {
+                let dry = bypassed.process_sample(0, SaturationMode::Tanh, 0., oversampling, x);
+                let wet = driven.process_sample(0, SaturationMode::Tanh, 1e-3, oversampling, x);
+                assert!((dry - wet).abs() < 1e-3, "{} != {}", dry, wet);
+            }
+        }
+
+        // At 2x the output lags behind by the interpolation, without drive too
+        let mut saturator = Saturator::default();
+        saturator.process_sample(0, SaturationMode::Tanh, 0., Oversampling::X2, 0.4);
+        let output = saturator.process_sample(0, SaturationMode::Tanh, 0., Oversampling::X2, 0.8);
+        assert!((output - (0.25 * 0.4 + 0.75 * 0.8)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_oversampling_settles_on_constant_input() {
+        for mode in MODES {
+            let mut saturator = Saturator::default();
+            let mut output = 0.;
+            for _ in 0..4 {
+                output = saturator.process_sample(1, mode, 0.5, Oversampling::X4, 0.4);
+            }
+            assert!((output - saturate(mode, 0.5, 0.4)).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_oversampled_output_is_bounded() {
+        for mode in MODES.into_iter().skip(1) {
+            let mut saturator = Saturator::default();
+            for x in [-10., -1.5, -0.5, 0.3, 1.2, 10.] {
+                let output = saturator.process_sample(0, mode, 0.7, Oversampling::X2, x);
+                assert!(output.abs() <= 1. + 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fold_reflects() {
+        assert!((fold(0.5) - 0.5).abs() < 1e-6);
+        assert!((fold(1.5) - 0.5).abs() < 1e-6);
+        assert!((fold(-1.5) + 0.5).abs() < 1e-6);
+        assert!((fold(3.5) + 0.5).abs() < 1e-6);
+    }
+}
@@ -1,7 +1,9 @@
 use nih_plug::prelude::*;
-use std::sync::{atomic::AtomicU64, Arc, RwLock};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, RwLock};
 
-use crate::{params::HardKickSamplerParams, tasks::AudioData};
+use crate::{midi_learn::MidiLearn, params::HardKickSamplerParams, tasks::AudioData, utils};
 
 #[derive(Debug)]
 pub struct SharedStates {
@@ -13,11 +15,44 @@ pub struct SharedStates {
     /// The params of the processor
     pub params: Arc<HardKickSamplerParams>,
 
-    /// The position in each buffer
-    pub positions: Vec<Arc<AtomicU64>>,
+    /// The playback progress of each sample through the displayed region (0.0-1.0)
+    pub positions: Vec<Arc<AtomicF32>>,
+
+    /// The fundamental detected in each sample by the pitch shifter, NaN if none
+    pub detected_frequencies: Vec<Arc<AtomicF32>>,
+
+    /// Whether the pitch shifter of each slot failed to process its sample
+    pub pitch_detection_failed: Vec<Arc<AtomicBool>>,
+
+    /// The previously loaded paths of each slot, to undo a load
+    pub load_histories: Vec<Arc<RwLock<Vec<PathBuf>>>>,
+
+    /// The held peak level of each slot output
+    pub peaks: Vec<Arc<AtomicF32>>,
+
+    /// The sample rate each slot plays its sample at, 0 without a sample
+    pub playback_sample_rates: Vec<Arc<AtomicF32>>,
+
+    /// The held peak level of the main output, after the master section
+    pub master_peak: Arc<AtomicF32>,
 
     /// The tempo of the host
     pub host_bpm: Arc<AtomicF32>,
+
+    /// The sample rate of the host
+    pub host_sample_rate: Arc<AtomicF32>,
+
+    /// The number of samples of the last block processed by the host
+    pub block_size: Arc<AtomicU32>,
+
+    /// Whether any sample is currently playing
+    pub is_active: Arc<AtomicBool>,
+
+    /// Plays a tick at the start of each sample, monitoring only
+    pub alignment_tick: Arc<AtomicBool>,
+
+    /// The CC values to apply to the params and the MIDI learn state
+    pub midi_learn: Arc<MidiLearn>,
 }
 
 impl SharedStates {
@@ -26,4 +61,72 @@ impl SharedStates {
         let audio_data = guard.as_ref()?;
         Some(audio_data.clone())
     }
+
+    /// Returns the native sample rate of the loaded sample, without copying it
+    pub fn get_sample_rate(&self, index: usize) -> Option<u32> {
+        let guard = self.shared_buffer[index].read().ok()?;
+        guard.as_ref().map(|audio_data| audio_data.spec.sample_rate)
+    }
+
+    /// Returns a short description of the loaded sample, see `utils::describe_sample`
+    pub fn get_sample_info(&self, index: usize) -> Option<String> {
+        let audio_data = self.get_buffer_copy(index)?;
+        let path = self.params.samples[index]
+            .sample_path
+            .read()
+            .ok()
+            .and_then(|path| path.clone());
+        Some(utils::describe_sample(&audio_data, path.as_deref()))
+    }
+
+    /// Returns the sample rate the slot plays its sample at, the host rate once resampled
+    pub fn get_playback_sample_rate(&self, index: usize) -> Option<f32> {
+        let sample_rate = self.playback_sample_rates[index].load(Ordering::Relaxed);
+        (sample_rate > 0.).then_some(sample_rate)
+    }
+
+    /// Returns whether the sample of the slot is read faster or slower than the host rate
+    pub fn has_sample_rate_mismatch(&self, index: usize) -> bool {
+        self.get_playback_sample_rate(index)
+            .is_some_and(|sample_rate| sample_rate != self.host_sample_rate.load(Ordering::Relaxed))
+    }
+
+    /// Returns how long the host takes to process a block, in seconds
+    pub fn get_block_duration(&self) -> f32 {
+        let sample_rate = self.host_sample_rate.load(Ordering::Relaxed);
+        if sample_rate > 0. {
+            self.block_size.load(Ordering::Relaxed) as f32 / sample_rate
+        } else {
+            0.
+        }
+    }
+
+    /// Returns the number of channels of the loaded sample, without copying it
+    pub fn get_num_channels(&self, index: usize) -> Option<u16> {
+        let guard = self.shared_buffer[index].read().ok()?;
+        guard.as_ref().map(|audio_data| audio_data.spec.channels)
+    }
+
+    /// Returns the fundamental detected in the sample of the slot, if any
+    pub fn get_detected_frequency(&self, index: usize) -> Option<f32> {
+        let frequency = self.detected_frequencies[index].load(Ordering::Relaxed);
+        (!frequency.is_nan()).then_some(frequency)
+    }
+
+    /// Returns whether the pitch shifter of the slot fell back to another playback
+    pub fn has_pitch_detection_failed(&self, index: usize) -> bool {
+        self.pitch_detection_failed[index].load(Ordering::Relaxed)
+    }
+
+    /// Returns the held peak level of the slot output, as a linear gain
+    pub fn get_peak(&self, index: usize) -> f32 {
+        self.peaks[index].load(Ordering::Relaxed)
+    }
+
+    /// Returns whether a previous sample can be restored in the slot
+    pub fn can_undo_load(&self, index: usize) -> bool {
+        self.load_histories[index]
+            .read()
+            .is_ok_and(|history| !history.is_empty())
+    }
 }
@@ -1,22 +1,252 @@
 use std::path::PathBuf;
+use std::sync::Arc;
 
-use derive_more::{Constructor, From, Into};
 use hound::WavSpec;
 
-#[derive(Debug, Constructor, Into, From, Clone)]
+use crate::params::HardKickSamplerParams;
+use crate::pitch_shift::psola::PsolaAnalysis;
+use crate::utils;
+
+/// Number of points of the waveform preview
+pub const PREVIEW_POINTS: usize = 8192;
+
+/// Number of points of the magnitude spectrum
+pub const SPECTRUM_POINTS: usize = 512;
+
+/// How a sample is prepared to be played
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlaybackSettings {
+    /// The host rate when the sample is resampled, its own rate otherwise
+    pub sample_rate: f32,
+
+    /// Whether the analyses preserving the formants are precomputed
+    pub preserve_formants: bool,
+}
+
+impl PlaybackSettings {
+    /// Returns the settings of the slot `index` for a sample recorded at `source_sample_rate`
+    pub fn new(
+        params: &HardKickSamplerParams,
+        index: usize,
+        source_sample_rate: f32,
+        host_sample_rate: f32,
+    ) -> Self {
+        let resampled =
+            params.resample_to_host.value() && source_sample_rate > 0. && host_sample_rate > 0.;
+        Self {
+            sample_rate: if resampled {
+                host_sample_rate
+            } else {
+                source_sample_rate
+            },
+            preserve_formants: params
+                .samples
+                .get(index)
+                .is_some_and(|sample| sample.preserve_formants.value()),
+        }
+    }
+}
+
+/// The buffer fed to the shifters, it is the loaded data unless it was resampled
+#[derive(Debug, Clone)]
+pub struct Playback {
+    pub settings: PlaybackSettings,
+    pub data: Arc<Vec<f32>>,
+
+    /// PSOLA analysis of the played data, shared by every shifter of the slot
+    pub analysis: Arc<PsolaAnalysis>,
+}
+
+impl Playback {
+    /// Prepares the audio data to be played with `settings`, this is slow on long samples.
+    ///
+    /// The data is resampled if its rate differs, otherwise shared without a copy.
+    pub fn new(audio_data: &AudioData, settings: PlaybackSettings) -> Self {
+        let channel_number = audio_data.spec.channels as usize;
+        let source_sample_rate = audio_data.spec.sample_rate as f32;
+        let data = if source_sample_rate > 0. && settings.sample_rate != source_sample_rate {
+            Arc::new(utils::resample_linear(
+                &audio_data.data,
+                channel_number,
+                source_sample_rate,
+                settings.sample_rate,
+            ))
+        } else {
+            Arc::clone(&audio_data.data)
+        };
+
+        let mut analysis = PsolaAnalysis::new(&data, channel_number, settings.sample_rate);
+        if settings.preserve_formants {
+            analysis = analysis.with_formants();
+        }
+        Self {
+            settings,
+            data,
+            analysis: Arc::new(analysis),
+        }
+    }
+}
+
+/// A decoded sample, cloning it shares the samples instead of copying them
+#[derive(Debug, Clone)]
 pub struct AudioData {
     pub spec: WavSpec,
-    pub data: Vec<f32>,
+    pub data: Arc<Vec<f32>>,
+
+    /// The buffer played and its analysis, prepared when loading so the audio thread
+    /// doesn't resample nor analyse it
+    pub playback: Option<Playback>,
+
+    /// Downsampled first channel for display, the x of each point is its frame
+    pub preview: Option<Arc<Vec<[f32; 2]>>>,
+
+    /// Magnitude spectrum of the first channel for display, see `utils::compute_spectrum`
+    pub spectrum: Option<Arc<Vec<[f32; 2]>>>,
+}
+
+impl AudioData {
+    pub fn new(spec: WavSpec, data: Vec<f32>) -> Self {
+        Self {
+            spec,
+            data: Arc::new(data),
+            playback: None,
+            preview: None,
+            spectrum: None,
+        }
+    }
+
+    /// Runs the slow processing of a loaded sample: the playback buffer and its
+    /// PSOLA analysis, the preview and the spectrum
+    pub fn prepared(self, settings: PlaybackSettings) -> Self {
+        self.with_playback(settings).with_preview().with_spectrum()
+    }
+
+    /// Prepares the buffer played with `settings`, see `Playback::new`
+    pub fn with_playback(mut self, settings: PlaybackSettings) -> Self {
+        self.playback = Some(Playback::new(&self, settings));
+        self
+    }
+
+    /// Computes the waveform preview of the first channel
+    pub fn with_preview(mut self) -> Self {
+        let num_channels = (self.spec.channels as usize).max(1);
+        let points = self
+            .data
+            .iter()
+            .step_by(num_channels)
+            .enumerate()
+            .map(|(frame, sample)| [frame as f32, *sample])
+            .collect::<Vec<_>>();
+        self.preview = Some(Arc::new(utils::downsample_lttb(&points, PREVIEW_POINTS)));
+        self
+    }
+
+    /// Computes the magnitude spectrum of the first channel
+    pub fn with_spectrum(mut self) -> Self {
+        self.spectrum = Some(Arc::new(utils::compute_spectrum(
+            &self.data,
+            self.spec.channels as usize,
+            self.spec.sample_rate as f32,
+            SPECTRUM_POINTS,
+        )));
+        self
+    }
+
+    /// Returns a mono copy of a single channel of the audio data,
+    /// `None` if the channel doesn't exist.
+    pub fn extract_channel(&self, channel: usize) -> Option<AudioData> {
+        let num_channels = self.spec.channels as usize;
+        if channel >= num_channels {
+            return None;
+        }
+
+        let data = self
+            .data
+            .iter()
+            .skip(channel)
+            .step_by(num_channels)
+            .copied()
+            .collect();
+        let spec = WavSpec {
+            channels: 1,
+            ..self.spec
+        };
+        Some(AudioData::new(spec, data))
+    }
 }
 
 #[derive(Debug)]
 pub enum TaskResults {
     LoadedFile(usize, PathBuf, AudioData),
+    LoadedChannel(usize, PathBuf, usize, AudioData),
+    RestoredFile(usize, PathBuf, AudioData),
+    /// The loaded sample of a slot with its playback prepared again, after
+    /// `resample_to_host`, the host rate or `preserve_formants` changed
+    PreparedPlayback(usize, AudioData),
     ClearSample(usize),
+    ClearAll,
+}
+
+impl TaskResults {
+    /// Loads a file for a slot, only keeping `channel` of the file if there is one
+    pub fn load_sample(
+        index: usize,
+        path: PathBuf,
+        channel: Option<usize>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let audio_data = utils::load_audio_file(&path)?;
+        match channel {
+            Some(channel) => audio_data
+                .extract_channel(channel)
+                .map(|data| TaskResults::LoadedChannel(index, path, channel, data))
+                .ok_or_else(|| "Stored sample channel doesn't exist".into()),
+            None => Ok(TaskResults::LoadedFile(index, path, audio_data)),
+        }
+    }
+
+    /// Runs the slow processing of the loaded audio data, if any,
+    /// the playback follows the current params and host rate
+    pub fn prepared(self, params: &HardKickSamplerParams, host_sample_rate: f32) -> Self {
+        let settings = |index: usize, data: &AudioData| {
+            PlaybackSettings::new(
+                params,
+                index,
+                data.spec.sample_rate as f32,
+                host_sample_rate,
+            )
+        };
+        match self {
+            TaskResults::LoadedFile(index, path, data) => {
+                let settings = settings(index, &data);
+                TaskResults::LoadedFile(index, path, data.prepared(settings))
+            }
+            TaskResults::LoadedChannel(index, path, channel, data) => {
+                let settings = settings(index, &data);
+                TaskResults::LoadedChannel(index, path, channel, data.prepared(settings))
+            }
+            TaskResults::RestoredFile(index, path, data) => {
+                let settings = settings(index, &data);
+                TaskResults::RestoredFile(index, path, data.prepared(settings))
+            }
+            TaskResults::PreparedPlayback(index, data) => {
+                let settings = settings(index, &data);
+                TaskResults::PreparedPlayback(index, data.with_playback(settings))
+            }
+            other => other,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum TaskRequests {
     TransfertTask(TaskResults),
     LoadFile(usize, PathBuf),
+    LoadFileSplit(usize, PathBuf),
+    UndoLoad(usize),
+    /// Prepares the playback of the loaded sample of a slot again
+    PreparePlayback(usize),
+    /// Loads the sample of the first slot in the second one
+    DuplicateSample(usize, usize),
+    SavePreset(PathBuf),
+    LoadPreset(PathBuf),
 }
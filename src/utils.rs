@@ -1,21 +1,301 @@
+use std::cmp::Ordering;
+use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
-use crate::{params::BlendGroup, tasks::AudioData};
+use pitch_detection::detector::mcleod::McLeodDetector;
+use pitch_detection::detector::PitchDetector;
+use rustfft::{num_complex::Complex, FftPlanner};
+
+use crate::{
+    params::{BlendCurve, BlendGroup},
+    plugin::DEFAULT_BPM,
+    tasks::AudioData,
+};
+
+/// Extensions of the audio files that can be loaded
+pub const SUPPORTED_EXTENSIONS: &[&str] = &["wav", "flac", "mp3", "ogg"];
+
+/// Priming frames at the start of a decoded mp3, encoder delay (576) + decoder delay (529)
+const MP3_PRIMING_FRAMES: usize = 1105;
+
+/// Format tag of floating point wav files
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+
+/// Returns the extension of a file in lowercase, packs often name their files `KICK.WAV`
+fn lowercase_extension(file_path: &Path) -> Option<String> {
+    file_path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase())
+}
+
+/// Returns whether the file has an extension that `load_audio_file` can read, whatever its case
+pub fn is_supported_audio_file(file_path: &Path) -> bool {
+    lowercase_extension(file_path).is_some_and(|ext| SUPPORTED_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// Decodes the percent-encoded characters of an url (`%20` -> ` `).
+///
+/// Returns `None` if an escape sequence is invalid or if the decoded
+/// bytes aren't valid utf-8.
+pub fn percent_decode(text: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut iter = text.bytes();
+
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let high = (iter.next()? as char).to_digit(16)?;
+            let low = (iter.next()? as char).to_digit(16)?;
+            bytes.push((high * 16 + low) as u8);
+        } else {
+            bytes.push(byte);
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+/// Converts a `file://` url to a path, `None` if it isn't a local file url.
+pub fn path_from_file_url(url: &str) -> Option<PathBuf> {
+    let rest = url.strip_prefix("file://")?;
+
+    // The host is either empty or localhost for local files
+    let rest = rest.strip_prefix("localhost").unwrap_or(rest);
+    if !rest.starts_with('/') {
+        return None;
+    }
+
+    let decoded = percent_decode(rest)?;
+
+    // Windows drive letters come as `/C:/...`
+    let decoded = match decoded.as_bytes() {
+        [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => decoded[1..].to_string(),
+        _ => decoded,
+    };
+
+    Some(PathBuf::from(decoded))
+}
+
+/// Parses the paths of a `text/uri-list` (one url per line, `#` for comments).
+///
+/// Lines that aren't urls are used as plain paths, since some hosts
+/// drop raw paths with this MIME type.
+pub fn paths_from_uri_list(text: &str) -> Vec<PathBuf> {
+    text.lines()
+        .map(|line| line.trim().trim_matches(['"', '\'']))
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            if line.contains("://") {
+                path_from_file_url(line)
+            } else {
+                Some(PathBuf::from(line))
+            }
+        })
+        .collect()
+}
+
+/// Resolves a path dropped on the window.
+///
+/// Some hosts drop a `text/uri-list` or a `file://` url instead of a native path,
+/// it then comes through as the text of the path and is parsed with `paths_from_uri_list`.
+pub fn paths_from_drop(path: &Path) -> Vec<PathBuf> {
+    if path.exists() {
+        return vec![path.to_path_buf()];
+    }
+    match path.to_str() {
+        Some(text) => paths_from_uri_list(text),
+        None => vec![path.to_path_buf()],
+    }
+}
+
+/// Returns the audio files to load for dropped paths, in the order they were dropped.
+///
+/// The paths are resolved with `paths_from_drop`. A directory is replaced by its supported
+/// audio files sorted with `natural_cmp`, without going through its subdirectories.
+/// Unsupported files are skipped.
+pub fn audio_files_from_drop(paths: &[PathBuf]) -> Vec<PathBuf> {
+    paths
+        .iter()
+        .flat_map(|path| paths_from_drop(path))
+        .flat_map(|path| {
+            if path.is_dir() {
+                let mut entries: Vec<PathBuf> = std::fs::read_dir(&path)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.path())
+                    .filter(|path| path.is_file())
+                    .collect();
+                entries.sort_by(|a, b| natural_path_cmp(a, b));
+                entries
+            } else {
+                vec![path]
+            }
+        })
+        .filter(|path| is_supported_audio_file(path))
+        .collect()
+}
+
+/// Longest time between two files of a single drop, each file arrives in its own event
+pub const DROP_GESTURE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Spreads the files of a drop over consecutive slots.
+///
+/// The window sends one event per dropped file, so the files dropped on the same slot
+/// within `DROP_GESTURE_INTERVAL` of each other go to the slots after the previous ones.
+#[derive(Debug, Default)]
+pub struct DropSlots {
+    /// The slot the drop started on, the next free slot and the time of the last file
+    gesture: Option<(usize, usize, Instant)>,
+}
+
+impl DropSlots {
+    /// Returns the first slot of `count` files dropped on `index` at `time`
+    pub fn first_slot(&mut self, index: usize, count: usize, time: Instant) -> usize {
+        let first = match self.gesture {
+            Some((origin, next, last))
+                if origin == index && time.duration_since(last) <= DROP_GESTURE_INTERVAL =>
+            {
+                next
+            }
+            _ => index,
+        };
+        self.gesture = Some((index, first + count, time));
+        first
+    }
+}
+
+/// Interpolates a position published once per block, so the indicator moves between blocks.
+///
+/// The displayed position glides to each new position over one block. A position going
+/// backwards is a new note (or the end of the playback), the indicator snaps to it.
+#[derive(Debug, Default)]
+pub struct PositionInterpolator {
+    /// The displayed position when the last one was published, that one and the time it was seen
+    glide: Option<(f32, f32, Instant)>,
+}
+
+impl PositionInterpolator {
+    /// Returns the position to display at `time`, `block_duration` is in seconds
+    pub fn position(&mut self, published: f32, block_duration: f32, time: Instant) -> f32 {
+        let displayed = |(from, to, since): (f32, f32, Instant)| {
+            let elapsed = time.duration_since(since).as_secs_f32();
+            if block_duration > 0. {
+                interpolate(from, to, (elapsed / block_duration).min(1.))
+            } else {
+                to
+            }
+        };
+
+        let glide = match self.glide {
+            Some(glide) if glide.1 == published => glide,
+            Some(glide) if published > glide.1 => (displayed(glide), published, time),
+            _ => (published, published, time),
+        };
+        self.glide = Some(glide);
+        displayed(glide)
+    }
+}
+
+/// Parses a path from a text, such as a pasted path, a `file://` url or an uri list.
+///
+/// Surrounding whitespaces and quotes are removed. Only the first existing,
+/// supported audio file is returned.
+pub fn path_from_text(text: &str) -> Option<PathBuf> {
+    paths_from_uri_list(text)
+        .into_iter()
+        .find(|path| path.is_file() && is_supported_audio_file(path))
+}
+
+/// Opens the file manager of the OS at a file.
+///
+/// The file is selected on Windows and macOS, elsewhere its directory is opened.
+/// This waits for the launched command, so call it from a background thread.
+pub fn reveal_in_file_manager(file_path: &Path) -> std::io::Result<()> {
+    use std::process::Command;
+
+    let mut command = if cfg!(target_os = "windows") {
+        let mut command = Command::new("explorer");
+        let mut select = std::ffi::OsString::from("/select,");
+        select.push(file_path);
+        command.arg(select);
+        command
+    } else if cfg!(target_os = "macos") {
+        let mut command = Command::new("open");
+        command.arg("-R").arg(file_path);
+        command
+    } else {
+        let mut command = Command::new("xdg-open");
+        command.arg(file_path.parent().unwrap_or(file_path));
+        command
+    };
+
+    // Explorer exits with an error code even when it worked
+    command.status().map(|_| ())
+}
 
 pub fn load_audio_file(file_path: &Path) -> Result<AudioData, Box<dyn std::error::Error>> {
-    match file_path.extension().and_then(|ext| ext.to_str()) {
+    match lowercase_extension(file_path).as_deref() {
         Some("wav") => load_wav(file_path),
+        Some("flac") => load_flac(file_path),
+        Some("mp3") => load_mp3(file_path),
+        Some("ogg") => load_ogg(file_path),
         _ => Err("Unsupported file format".into()),
     }
 }
 
+/// Returns whether the bytes start with an ogg page holding an opus header.
+///
+/// Opus uses the same container as vorbis, so `.ogg` files may contain either.
+pub fn is_opus_stream(bytes: &[u8]) -> bool {
+    // The first packet starts after the page header and its segment table
+    if !bytes.starts_with(b"OggS") || bytes.len() < 27 {
+        return false;
+    }
+    let header_length = 27 + bytes[26] as usize;
+    bytes
+        .get(header_length..)
+        .is_some_and(|packet| packet.starts_with(b"OpusHead"))
+}
+
+/// Removes the first `num_frames` frames of interleaved samples
+pub fn trim_leading_frames(samples: &mut Vec<f32>, num_channels: usize, num_frames: usize) {
+    let num_samples = (num_frames * num_channels).min(samples.len());
+    samples.drain(..num_samples);
+}
+
+/// Scales an integer sample to `[-1.0, 1.0]`, the same way as the wav loader does
+#[inline]
+pub fn normalize_int_sample(sample: i32, bits_per_sample: u32) -> f32 {
+    sample as f32 / ((1_i64 << (bits_per_sample - 1)) - 1) as f32
+}
+
 fn load_wav(file_path: &Path) -> Result<AudioData, Box<dyn std::error::Error>> {
-    let mut reader = hound::WavReader::open(file_path)?;
+    let mut reader = match hound::WavReader::open(file_path) {
+        Ok(reader) => reader,
+        // Hound refuses 64-bit float files, they are decoded by hand
+        Err(hound::Error::Unsupported) => return load_wav_f64(file_path),
+        Err(e) => return Err(e.into()),
+    };
     let spec = reader.spec();
 
     let samples: Vec<f32> = match &spec.sample_format {
-        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<Vec<_>, _>>()?,
+        hound::SampleFormat::Float => match spec.bits_per_sample {
+            32 => reader.samples::<f32>().collect::<Result<Vec<_>, _>>()?,
+            bits => {
+                return Err(
+                    format!("Unsupported wav float bit depth: {bits} (supported: 32, 64)").into(),
+                )
+            }
+        },
         hound::SampleFormat::Int => match spec.bits_per_sample {
+            // 8-bit wav is unsigned, hound already recenters it around 0
+            // so this is (s - 128) / 128 on the raw byte
+            8 => reader
+                .samples::<i8>()
+                .map(|s| s.map(|sample| sample as f32 / 128.))
+                .collect::<Result<Vec<_>, _>>()?,
             16 => reader
                 .samples::<i16>()
                 .map(|s| s.map(|sample| sample as f32 / i16::MAX as f32))
@@ -28,18 +308,240 @@ fn load_wav(file_path: &Path) -> Result<AudioData, Box<dyn std::error::Error>> {
                 .samples::<i32>()
                 .map(|s| s.map(|sample| sample as f32 / i32::MAX as f32))
                 .collect::<Result<Vec<_>, _>>()?,
-            _ => return Err(format!("Unsupported bit depth: {}", spec.bits_per_sample).into()),
+            bits => {
+                return Err(format!(
+                    "Unsupported wav integer bit depth: {bits} (supported: 8, 16, 24, 32)"
+                )
+                .into())
+            }
         },
     };
 
     Ok(AudioData::new(spec, samples))
 }
 
+fn load_wav_f64(file_path: &Path) -> Result<AudioData, Box<dyn std::error::Error>> {
+    parse_wav_f64(&std::fs::read(file_path)?)
+}
+
+/// Decodes the bytes of a 64-bit float wav file.
+///
+/// Only the `fmt ` and `data` chunks are read, any other format is an error.
+fn parse_wav_f64(bytes: &[u8]) -> Result<AudioData, Box<dyn std::error::Error>> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err("Not a wav file".into());
+    }
+
+    let read_u16 = |b: &[u8], at: usize| u16::from_le_bytes([b[at], b[at + 1]]);
+    let read_u32 =
+        |b: &[u8], at: usize| u32::from_le_bytes([b[at], b[at + 1], b[at + 2], b[at + 3]]);
+
+    let mut format = None;
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let size = read_u32(bytes, offset + 4) as usize;
+        let body = &bytes[offset + 8..(offset + 8).saturating_add(size).min(bytes.len())];
+
+        match id {
+            b"fmt " => {
+                if body.len() < 16 {
+                    return Err("Truncated wav fmt chunk".into());
+                }
+                // WAVE_FORMAT_EXTENSIBLE stores the actual format at the start of the sub format
+                let tag = match read_u16(body, 0) {
+                    0xFFFE if body.len() >= 26 => read_u16(body, 24),
+                    tag => tag,
+                };
+                let bits = read_u16(body, 14);
+                if tag != WAVE_FORMAT_IEEE_FLOAT || bits != 64 {
+                    return Err(format!(
+                        "Unsupported wav format: format tag {tag}, bit depth {bits}"
+                    )
+                    .into());
+                }
+                format = Some((read_u16(body, 2), read_u32(body, 4)));
+            }
+            b"data" => {
+                let (channels, sample_rate) = format.ok_or("Wav data chunk before fmt chunk")?;
+                let samples = body
+                    .chunks_exact(8)
+                    .map(|b| f64::from_le_bytes(b.try_into().unwrap()) as f32)
+                    .collect();
+                let spec = hound::WavSpec {
+                    channels,
+                    sample_rate,
+                    bits_per_sample: 64,
+                    sample_format: hound::SampleFormat::Float,
+                };
+                return Ok(AudioData::new(spec, samples));
+            }
+            _ => {}
+        }
+
+        // Chunks are padded to an even size
+        offset = offset.saturating_add(8 + size + (size & 1));
+    }
+
+    Err("Wav file has no data chunk".into())
+}
+
+fn load_mp3(file_path: &Path) -> Result<AudioData, Box<dyn std::error::Error>> {
+    let mut decoder = minimp3::Decoder::new(std::fs::File::open(file_path)?);
+
+    let mut samples = Vec::new();
+    let mut format = None;
+    loop {
+        match decoder.next_frame() {
+            Ok(frame) => {
+                // The format of the first frame is used for the whole file
+                let (sample_rate, channels) =
+                    *format.get_or_insert((frame.sample_rate as u32, frame.channels));
+                if (frame.sample_rate as u32, frame.channels) != (sample_rate, channels) {
+                    return Err("Mp3 format changes within the file".into());
+                }
+                samples.extend(
+                    frame
+                        .data
+                        .iter()
+                        .map(|&sample| normalize_int_sample(sample as i32, 16)),
+                );
+            }
+            // Tags and garbage between frames
+            Err(minimp3::Error::SkippedData) => continue,
+            Err(minimp3::Error::Eof) => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let (sample_rate, channels) = format.ok_or("Mp3 file has no audio frame")?;
+
+    // Without trimming the priming frames, the attack would be late
+    trim_leading_frames(&mut samples, channels, MP3_PRIMING_FRAMES);
+
+    let spec = hound::WavSpec {
+        channels: channels as u16,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+
+    Ok(AudioData::new(spec, samples))
+}
+
+fn load_ogg(file_path: &Path) -> Result<AudioData, Box<dyn std::error::Error>> {
+    // Lewton only decodes vorbis, tell the user why an opus file can't be loaded
+    let mut header = Vec::with_capacity(512);
+    std::fs::File::open(file_path)?
+        .take(512)
+        .read_to_end(&mut header)?;
+    if is_opus_stream(&header) {
+        return Err("Ogg Opus files are not supported, only Ogg Vorbis".into());
+    }
+
+    let mut reader = lewton::inside_ogg::OggStreamReader::new(std::fs::File::open(file_path)?)?;
+    let channels = reader.ident_hdr.audio_channels as u16;
+    let sample_rate = reader.ident_hdr.audio_sample_rate;
+
+    // Packets are decoded already interleaved
+    let mut samples = Vec::new();
+    while let Some(packet) = reader.read_dec_packet_itl()? {
+        samples.extend(
+            packet
+                .iter()
+                .map(|&sample| normalize_int_sample(sample as i32, 16)),
+        );
+    }
+
+    let spec = hound::WavSpec {
+        channels,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+
+    Ok(AudioData::new(spec, samples))
+}
+
+fn load_flac(file_path: &Path) -> Result<AudioData, Box<dyn std::error::Error>> {
+    let mut reader = claxon::FlacReader::open(file_path)?;
+    let info = reader.streaminfo();
+
+    // Flac samples are always integers, up to 32 bits
+    let samples: Vec<f32> = reader
+        .samples()
+        .map(|s| s.map(|sample| normalize_int_sample(sample, info.bits_per_sample)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // The rest of the plugin works on wav specs
+    let spec = hound::WavSpec {
+        channels: info.channels as u16,
+        sample_rate: info.sample_rate,
+        bits_per_sample: info.bits_per_sample as u16,
+        sample_format: hound::SampleFormat::Int,
+    };
+
+    Ok(AudioData::new(spec, samples))
+}
+
 #[inline]
 pub fn interpolate(v1: f32, v2: f32, fraction: f32) -> f32 {
     v1 * (1. - fraction) + v2 * fraction
 }
 
+/// 4-point Hermite (Catmull-Rom) interpolation between `v1` and `v2`,
+/// `v0` and `v3` are the values around them.
+pub fn interpolate_hermite(v0: f32, v1: f32, v2: f32, v3: f32, fraction: f32) -> f32 {
+    let c1 = 0.5 * (v2 - v0);
+    let c2 = v0 - 2.5 * v1 + 2. * v2 - 0.5 * v3;
+    let c3 = 0.5 * (v3 - v0) + 1.5 * (v1 - v2);
+    ((c3 * fraction + c2) * fraction + c1) * fraction + v1
+}
+
+/// Resamples interleaved audio data with linear interpolation.
+///
+/// The length is scaled by the rate ratio, the last frame is held when
+/// reading past the end. Returns the data unchanged if a rate isn't positive.
+pub fn resample_linear(data: &[f32], channels: usize, from_sr: f32, to_sr: f32) -> Vec<f32> {
+    if channels == 0 || from_sr <= 0. || to_sr <= 0. || from_sr == to_sr {
+        return data.to_vec();
+    }
+
+    let num_frames = data.len() / channels;
+    if num_frames == 0 {
+        return Vec::new();
+    }
+
+    let step = from_sr as f64 / to_sr as f64;
+    let out_frames = ((num_frames as f64 / step).round() as usize).max(1);
+    let mut output = Vec::with_capacity(out_frames * channels);
+    for frame in 0..out_frames {
+        let position = frame as f64 * step;
+        let index = (position as usize).min(num_frames - 1);
+        let next = (index + 1).min(num_frames - 1);
+        let fraction = (position - index as f64) as f32;
+        for channel in 0..channels {
+            output.push(interpolate(
+                data[index * channels + channel],
+                data[next * channels + channel],
+                fraction,
+            ));
+        }
+    }
+    output
+}
+
+/// Normalized sinc, `sin(pi * x) / (pi * x)`
+#[inline]
+pub fn sinc(x: f32) -> f32 {
+    if x.abs() < f32::EPSILON {
+        1.
+    } else {
+        let pi_x = std::f32::consts::PI * x;
+        pi_x.sin() / pi_x
+    }
+}
+
 pub fn semitones_to_note(mut semi: i32) -> String {
     // Handle negative values and values >= 12 by wrapping to 0-11 range
     if semi < 0 {
@@ -67,65 +569,224 @@ pub fn semitones_to_note(mut semi: i32) -> String {
     String::from(value)
 }
 
-fn get_sorted_files_in_directory(file_path: &str) -> Option<Vec<PathBuf>> {
-    let path = Path::new(file_path);
-    let parent = path.parent()?;
+/// Extensions of the lossy formats, their decoded bit depth says nothing about the file
+const LOSSY_EXTENSIONS: &[&str] = &["mp3", "ogg"];
 
-    // Read directory entries and collect files
-    let mut entries: Vec<PathBuf> = std::fs::read_dir(parent)
-        .ok()?
-        .filter_map(|entry| entry.ok())
-        .map(|entry| entry.path())
-        .filter(|path| path.is_file())
-        .collect();
+/// Formats a sample rate in kHz, such as `48kHz` or `44.1kHz`
+pub fn format_sample_rate(sample_rate: f32) -> String {
+    format!("{}kHz", (sample_rate / 100.).round() / 10.)
+}
 
-    // Sort entries for consistent ordering
-    entries.sort();
+/// Describes a loaded sample, such as `1.2s · 48kHz · Stereo · 24-bit`.
+///
+/// The bit depth of the lossy files is left out, it would only be the
+/// precision of the decoder.
+pub fn describe_sample(audio_data: &AudioData, file_path: Option<&Path>) -> String {
+    let spec = audio_data.spec;
+    let num_frames = audio_data.data.len() / (spec.channels as usize).max(1);
+    let duration = num_frames as f32 / spec.sample_rate.max(1) as f32;
 
-    Some(entries)
+    let mut parts = vec![
+        if duration < 1. {
+            format!("{:.0}ms", duration * 1000.)
+        } else {
+            format!("{:.1}s", duration)
+        },
+        format_sample_rate(spec.sample_rate as f32),
+        match spec.channels {
+            1 => String::from("Mono"),
+            2 => String::from("Stereo"),
+            channels => format!("{}ch", channels),
+        },
+    ];
+
+    let lossy = file_path
+        .and_then(lowercase_extension)
+        .is_some_and(|ext| LOSSY_EXTENSIONS.contains(&ext.as_str()));
+    if !lossy {
+        parts.push(match spec.sample_format {
+            hound::SampleFormat::Float => format!("{}-bit float", spec.bits_per_sample),
+            hound::SampleFormat::Int => format!("{}-bit", spec.bits_per_sample),
+        });
+    }
+
+    parts.join(" · ")
 }
 
-pub fn get_next_file_in_directory_wrap(file_path: &str) -> Option<PathBuf> {
-    let path = Path::new(file_path);
-    let current_filename = path.file_name()?;
+/// Returns the nearest MIDI note of a frequency (A4 = 440 Hz = 69)
+pub fn frequency_to_midi_note(frequency: f32) -> i32 {
+    (69. + 12. * (frequency / 440.).log2()).round() as i32
+}
 
-    let entries = get_sorted_files_in_directory(file_path)?;
+/// Returns the name and octave of a MIDI note, like "G#1"
+pub fn midi_note_to_name(note: i32) -> String {
+    format!("{}{}", semitones_to_note(note), note.div_euclid(12) - 1)
+}
 
-    if entries.is_empty() {
-        return None;
+/// Compares two names the way a file browser would: ignoring the case, and with the
+/// numbers compared by value, so "Kick2" comes before "Kick10"
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().flat_map(char::to_lowercase).peekable();
+    let mut b = b.chars().flat_map(char::to_lowercase).peekable();
+
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let a_digits = take_digits(&mut a);
+                let b_digits = take_digits(&mut b);
+                let a_value = a_digits.trim_start_matches('0');
+                let b_value = b_digits.trim_start_matches('0');
+
+                // A longer number is bigger, then the digits decide, then the leading zeros
+                let ordering = a_value
+                    .len()
+                    .cmp(&b_value.len())
+                    .then_with(|| a_value.cmp(b_value))
+                    .then_with(|| a_digits.len().cmp(&b_digits.len()));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a.next();
+                b.next();
+            }
+        }
     }
+}
 
-    let current_index = entries
-        .iter()
-        .position(|p| p.file_name() == Some(current_filename))?;
+/// Consumes the digits at the start of `chars`
+fn take_digits(chars: &mut std::iter::Peekable<impl Iterator<Item = char>>) -> String {
+    let mut digits = String::new();
+    while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+        digits.push(digit);
+    }
+    digits
+}
 
-    // Wrap around to first if at end
-    let next_index = (current_index + 1) % entries.len();
-    Some(entries[next_index].clone())
+/// Compares two paths component by component with `natural_cmp`, so the files of a
+/// folder stay together
+pub fn natural_path_cmp(a: &Path, b: &Path) -> Ordering {
+    let mut a = a.iter();
+    let mut b = b.iter();
+
+    loop {
+        match (a.next(), b.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => {
+                let ordering = natural_cmp(&x.to_string_lossy(), &y.to_string_lossy());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+        }
+    }
 }
 
-pub fn get_previous_file_in_directory_wrap(file_path: &str) -> Option<PathBuf> {
+/// How deep the recursive browsing goes into the pack folder
+const MAX_BROWSE_DEPTH: usize = 4;
+
+/// Returns the supported audio files next to a file, in the `natural_path_cmp` order.
+///
+/// With `recursive`, these are the files of the pack folder (the parent of the file
+/// directory) and of its subfolders, up to `MAX_BROWSE_DEPTH` levels.
+fn get_sorted_files_in_directory(file_path: &str, recursive: bool) -> Option<Vec<PathBuf>> {
     let path = Path::new(file_path);
-    let current_filename = path.file_name()?;
+    let parent = path.parent()?;
+
+    let mut entries = if recursive {
+        let pack = parent.parent().unwrap_or(parent);
+        let mut entries = Vec::new();
+        collect_audio_files(pack, MAX_BROWSE_DEPTH, &mut entries);
+        entries
+    } else {
+        // Read directory entries and collect the files we can load, so browsing never lands
+        // on the artwork or the readme of a pack
+        std::fs::read_dir(parent)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file() && is_supported_audio_file(path))
+            .collect()
+    };
+
+    // Sort entries for consistent ordering
+    entries.sort_by(|a, b| natural_path_cmp(a, b));
+
+    Some(entries)
+}
+
+/// Adds the supported audio files of a directory and its subdirectories to `files`
+fn collect_audio_files(directory: &Path, depth: usize, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        return;
+    };
+    for path in entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+    {
+        if path.is_dir() {
+            if depth > 0 {
+                collect_audio_files(&path, depth - 1, files);
+            }
+        } else if is_supported_audio_file(&path) {
+            files.push(path);
+        }
+    }
+}
 
-    let entries = get_sorted_files_in_directory(file_path)?;
+/// Returns the file `step` places away from a file in the sorted files next to it,
+/// wrapping around at both ends
+fn get_file_in_directory_wrap(file_path: &str, recursive: bool, step: isize) -> Option<PathBuf> {
+    let path = Path::new(file_path);
+    let entries = get_sorted_files_in_directory(file_path, recursive)?;
 
     if entries.is_empty() {
         return None;
     }
 
-    let current_index = entries
-        .iter()
-        .position(|p| p.file_name() == Some(current_filename))?;
+    let current_index = entries.iter().position(|p| p == path)?;
+    let index = (current_index as isize + step).rem_euclid(entries.len() as isize);
+    Some(entries[index as usize].clone())
+}
+
+pub fn get_next_file_in_directory_wrap(file_path: &str, recursive: bool) -> Option<PathBuf> {
+    // Wrap around to first if at end
+    get_file_in_directory_wrap(file_path, recursive, 1)
+}
 
+pub fn get_previous_file_in_directory_wrap(file_path: &str, recursive: bool) -> Option<PathBuf> {
     // Wrap around to last if at beginning
-    let prev_index = if current_index == 0 {
-        entries.len() - 1
-    } else {
-        current_index - 1
-    };
+    get_file_in_directory_wrap(file_path, recursive, -1)
+}
+
+/// Number of beats of the sample shown by the waveform displays, from the start offset
+pub const DISPLAYED_BEATS: f32 = 1.;
 
-    Some(entries[prev_index].clone())
+/// Returns the number of frames of a sample shown by the waveform displays
+pub fn get_num_displayed_frames(beats: f32, sr: f32, bpm: f32) -> usize {
+    (beats * 60.0 * sr / effective_bpm(Some(bpm))) as usize
+}
+
+/// Returns the tempo to use for anything that depends on the host bpm.
+///
+/// Some hosts don't provide a tempo at all (or report 0 when the transport
+/// is stopped), in that case we fall back to `DEFAULT_BPM` so the process,
+/// the display and any beat-synced feature always agree on the same value.
+#[inline]
+pub fn effective_bpm(tempo: Option<f32>) -> f32 {
+    match tempo {
+        Some(bpm) if bpm.is_finite() && bpm > 0. => bpm,
+        _ => DEFAULT_BPM as f32,
+    }
 }
 
 pub fn optional_positive_sub(lhs: f32, rhs: f32) -> Option<f32> {
@@ -136,26 +797,121 @@ pub fn optional_positive_sub(lhs: f32, rhs: f32) -> Option<f32> {
     }
 }
 
+/// Words marking the number after them as a midi note in a file name, such as `midi60` or
+/// `note_60`. A marked number is preferred to a bare one, which may be the index of a variation.
+const FILENAME_MIDI_MARKERS: &[&str] = &["midi", "note"];
+
+/// Finds the root note in a file name such as `Kick_F#.wav`, `Kick A1.wav` or `tone_60.wav`.
+///
+/// The note has to be a standalone token (between separators) of at most two
+/// characters, optionally followed by a single octave digit, so words that
+/// merely start with a note letter are never matched. The octave is ignored.
+///
+/// When there is no note name, a midi note number (0-127) is mapped to its note with a
+/// modulo 12. A number after one of the `FILENAME_MIDI_MARKERS`, in the same token or the
+/// previous one, comes first, a bare number token is the last resort.
 pub fn get_root_note_from_filename(file_name: String) -> Option<i32> {
     // chunk with some common separator
-    for chunk in file_name.split(['_', ' ', '-', '.']).rev() {
-        match chunk.to_uppercase().as_str() {
-            "C" => return Some(0),
-            "C#" | "CS" | "DB" => return Some(1),
-            "D" => return Some(2),
-            "D#" | "DS" | "EB" => return Some(3),
-            "E" => return Some(4),
-            "F" => return Some(5),
-            "F#" | "FS" | "GB" => return Some(6),
-            "G" => return Some(7),
-            "G#" | "GS" | "AB" => return Some(8),
-            "A" => return Some(9),
-            "A#" | "AS" | "BB" => return Some(10),
-            "B" => return Some(11),
-            _ => {}
-        };
+    let chunks: Vec<&str> = file_name.split(['_', ' ', '-', '.']).collect();
+
+    chunks
+        .iter()
+        .rev()
+        .find_map(|chunk| note_name_to_root(chunk))
+        .or_else(|| {
+            chunks.iter().enumerate().rev().find_map(|(index, chunk)| {
+                let previous = index.checked_sub(1).map(|previous| chunks[previous]);
+                midi_number_to_root(previous, chunk)
+            })
+        })
+        .or_else(|| chunks.iter().rev().find_map(|chunk| parse_midi_note(chunk)))
+}
+
+/// Returns the root note of a note name token such as `F#` or `Bb3`
+fn note_name_to_root(chunk: &str) -> Option<i32> {
+    // Remove the optional octave digit
+    let note = match chunk.char_indices().last() {
+        Some((i, c)) if c.is_ascii_digit() => &chunk[..i],
+        _ => chunk,
+    };
+
+    if note.is_empty() || note.len() > 2 {
+        return None;
+    }
+
+    match note.to_uppercase().as_str() {
+        "C" => Some(0),
+        "C#" | "CS" | "DB" => Some(1),
+        "D" => Some(2),
+        "D#" | "DS" | "EB" => Some(3),
+        "E" => Some(4),
+        "F" => Some(5),
+        "F#" | "FS" | "GB" => Some(6),
+        "G" => Some(7),
+        "G#" | "GS" | "AB" => Some(8),
+        "A" => Some(9),
+        "A#" | "AS" | "BB" => Some(10),
+        "B" => Some(11),
+        _ => None,
+    }
+}
+
+/// Returns the root note of a marked midi note number, `midi60` or `60` after a `midi` token
+fn midi_number_to_root(previous: Option<&str>, chunk: &str) -> Option<i32> {
+    let is_marker = |token: &str| {
+        FILENAME_MIDI_MARKERS
+            .iter()
+            .any(|marker| token.eq_ignore_ascii_case(marker))
+    };
+    let number = match chunk.char_indices().find(|(_, c)| c.is_ascii_digit()) {
+        Some((0, _)) if previous.is_some_and(is_marker) => chunk,
+        Some((start, _)) if start > 0 && is_marker(&chunk[..start]) => &chunk[start..],
+        _ => return None,
+    };
+    parse_midi_note(number)
+}
+
+/// Returns the root note of a midi note number token such as `60`
+fn parse_midi_note(number: &str) -> Option<i32> {
+    if number.is_empty() || !number.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
     }
-    None
+
+    let note = number.parse::<u32>().ok()?;
+    (note <= 127).then_some((note % 12) as i32)
+}
+
+/// Minimum signal power for the tonal detection to consider the sample
+const TONAL_POWER_THRESHOLD: f32 = 5.0;
+
+/// Minimum clarity (periodicity strength) for a sample to be considered tonal
+const TONAL_CLARITY_THRESHOLD: f32 = 0.7;
+
+/// Heuristic that tells whether a sample has a strong enough periodicity
+/// to be considered tonal.
+///
+/// Only the first channel is analysed, this is enough for kicks where
+/// all channels usually share the same pitch.
+pub fn is_tonal_sample(data: &[f32], num_channels: usize, sample_rate: f32) -> bool {
+    if num_channels == 0 || data.is_empty() {
+        return false;
+    }
+
+    let single_channel = data
+        .iter()
+        .step_by(num_channels)
+        .copied()
+        .collect::<Vec<f32>>();
+
+    let mut detector = McLeodDetector::new(single_channel.len(), single_channel.len() / 2);
+    detector
+        .get_pitch(
+            &single_channel,
+            sample_rate as usize,
+            TONAL_POWER_THRESHOLD,
+            TONAL_CLARITY_THRESHOLD,
+        )
+        .is_some()
 }
 
 #[inline]
@@ -220,11 +976,55 @@ pub fn get_blend_value(
     }
 }
 
+/// Returns the blend value of a layer, for blend points ordered by time.
+///
+/// The points are (time, transition) in seconds. Layer `k` fades in through point `k - 1`
+/// and out through point `k`, the first layer starts at full volume and the last one stays there.
+#[inline]
+pub fn get_layer_blend_value(layer: usize, current_time: f32, points: &[(f32, f32)]) -> f32 {
+    let fade_in = layer
+        .checked_sub(1)
+        .and_then(|index| points.get(index.min(points.len().saturating_sub(1))))
+        .map_or(1., |&(time, transition)| {
+            get_blend_value(BlendGroup::End, current_time, time, transition)
+        });
+    let fade_out = points.get(layer).map_or(1., |&(time, transition)| {
+        get_blend_value(BlendGroup::Start, current_time, time, transition)
+    });
+    fade_in * fade_out
+}
+
+/// Shapes a blend value from `get_blend_value`.
+///
+/// Equal power keeps the summed power of the blended layers constant,
+/// smooth eases in and out of the transition.
+#[inline]
+pub fn apply_blend_curve(curve: BlendCurve, value: f32) -> f32 {
+    match curve {
+        BlendCurve::Linear => value,
+        BlendCurve::EqualPower => (value * std::f32::consts::FRAC_PI_2).sin(),
+        BlendCurve::Smooth => value * value * (3. - 2. * value),
+    }
+}
+
+/// Downsamples a line to `target_points` points using the
+/// Largest-Triangle-Three-Buckets algorithm.
+///
+/// First and last points are always kept. If the data already fits in the
+/// target, it is returned as it is.
 pub fn downsample_lttb(data: &[[f32; 2]], target_points: usize) -> Vec<[f32; 2]> {
-    if data.len() <= target_points || target_points < 3 {
+    if data.len() <= target_points {
         return data.to_vec();
     }
 
+    // Not enough points to have buckets, only keep the bounds
+    match target_points {
+        0 => return Vec::new(),
+        1 => return vec![data[0]],
+        2 => return vec![data[0], data[data.len() - 1]],
+        _ => {}
+    }
+
     let mut result = Vec::with_capacity(target_points);
     let bucket_size = (data.len() - 2) as f32 / (target_points - 2) as f32;
 
@@ -259,20 +1059,15 @@ pub fn downsample_lttb(data: &[[f32; 2]], target_points: usize) -> Vec<[f32; 2]>
         };
 
         // Calculate range for current bucket
-        let range_start = ((i - 1) as f32 * bucket_size) as usize + 1;
-        let range_end = (i as f32 * bucket_size) as usize + 1;
+        let range_start = (((i - 1) as f32 * bucket_size) as usize + 1).min(data.len() - 1);
+        let range_end = ((i as f32 * bucket_size) as usize + 1).min(data.len());
 
         let point_a = data[a];
         let mut max_area = 0.0;
         let mut max_area_point = range_start;
 
         // Find point that forms largest triangle with point A and average point
-        for (idx, point) in data
-            .iter()
-            .enumerate()
-            .take(range_end.min(data.len()))
-            .skip(range_start)
-        {
+        for (idx, point) in data.iter().enumerate().take(range_end).skip(range_start) {
             // Calculate triangle area using cross product
             let area = ((point_a[0] - avg_x) * (point[1] - point_a[1])
                 - (point_a[0] - point[0]) * (avg_y - point_a[1]))
@@ -293,8 +1088,779 @@ pub fn downsample_lttb(data: &[[f32; 2]], target_points: usize) -> Vec<[f32; 2]>
     result
 }
 
+/// Lowest frequency of the spectrum, in Hz
+pub const SPECTRUM_MIN_FREQUENCY: f32 = 20.;
+
+/// Lowest level of the spectrum, in dB below its loudest frequency
+pub const SPECTRUM_MIN_DB: f32 = -96.;
+
+/// Longest part of a sample the spectrum is computed on, in frames
+const SPECTRUM_MAX_FRAMES: usize = 1 << 16;
+
+/// Returns the magnitude spectrum of the first channel as `num_points` (frequency, level) points.
+///
+/// The frequencies (in Hz) are spread on a log scale from `SPECTRUM_MIN_FREQUENCY` to nyquist,
+/// each point is the loudest bin of its band. The levels are in dB relative to the loudest bin,
+/// down to `SPECTRUM_MIN_DB`. Only the first `SPECTRUM_MAX_FRAMES` frames are analysed,
+/// zero padded to a power of two.
+pub fn compute_spectrum(
+    data: &[f32],
+    num_channels: usize,
+    sample_rate: f32,
+    num_points: usize,
+) -> Vec<[f32; 2]> {
+    let num_channels = num_channels.max(1);
+    let num_frames = (data.len() / num_channels).min(SPECTRUM_MAX_FRAMES);
+    let nyquist = sample_rate / 2.;
+    if num_frames < 2 || num_points < 2 || nyquist <= SPECTRUM_MIN_FREQUENCY {
+        return Vec::new();
+    }
+
+    let fft_size = num_frames.next_power_of_two();
+    let mut buffer: Vec<Complex<f32>> = data
+        .iter()
+        .step_by(num_channels)
+        .take(num_frames)
+        .map(|&sample| Complex::new(sample, 0.))
+        .collect();
+    buffer.resize(fft_size, Complex::new(0., 0.));
+    FftPlanner::new()
+        .plan_fft_forward(fft_size)
+        .process(&mut buffer);
+
+    let magnitudes: Vec<f32> = buffer[..=fft_size / 2].iter().map(|c| c.norm()).collect();
+    let loudest = magnitudes
+        .iter()
+        .fold(f32::MIN_POSITIVE, |loudest, &magnitude| {
+            loudest.max(magnitude)
+        });
+
+    let bin_width = sample_rate / fft_size as f32;
+    let last_bin = magnitudes.len() - 1;
+    let ratio = nyquist / SPECTRUM_MIN_FREQUENCY;
+    (0..num_points)
+        .map(|point| {
+            let low = SPECTRUM_MIN_FREQUENCY * ratio.powf(point as f32 / num_points as f32);
+            let high = SPECTRUM_MIN_FREQUENCY * ratio.powf((point + 1) as f32 / num_points as f32);
+
+            // The nearest bin when the band is narrower than a bin
+            let first = ((low / bin_width).round() as usize).min(last_bin);
+            let last = ((high / bin_width).round() as usize).clamp(first, last_bin);
+            let magnitude = magnitudes[first..=last]
+                .iter()
+                .fold(0f32, |band, &magnitude| band.max(magnitude));
+
+            let level = 20. * (magnitude / loudest).max(f32::MIN_POSITIVE).log10();
+            [(low * high).sqrt(), level.max(SPECTRUM_MIN_DB)]
+        })
+        .collect()
+}
+
+/// Root mean square of samples, 0 without any sample.
+///
+/// Takes an iterator so split channels are measured without being copied together.
+pub fn rms<'a>(data: impl IntoIterator<Item = &'a f32>) -> f32 {
+    let (sum, count) = data
+        .into_iter()
+        .fold((0., 0usize), |(sum, count), v| (sum + v * v, count + 1));
+    if count == 0 {
+        return 0.;
+    }
+    (sum / count as f32).sqrt()
+}
+
 pub fn semitone_offset_to_playback_rate(semitone_offset: f32) -> f32 {
     /// Number of semitone in one octave
     const SEMITONE_PER_OCTAVE: f32 = 12.;
     2.0_f32.powf(semitone_offset / SEMITONE_PER_OCTAVE)
 }
+
+/// How fast the level meters fall back after a peak, in dB per second
+pub const METER_DECAY_DB_PER_SECOND: f32 = 20.;
+
+/// Returns the held peak of a level meter after a block.
+///
+/// The previous peak decays over the block duration (in s), unless the block goes higher.
+#[inline]
+pub fn decay_peak(previous: f32, block_peak: f32, block_duration: f32) -> f32 {
+    let decay = 10f32.powf(-METER_DECAY_DB_PER_SECOND * block_duration / 20.);
+    block_peak.max(previous * decay)
+}
+
+/// Returns the semitone offset of the pitch envelope at `current_time` (in s) after the note start.
+///
+/// It goes linearly from `amount` to 0 over `time`, then stays at 0.
+#[inline]
+pub fn get_pitch_envelope(amount: f32, time: f32, current_time: f32) -> f32 {
+    if amount == 0. || current_time >= time {
+        return 0.;
+    }
+    amount * (1. - current_time.max(0.) / time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_effective_bpm_uses_host_tempo() {
+        assert_eq!(effective_bpm(Some(174.)), 174.);
+    }
+
+    #[test]
+    fn test_normalize_int_sample_matches_wav() {
+        assert_eq!(normalize_int_sample(i16::MAX as i32, 16), 1.);
+        assert_eq!(normalize_int_sample(-(i16::MAX as i32), 16), -1.);
+        assert_eq!(normalize_int_sample((1 << 23) - 1, 24), 1.);
+        assert_eq!(normalize_int_sample(i32::MAX, 32), 1.);
+        assert_eq!(normalize_int_sample(0, 24), 0.);
+    }
+
+    #[test]
+    fn test_parse_wav_f64() {
+        let wav = |tag: u16, bits: u16, data: &[f64]| {
+            let mut fmt = Vec::new();
+            fmt.extend(tag.to_le_bytes());
+            fmt.extend(2_u16.to_le_bytes());
+            fmt.extend(48000_u32.to_le_bytes());
+            fmt.extend((48000_u32 * 16).to_le_bytes());
+            fmt.extend(16_u16.to_le_bytes());
+            fmt.extend(bits.to_le_bytes());
+
+            let mut bytes = b"RIFF\0\0\0\0WAVE".to_vec();
+            // An odd sized chunk before fmt, it must be skipped with its padding
+            bytes.extend(b"LIST\x03\0\0\0abc\0");
+            bytes.extend(b"fmt ");
+            bytes.extend((fmt.len() as u32).to_le_bytes());
+            bytes.extend(fmt);
+            bytes.extend(b"data");
+            bytes.extend((data.len() as u32 * 8).to_le_bytes());
+            data.iter().for_each(|v| bytes.extend(v.to_le_bytes()));
+            bytes
+        };
+
+        let audio = parse_wav_f64(&wav(3, 64, &[0.5, -0.25, 1., 0.])).unwrap();
+        assert_eq!(audio.spec.channels, 2);
+        assert_eq!(audio.spec.sample_rate, 48000);
+        assert_eq!(*audio.data, vec![0.5, -0.25, 1., 0.]);
+
+        assert!(parse_wav_f64(&wav(1, 16, &[0.])).is_err());
+        assert!(parse_wav_f64(b"not a wav file").is_err());
+    }
+
+    #[test]
+    fn test_trim_leading_frames() {
+        let mut samples = vec![0., 1., 2., 3., 4., 5.];
+        trim_leading_frames(&mut samples, 2, 2);
+        assert_eq!(samples, vec![4., 5.]);
+
+        // Never trims more than the whole buffer
+        trim_leading_frames(&mut samples, 2, 10);
+        assert!(samples.is_empty());
+    }
+
+    #[test]
+    fn test_is_opus_stream() {
+        let page = |packet: &[u8]| {
+            let mut bytes = b"OggS".to_vec();
+            bytes.extend([0; 22]);
+            bytes.push(1); // One segment
+            bytes.push(packet.len() as u8);
+            bytes.extend(packet);
+            bytes
+        };
+        assert!(is_opus_stream(&page(b"OpusHead\x01\x02")));
+        assert!(!is_opus_stream(&page(b"\x01vorbis")));
+        assert!(!is_opus_stream(b"RIFF"));
+        assert!(!is_opus_stream(b""));
+    }
+
+    #[test]
+    fn test_resample_linear() {
+        // Stereo ramp, doubling the rate inserts the midpoints
+        let data = [0., 10., 1., 11., 2., 12.];
+        assert_eq!(
+            resample_linear(&data, 2, 24000., 48000.),
+            vec![0., 10., 0.5, 10.5, 1., 11., 1.5, 11.5, 2., 12., 2., 12.]
+        );
+
+        // Halving the rate keeps every other frame
+        assert_eq!(
+            resample_linear(&[0., 1., 2., 3.], 1, 48000., 24000.),
+            vec![0., 2.]
+        );
+
+        assert_eq!(resample_linear(&data, 2, 48000., 48000.), data.to_vec());
+        assert!(resample_linear(&[], 2, 44100., 48000.).is_empty());
+    }
+
+    #[test]
+    fn test_sinc() {
+        assert_eq!(sinc(0.), 1.);
+        assert!(sinc(1.).abs() < 1e-6);
+        assert!(sinc(-2.).abs() < 1e-6);
+        assert!((sinc(0.5) - 2. / std::f32::consts::PI).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_frequency_to_note_name() {
+        assert_eq!(frequency_to_midi_note(440.), 69);
+        assert_eq!(frequency_to_midi_note(52.), 32);
+        assert_eq!(midi_note_to_name(32), "G#1");
+        assert_eq!(midi_note_to_name(60), "C4");
+    }
+
+    #[test]
+    fn test_describe_sample() {
+        let spec = |channels, bits_per_sample, sample_format| hound::WavSpec {
+            channels,
+            sample_rate: 48000,
+            bits_per_sample,
+            sample_format,
+        };
+        let stereo = AudioData::new(spec(2, 24, hound::SampleFormat::Int), vec![0.; 2 * 57600]);
+        assert_eq!(
+            describe_sample(&stereo, Some(Path::new("kick.wav"))),
+            "1.2s · 48kHz · Stereo · 24-bit"
+        );
+        assert_eq!(
+            describe_sample(&stereo, Some(Path::new("kick.mp3"))),
+            "1.2s · 48kHz · Stereo"
+        );
+        assert_eq!(
+            describe_sample(&stereo, Some(Path::new("KICK.MP3"))),
+            "1.2s · 48kHz · Stereo"
+        );
+
+        let mono = AudioData::new(spec(1, 32, hound::SampleFormat::Float), vec![0.; 24000]);
+        assert_eq!(
+            describe_sample(&mono, None),
+            "500ms · 48kHz · Mono · 32-bit float"
+        );
+        assert_eq!(format_sample_rate(44100.), "44.1kHz");
+    }
+
+    #[test]
+    fn test_root_note_from_filename() {
+        assert_eq!(get_root_note_from_filename("Kick_F#.wav".into()), Some(6));
+        assert_eq!(get_root_note_from_filename("Kick A1.wav".into()), Some(9));
+        assert_eq!(get_root_note_from_filename("Kick-Bb3.wav".into()), Some(10));
+        assert_eq!(get_root_note_from_filename("kick_c.wav".into()), Some(0));
+        assert_eq!(get_root_note_from_filename("Kick_A1.wav".into()), Some(9));
+        assert_eq!(get_root_note_from_filename("sub_C#3.wav".into()), Some(1));
+        assert_eq!(get_root_note_from_filename("Kick 33 F.wav".into()), Some(5));
+
+        // Midi note numbers, the marked ones first
+        assert_eq!(get_root_note_from_filename("tone_60.wav".into()), Some(0));
+        assert_eq!(get_root_note_from_filename("kick_36.wav".into()), Some(0));
+        assert_eq!(get_root_note_from_filename("Kick_2.wav".into()), Some(2));
+        assert_eq!(
+            get_root_note_from_filename("tone_midi60.wav".into()),
+            Some(0)
+        );
+        assert_eq!(
+            get_root_note_from_filename("midi_61_take_40.wav".into()),
+            Some(1)
+        );
+        assert_eq!(
+            get_root_note_from_filename("Tone Note 61.wav".into()),
+            Some(1)
+        );
+        assert_eq!(
+            get_root_note_from_filename("sub-MIDI-43.wav".into()),
+            Some(7)
+        );
+    }
+
+    #[test]
+    fn test_root_note_from_filename_false_positives() {
+        assert_eq!(get_root_note_from_filename("Punch_Bass.wav".into()), None);
+        assert_eq!(get_root_note_from_filename("DeepKick.wav".into()), None);
+        assert_eq!(get_root_note_from_filename("Kick_Analog.wav".into()), None);
+        assert_eq!(get_root_note_from_filename("Kick 808.wav".into()), None);
+        assert_eq!(get_root_note_from_filename("Kick_140bpm.wav".into()), None);
+
+        // Numbers out of the midi range aren't notes
+        assert_eq!(get_root_note_from_filename("midi_200.wav".into()), None);
+        assert_eq!(get_root_note_from_filename("Kick_1024.wav".into()), None);
+    }
+
+    #[test]
+    fn test_rms_of_split_channels() {
+        let channels = [vec![0.5, -0.5], vec![1., -1., 0.]];
+        assert_eq!(rms(channels.iter().flatten()), rms(&channels.concat()));
+        assert!((rms(&[0.5, -0.5]) - 0.5).abs() < 1e-6);
+        assert_eq!(rms(&[]), 0.);
+    }
+
+    #[test]
+    fn test_extensions_ignore_case() {
+        assert!(is_supported_audio_file(Path::new("KICK.WAV")));
+        assert!(is_supported_audio_file(Path::new("Kick.Flac")));
+        assert!(!is_supported_audio_file(Path::new("KICK.TXT")));
+        assert!(!is_supported_audio_file(Path::new("KICK")));
+
+        // The loader reads what the filter lets through
+        let file = std::env::temp_dir().join("hks_extensions_ignore_case.WAV");
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 48000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&file, spec).unwrap();
+        writer.write_sample(0i16).unwrap();
+        writer.finalize().unwrap();
+        assert!(load_audio_file(&file).is_ok());
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_audio_files_from_drop() {
+        let dir = std::env::temp_dir().join("hks_audio_files_from_drop");
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        for name in ["b.wav", "a.flac", "notes.txt", "nested/c.wav"] {
+            std::fs::write(dir.join(name), []).unwrap();
+        }
+
+        let single = dir.join("b.wav");
+        assert_eq!(
+            audio_files_from_drop(&[single.clone(), dir.join("notes.txt")]),
+            vec![single.clone()]
+        );
+        assert_eq!(
+            audio_files_from_drop(&[dir.clone(), single.clone()]),
+            vec![dir.join("a.flac"), single.clone(), single.clone()]
+        );
+
+        // Some hosts drop an uri list instead of the paths
+        let url = format!("file://{}", single.to_string_lossy().replace(' ', "%20"));
+        assert_eq!(
+            audio_files_from_drop(&[PathBuf::from(format!("# dropped\r\n{}\r\n", url))]),
+            vec![single]
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_browse_directory() {
+        let pack = std::env::temp_dir().join("hks_browse_directory");
+        for directory in ["a", "b", "b/deep"] {
+            std::fs::create_dir_all(pack.join(directory)).unwrap();
+        }
+        for name in [
+            "a/1.wav",
+            "a/2.wav",
+            "a/cover.png",
+            "a/notes.txt",
+            "b/1.wav",
+            "b/deep/1.wav",
+        ] {
+            std::fs::write(pack.join(name), []).unwrap();
+        }
+        let file = |name: &str| pack.join(name).to_string_lossy().into_owned();
+
+        // Only the audio files of the directory of the file
+        assert_eq!(
+            get_next_file_in_directory_wrap(&file("a/2.wav"), false),
+            Some(pack.join("a/1.wav"))
+        );
+        assert_eq!(
+            get_previous_file_in_directory_wrap(&file("a/1.wav"), false),
+            Some(pack.join("a/2.wav"))
+        );
+
+        // Across the pack folders
+        assert_eq!(
+            get_next_file_in_directory_wrap(&file("a/2.wav"), true),
+            Some(pack.join("b/1.wav"))
+        );
+        assert_eq!(
+            get_next_file_in_directory_wrap(&file("b/1.wav"), true),
+            Some(pack.join("b/deep/1.wav"))
+        );
+        assert_eq!(
+            get_previous_file_in_directory_wrap(&file("a/1.wav"), true),
+            Some(pack.join("b/deep/1.wav"))
+        );
+
+        std::fs::remove_dir_all(pack).unwrap();
+    }
+
+    #[test]
+    fn test_drop_fills_consecutive_slots() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let mut slots = DropSlots::default();
+
+        // The files of one drop follow each other, a directory takes a slot per file
+        assert_eq!(slots.first_slot(2, 1, at(0)), 2);
+        assert_eq!(slots.first_slot(2, 1, at(10)), 3);
+        assert_eq!(slots.first_slot(2, 3, at(20)), 4);
+        assert_eq!(slots.first_slot(2, 1, at(30)), 7);
+
+        // A later drop, or a drop on another slot, starts over
+        assert_eq!(slots.first_slot(2, 1, at(1000)), 2);
+        assert_eq!(slots.first_slot(5, 1, at(1010)), 5);
+    }
+
+    #[test]
+    fn test_position_interpolator() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let mut interpolator = PositionInterpolator::default();
+
+        // The first position is displayed as is
+        assert_eq!(interpolator.position(0.1, 0.01, at(0)), 0.1);
+
+        // A new position is reached after one block, from where the indicator was
+        assert_eq!(interpolator.position(0.2, 0.01, at(0)), 0.1);
+        assert!((interpolator.position(0.2, 0.01, at(5)) - 0.15).abs() < 1e-6);
+        assert_eq!(interpolator.position(0.2, 0.01, at(20)), 0.2);
+
+        // A new note snaps back to the start
+        assert_eq!(interpolator.position(0.01, 0.01, at(25)), 0.01);
+
+        // Without a block duration the position isn't interpolated
+        assert_eq!(interpolator.position(0.3, 0., at(25)), 0.3);
+    }
+
+    #[test]
+    fn test_natural_cmp() {
+        assert_eq!(natural_cmp("Kick2.wav", "Kick10.wav"), Ordering::Less);
+        assert_eq!(natural_cmp("kick 3", "Kick 2"), Ordering::Greater);
+        assert_eq!(natural_cmp("KICK", "kick"), Ordering::Equal);
+        assert_eq!(natural_cmp("Kick01", "Kick1"), Ordering::Greater);
+        assert_eq!(natural_cmp("Kick", "Kick1"), Ordering::Less);
+
+        let mut names = vec!["Kick10.wav", "kick1.wav", "Kick2.wav", "Clap.wav"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(names, ["Clap.wav", "kick1.wav", "Kick2.wav", "Kick10.wav"]);
+
+        assert_eq!(
+            natural_path_cmp(Path::new("pack/B/1.wav"), Path::new("pack/a/2.wav")),
+            Ordering::Greater
+        );
+        assert_eq!(
+            natural_path_cmp(
+                Path::new("pack/Kick2/x.wav"),
+                Path::new("pack/Kick10/a.wav")
+            ),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn test_path_from_text() {
+        let file = std::env::temp_dir().join("hks_path_from_text.wav");
+        std::fs::write(&file, []).unwrap();
+        let text = file.to_str().unwrap();
+
+        assert_eq!(path_from_text(text), Some(file.clone()));
+        assert_eq!(
+            path_from_text(&format!(" \"{}\"\n", text)),
+            Some(file.clone())
+        );
+        assert_eq!(
+            path_from_text(&format!("file://{}", text)),
+            Some(file.clone())
+        );
+        assert_eq!(path_from_text("not a path"), None);
+        assert_eq!(
+            path_from_text(&file.with_extension("txt").to_string_lossy()),
+            None
+        );
+
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(
+            percent_decode("My%20Kick.wav").as_deref(),
+            Some("My Kick.wav")
+        );
+        assert_eq!(percent_decode("F%23%C3%A9").as_deref(), Some("F#é"));
+        assert_eq!(percent_decode("no_escape").as_deref(), Some("no_escape"));
+        assert_eq!(percent_decode("bad%zz"), None);
+        assert_eq!(percent_decode("truncated%2"), None);
+    }
+
+    #[test]
+    fn test_path_from_file_url() {
+        assert_eq!(
+            path_from_file_url("file:///tmp/My%20Kick.wav"),
+            Some(PathBuf::from("/tmp/My Kick.wav"))
+        );
+        assert_eq!(
+            path_from_file_url("file://localhost/tmp/kick.wav"),
+            Some(PathBuf::from("/tmp/kick.wav"))
+        );
+        assert_eq!(
+            path_from_file_url("file:///C:/Samples/kick.wav"),
+            Some(PathBuf::from("C:/Samples/kick.wav"))
+        );
+        assert_eq!(path_from_file_url("file://server/kick.wav"), None);
+        assert_eq!(path_from_file_url("https://example.com/kick.wav"), None);
+    }
+
+    #[test]
+    fn test_paths_from_uri_list() {
+        let list = "# dropped by host\r\nfile:///tmp/a.wav\r\n\r\nfile:///tmp/b%20c.wav\r\n";
+        assert_eq!(
+            paths_from_uri_list(list),
+            vec![PathBuf::from("/tmp/a.wav"), PathBuf::from("/tmp/b c.wav")]
+        );
+        assert_eq!(
+            paths_from_uri_list("/tmp/raw.wav"),
+            vec![PathBuf::from("/tmp/raw.wav")]
+        );
+    }
+
+    #[test]
+    fn test_effective_bpm_fallback() {
+        assert_eq!(effective_bpm(None), DEFAULT_BPM as f32);
+        assert_eq!(effective_bpm(Some(0.)), DEFAULT_BPM as f32);
+        assert_eq!(effective_bpm(Some(-120.)), DEFAULT_BPM as f32);
+        assert_eq!(effective_bpm(Some(f32::NAN)), DEFAULT_BPM as f32);
+    }
+
+    #[test]
+    fn test_blend_none_is_always_full() {
+        for time in [0., 0.1, 0.2, 0.5, 10.] {
+            assert_eq!(get_blend_value(BlendGroup::None, time, 0.2, 0.05), 1.);
+        }
+    }
+
+    #[test]
+    fn test_blend_start_fades_out() {
+        // Window is centered on blend_time : [0.15, 0.25]
+        assert_eq!(get_blend_value(BlendGroup::Start, 0.0, 0.2, 0.1), 1.);
+        assert_eq!(get_blend_value(BlendGroup::Start, 0.14, 0.2, 0.1), 1.);
+        assert!((get_blend_value(BlendGroup::Start, 0.2, 0.2, 0.1) - 0.5).abs() < 1e-4);
+        assert_eq!(get_blend_value(BlendGroup::Start, 0.26, 0.2, 0.1), 0.);
+        assert_eq!(get_blend_value(BlendGroup::Start, 5.0, 0.2, 0.1), 0.);
+    }
+
+    #[test]
+    fn test_blend_end_fades_in() {
+        assert_eq!(get_blend_value(BlendGroup::End, 0.0, 0.2, 0.1), 0.);
+        assert_eq!(get_blend_value(BlendGroup::End, 0.14, 0.2, 0.1), 0.);
+        assert!((get_blend_value(BlendGroup::End, 0.2, 0.2, 0.1) - 0.5).abs() < 1e-4);
+        assert_eq!(get_blend_value(BlendGroup::End, 0.26, 0.2, 0.1), 1.);
+        assert_eq!(get_blend_value(BlendGroup::End, 5.0, 0.2, 0.1), 1.);
+    }
+
+    #[test]
+    fn test_blend_start_and_end_are_complementary() {
+        for step in 0..=20 {
+            let time = 0.1 + step as f32 * 0.01;
+            let start = get_blend_value(BlendGroup::Start, time, 0.2, 0.1);
+            let end = get_blend_value(BlendGroup::End, time, 0.2, 0.1);
+            assert!((start + end - 1.).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_blend_transition_is_monotonic() {
+        let values: Vec<f32> = (0..=100)
+            .map(|step| get_blend_value(BlendGroup::Start, step as f32 * 0.005, 0.2, 0.1))
+            .collect();
+        for pair in values.windows(2) {
+            assert!(pair[1] <= pair[0]);
+        }
+    }
+
+    #[test]
+    fn test_blend_pathological_inputs() {
+        // Zero transition, value is 0 / 0 in the window
+        let value = get_blend_value(BlendGroup::Start, 0.2, 0.2, 0.);
+        assert!(value.is_finite() && (0. ..=1.).contains(&value));
+
+        for group in [BlendGroup::Start, BlendGroup::End] {
+            for (time, blend_time, transition) in [
+                (f32::NAN, 0.2, 0.1),
+                (0.2, f32::NAN, 0.1),
+                (0.2, 0.2, f32::NAN),
+                (f32::INFINITY, 0.2, 0.1),
+                (0.2, 0.2, -0.1),
+            ] {
+                let value = get_blend_value(group, time, blend_time, transition);
+                assert!(value.is_finite() && (0. ..=1.).contains(&value));
+            }
+        }
+    }
+
+    #[test]
+    fn test_layers_match_start_and_end_groups() {
+        let points = [(0.2, 0.1)];
+        for step in 0..=50 {
+            let time = step as f32 * 0.01;
+            assert_eq!(
+                get_layer_blend_value(0, time, &points),
+                get_blend_value(BlendGroup::Start, time, 0.2, 0.1)
+            );
+            assert_eq!(
+                get_layer_blend_value(1, time, &points),
+                get_blend_value(BlendGroup::End, time, 0.2, 0.1)
+            );
+        }
+    }
+
+    #[test]
+    fn test_layers_cross_at_each_point() {
+        let points = [(0.1, 0.05), (0.3, 0.05), (0.5, 0.05)];
+        for step in 0..=70 {
+            let time = step as f32 * 0.01;
+            let values: Vec<f32> = (0..=points.len())
+                .map(|layer| get_layer_blend_value(layer, time, &points))
+                .collect();
+            assert!((values.iter().sum::<f32>() - 1.).abs() < 1e-4);
+        }
+
+        // Middle of each layer, only that one plays
+        for (layer, time) in [0.0, 0.2, 0.4, 0.6].into_iter().enumerate() {
+            assert_eq!(get_layer_blend_value(layer, time, &points), 1.);
+        }
+
+        // Layers past the last point stay on the last one
+        assert_eq!(get_layer_blend_value(5, 0.6, &points), 1.);
+        assert_eq!(get_layer_blend_value(5, 0.0, &points), 0.);
+    }
+
+    #[test]
+    fn test_peak_holds_and_decays() {
+        // A louder block replaces the peak
+        assert_eq!(decay_peak(0.5, 0.8, 0.01), 0.8);
+
+        // One second later the peak lost the decay
+        let decayed = decay_peak(1., 0., 1.);
+        assert!((20. * decayed.log10() + METER_DECAY_DB_PER_SECOND).abs() < 1e-3);
+
+        // A quieter block doesn't pull the peak down faster
+        assert_eq!(decay_peak(1., 0.1, 0.), 1.);
+    }
+
+    #[test]
+    fn test_pitch_envelope_decays_to_zero() {
+        assert_eq!(get_pitch_envelope(12., 0.1, 0.), 12.);
+        assert!((get_pitch_envelope(12., 0.1, 0.05) - 6.).abs() < 1e-5);
+        assert_eq!(get_pitch_envelope(12., 0.1, 0.1), 0.);
+        assert_eq!(get_pitch_envelope(12., 0.1, 1.), 0.);
+        assert_eq!(get_pitch_envelope(-7., 0.1, 0.), -7.);
+        assert_eq!(get_pitch_envelope(0., 0.1, 0.), 0.);
+    }
+
+    #[test]
+    fn test_blend_curves() {
+        for curve in [
+            BlendCurve::Linear,
+            BlendCurve::EqualPower,
+            BlendCurve::Smooth,
+        ] {
+            assert_eq!(apply_blend_curve(curve, 0.), 0.);
+            assert!((apply_blend_curve(curve, 1.) - 1.).abs() < 1e-6);
+        }
+        assert_eq!(apply_blend_curve(BlendCurve::Linear, 0.3), 0.3);
+
+        for step in 0..=20 {
+            let time = 0.1 + step as f32 * 0.01;
+            let start = get_blend_value(BlendGroup::Start, time, 0.2, 0.1);
+            let end = get_blend_value(BlendGroup::End, time, 0.2, 0.1);
+
+            // Equal power keeps the summed power, smooth the summed gain
+            let start_power = apply_blend_curve(BlendCurve::EqualPower, start).powi(2);
+            let end_power = apply_blend_curve(BlendCurve::EqualPower, end).powi(2);
+            assert!((start_power + end_power - 1.).abs() < 1e-4);
+
+            let smooth_sum = apply_blend_curve(BlendCurve::Smooth, start)
+                + apply_blend_curve(BlendCurve::Smooth, end);
+            assert!((smooth_sum - 1.).abs() < 1e-4);
+        }
+    }
+
+    fn make_line(len: usize) -> Vec<[f32; 2]> {
+        (0..len)
+            .map(|i| [i as f32 / len as f32, (i as f32 * 0.1).sin()])
+            .collect()
+    }
+
+    #[test]
+    fn test_spectrum_peaks_at_the_sine_frequency() {
+        let sample_rate = 48000.;
+        let data: Vec<f32> = (0..4096)
+            .map(|i| (2. * std::f32::consts::PI * 100. * i as f32 / sample_rate).sin())
+            .collect();
+        let spectrum = compute_spectrum(&data, 1, sample_rate, 256);
+        assert_eq!(spectrum.len(), 256);
+
+        let [frequency, level] = spectrum
+            .iter()
+            .copied()
+            .max_by(|a, b| a[1].total_cmp(&b[1]))
+            .unwrap();
+        assert_eq!(level, 0.);
+        assert!((frequency - 100.).abs() < 100. * 0.1);
+
+        // Far from the sine, the level is much lower
+        let high = spectrum
+            .iter()
+            .find(|[frequency, _]| *frequency > 5000.)
+            .unwrap();
+        assert!(high[1] < -30.);
+    }
+
+    #[test]
+    fn test_spectrum_too_short() {
+        assert!(compute_spectrum(&[0.5], 1, 48000., 256).is_empty());
+        assert!(compute_spectrum(&[0.5, 0.2], 2, 48000., 256).is_empty());
+    }
+
+    #[test]
+    fn test_lttb_empty() {
+        assert!(downsample_lttb(&[], 0).is_empty());
+        assert!(downsample_lttb(&[], 100).is_empty());
+    }
+
+    #[test]
+    fn test_lttb_data_fits_target() {
+        let data = make_line(100);
+        assert_eq!(downsample_lttb(&data, 100), data);
+        assert_eq!(downsample_lttb(&data, 1000), data);
+    }
+
+    #[test]
+    fn test_lttb_small_target() {
+        let data = make_line(100);
+        assert!(downsample_lttb(&data, 0).is_empty());
+        assert_eq!(downsample_lttb(&data, 1), vec![data[0]]);
+        assert_eq!(downsample_lttb(&data, 2), vec![data[0], data[99]]);
+    }
+
+    #[test]
+    fn test_lttb_large_input() {
+        let data = make_line(100_000);
+        let result = downsample_lttb(&data, 1000);
+
+        assert_eq!(result.len(), 1000);
+        assert_eq!(result[0], data[0]);
+        assert_eq!(result[999], data[99_999]);
+
+        // Points keep their order
+        for pair in result.windows(2) {
+            assert!(pair[1][0] > pair[0][0]);
+        }
+    }
+
+    #[test]
+    fn test_lttb_odd_lengths_never_panic() {
+        for len in 0..64 {
+            let data = make_line(len);
+            for target in 0..(len + 4) {
+                let result = downsample_lttb(&data, target);
+                assert_eq!(result.len(), target.min(len));
+            }
+        }
+    }
+}